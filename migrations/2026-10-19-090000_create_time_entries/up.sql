@@ -0,0 +1,16 @@
+CREATE TABLE time_entries (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    source VARCHAR NOT NULL,
+    external_id VARCHAR NOT NULL,
+    client_id INTEGER,
+    client_name VARCHAR NOT NULL,
+    project VARCHAR,
+    description VARCHAR NOT NULL,
+    duration_minutes INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    invoice_id INTEGER,
+
+    FOREIGN KEY(client_id) REFERENCES clients(id),
+    FOREIGN KEY(invoice_id) REFERENCES invoices(id),
+    UNIQUE(source, external_id)
+);
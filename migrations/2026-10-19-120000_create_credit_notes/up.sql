@@ -0,0 +1,20 @@
+CREATE TABLE credit_notes (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    invoice_id INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    reason TEXT,
+    total_amount DOUBLE NOT NULL,
+
+    FOREIGN KEY(invoice_id) REFERENCES invoices(id)
+);
+
+CREATE TABLE credit_note_items (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    credit_note_id INTEGER NOT NULL,
+    description VARCHAR NOT NULL,
+    quantity INTEGER NOT NULL,
+    unit_price DOUBLE NOT NULL,
+    total DOUBLE NOT NULL,
+
+    FOREIGN KEY(credit_note_id) REFERENCES credit_notes(id)
+);
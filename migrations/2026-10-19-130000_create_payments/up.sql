@@ -0,0 +1,10 @@
+CREATE TABLE payments (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    invoice_id INTEGER NOT NULL,
+    amount DOUBLE NOT NULL,
+    date TEXT NOT NULL,
+    method VARCHAR,
+    reference VARCHAR,
+
+    FOREIGN KEY(invoice_id) REFERENCES invoices(id)
+);
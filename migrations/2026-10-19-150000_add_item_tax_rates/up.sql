@@ -0,0 +1,3 @@
+ALTER TABLE items ADD COLUMN tax_rate DOUBLE;
+ALTER TABLE quote_items ADD COLUMN tax_rate DOUBLE;
+ALTER TABLE credit_note_items ADD COLUMN tax_rate DOUBLE;
@@ -0,0 +1,22 @@
+-- Invoices from before due dates were recorded were all saved against client
+-- and company 1. Point each at the client and company named on it, or at
+-- nothing when that name doesn't identify exactly one.
+UPDATE invoices
+SET client_id = (
+    SELECT CASE WHEN COUNT(*) = 1 THEN MIN(id) END
+    FROM clients
+    WHERE clients.name = invoices.client_name
+)
+WHERE due_date IS NULL
+  AND client_id = 1
+  AND NOT EXISTS (SELECT 1 FROM clients WHERE clients.id = 1 AND clients.name = invoices.client_name);
+
+UPDATE invoices
+SET company_id = (
+    SELECT CASE WHEN COUNT(*) = 1 THEN MIN(id) END
+    FROM companies
+    WHERE companies.name = invoices.company_name
+)
+WHERE due_date IS NULL
+  AND company_id = 1
+  AND NOT EXISTS (SELECT 1 FROM companies WHERE companies.id = 1 AND companies.name = invoices.company_name);
@@ -0,0 +1,2 @@
+ALTER TABLE invoices DROP COLUMN original_number;
+ALTER TABLE payments DROP COLUMN date_unknown;
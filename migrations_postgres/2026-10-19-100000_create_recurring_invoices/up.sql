@@ -0,0 +1,16 @@
+CREATE TABLE recurring_invoices (
+    id SERIAL PRIMARY KEY,
+    company_id INTEGER NOT NULL,
+    client_id INTEGER NOT NULL,
+    items TEXT NOT NULL,
+    notes TEXT,
+    "interval" VARCHAR NOT NULL,
+    day_of_month INTEGER NOT NULL,
+    start_date TEXT NOT NULL,
+    end_date TEXT,
+    next_run_date TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+
+    FOREIGN KEY(company_id) REFERENCES companies(id),
+    FOREIGN KEY(client_id) REFERENCES clients(id)
+);
@@ -0,0 +1,36 @@
+CREATE TABLE quotes (
+    id SERIAL PRIMARY KEY,
+    company_id INTEGER,
+    company_name VARCHAR NOT NULL,
+    company_address VARCHAR,
+    company_email VARCHAR,
+    company_phone VARCHAR,
+    client_id INTEGER,
+    client_name VARCHAR NOT NULL,
+    client_address VARCHAR,
+    client_email VARCHAR,
+    client_phone VARCHAR,
+    date TEXT NOT NULL,
+    valid_until TEXT NOT NULL,
+    total_amount DOUBLE PRECISION NOT NULL,
+    notes TEXT,
+    status VARCHAR NOT NULL DEFAULT 'pending',
+    invoice_id INTEGER,
+
+    FOREIGN KEY(company_id) REFERENCES companies(id),
+    FOREIGN KEY(client_id) REFERENCES clients(id),
+    FOREIGN KEY(invoice_id) REFERENCES invoices(id)
+);
+
+CREATE TABLE quote_items (
+    id SERIAL PRIMARY KEY,
+    quote_id INTEGER NOT NULL,
+    description VARCHAR NOT NULL,
+    quantity INTEGER NOT NULL,
+    unit_price DOUBLE PRECISION NOT NULL,
+    total DOUBLE PRECISION NOT NULL,
+
+    FOREIGN KEY(quote_id) REFERENCES quotes(id)
+);
+
+ALTER TABLE invoices ADD COLUMN quote_id INTEGER REFERENCES quotes(id);
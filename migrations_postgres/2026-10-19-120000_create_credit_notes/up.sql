@@ -0,0 +1,20 @@
+CREATE TABLE credit_notes (
+    id SERIAL PRIMARY KEY,
+    invoice_id INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    reason TEXT,
+    total_amount DOUBLE PRECISION NOT NULL,
+
+    FOREIGN KEY(invoice_id) REFERENCES invoices(id)
+);
+
+CREATE TABLE credit_note_items (
+    id SERIAL PRIMARY KEY,
+    credit_note_id INTEGER NOT NULL,
+    description VARCHAR NOT NULL,
+    quantity INTEGER NOT NULL,
+    unit_price DOUBLE PRECISION NOT NULL,
+    total DOUBLE PRECISION NOT NULL,
+
+    FOREIGN KEY(credit_note_id) REFERENCES credit_notes(id)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE payments (
+    id SERIAL PRIMARY KEY,
+    invoice_id INTEGER NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    date TEXT NOT NULL,
+    method VARCHAR,
+    reference VARCHAR,
+
+    FOREIGN KEY(invoice_id) REFERENCES invoices(id)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE credit_note_items DROP COLUMN tax_rate;
+ALTER TABLE quote_items DROP COLUMN tax_rate;
+ALTER TABLE items DROP COLUMN tax_rate;
@@ -0,0 +1 @@
+ALTER TABLE clients ADD COLUMN credit_limit DOUBLE PRECISION;
@@ -0,0 +1,2 @@
+-- The ids replaced by up.sql were wrong, so there is nothing to restore
+SELECT 1;
@@ -0,0 +1,2 @@
+ALTER TABLE invoices ADD COLUMN original_number TEXT;
+ALTER TABLE payments ADD COLUMN date_unknown BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,551 @@
+use crate::config::{self, Config};
+use crate::db::{establish_connection, require_sqlite, run_migration, DbError, MIGRATIONS};
+use crate::utils::get_input;
+use base64::{engine::general_purpose, Engine as _};
+use diesel::migration::MigrationSource;
+use diesel::sqlite::Sqlite;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use text_colorizer::*;
+
+/// Version of the export bundle layout. Bump it when the bundle changes in a
+/// way older versions of the tool cannot read.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Every table holding user data, parents before children.
+const TABLES: [&str; 12] = [
+    "companies",
+    "clients",
+    "company_clients",
+    "quotes",
+    "quote_items",
+    "invoices",
+    "items",
+    "payments",
+    "credit_notes",
+    "credit_note_items",
+    "recurring_invoices",
+    "time_entries",
+];
+
+const BUNDLE_FILE: &str = "bundle.json";
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    version: u32,
+    created_at: String,
+    /// The latest migration applied to the exported database
+    schema_version: String,
+    config: Value,
+    tables: Map<String, Value>,
+}
+
+fn timestamp() -> String {
+    chrono::offset::Local::now()
+        .format("%Y%m%d-%H%M%S")
+        .to_string()
+}
+
+fn default_backup_path(database_url: &str) -> PathBuf {
+    let dir = Path::new(database_url)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("backups");
+    let stamp = timestamp();
+
+    // Backups taken in the same second get a numbered suffix
+    let mut path = dir.join(format!("cli_invoice-{}.sqlite3", stamp));
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("cli_invoice-{}-{}.sqlite3", stamp, n));
+        n += 1;
+    }
+    path
+}
+
+pub fn confirm(prompt: &str) -> bool {
+    matches!(
+        get_input(&format!("{} [y/N]: ", prompt))
+            .to_lowercase()
+            .as_str(),
+        "y" | "yes"
+    )
+}
+
+fn schema_version(conn: &Connection) -> rusqlite::Result<String> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), '') FROM __diesel_schema_migrations",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Migrations applied to `conn` that this binary doesn't have, newest first.
+fn unknown_migrations(conn: &Connection) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let known = MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+        .map_err(|e| DbError::Migration(e.to_string()))?
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect::<Vec<_>>();
+
+    let mut statement = conn.prepare("SELECT version FROM __diesel_schema_migrations")?;
+    let mut unknown = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|version| !known.contains(version))
+        .collect::<Vec<_>>();
+    unknown.sort_by(|a, b| b.cmp(a));
+
+    Ok(unknown)
+}
+
+/// Copies the live database to `path` with the SQLite online backup API, so
+/// the snapshot is consistent even if another process is writing to it.
+pub fn backup(path: Option<PathBuf>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let config = config::load_config()?;
+    require_sqlite(&config.database_url)?;
+    let path = path.unwrap_or_else(|| default_backup_path(&config.database_url));
+
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()).into());
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let conn = Connection::open_with_flags(&config.database_url, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.backup(DatabaseName::Main, &path, None)?;
+
+    Ok(path)
+}
+
+/// Replaces the live database with a backup, after saving a snapshot of the
+/// current one. Pending migrations are applied to older backups.
+pub fn restore(path: &Path, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load_config()?;
+    require_sqlite(&config.database_url)?;
+
+    let source = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let (invoices, clients) = source
+        .query_row(
+            "SELECT (SELECT COUNT(*) FROM invoices), (SELECT COUNT(*) FROM clients)",
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )
+        .map_err(|_| format!("{} is not a cli-invoice database", path.display()))?;
+    // Restoring a newer schema would leave a database this binary refuses
+    // to open
+    if let Some(version) = unknown_migrations(&source)?.into_iter().next() {
+        return Err(DbError::SchemaTooNew(version).into());
+    }
+    drop(source);
+
+    println!(
+        "{} contains {} invoice(s) and {} client(s)",
+        path.display(),
+        invoices,
+        clients
+    );
+    if !yes
+        && !confirm(&format!(
+            "This replaces everything in {}. Continue?",
+            config.database_url
+        ))
+    {
+        return Err("Restore cancelled".into());
+    }
+
+    let safety = backup(None)?;
+    println!(
+        "Current database saved to {}",
+        safety.display().to_string().bright_black()
+    );
+
+    let mut conn = Connection::open(&config.database_url)?;
+    conn.restore(
+        DatabaseName::Main,
+        path,
+        None::<fn(rusqlite::backup::Progress)>,
+    )?;
+    drop(conn);
+
+    run_migration(&mut *establish_connection()?)?;
+
+    Ok(())
+}
+
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text).to_string()),
+        ValueRef::Blob(blob) => Value::from(general_purpose::STANDARD.encode(blob)),
+    }
+}
+
+fn to_sql(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn read_table(conn: &Connection, table: &str) -> rusqlite::Result<Value> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {} ORDER BY rowid", table))?;
+    let columns = stmt
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    let rows = stmt
+        .query_map([], |row| {
+            let mut object = Map::new();
+            for (i, column) in columns.iter().enumerate() {
+                object.insert(column.clone(), to_json(row.get_ref(i)?));
+            }
+            Ok(Value::Object(object))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(Value::Array(rows))
+}
+
+/// Every PDF below `dir`, as paths relative to it.
+fn find_pdfs(dir: &Path) -> Vec<PathBuf> {
+    let mut pdfs = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+            {
+                if let Ok(relative) = path.strip_prefix(dir) {
+                    pdfs.push(relative.to_path_buf());
+                }
+            }
+        }
+    }
+
+    pdfs.sort();
+    pdfs
+}
+
+pub struct ExportArgs {
+    pub path: PathBuf,
+    pub pdfs: bool,
+    pub logo: bool,
+}
+
+/// Writes every table and the config to a versioned JSON bundle. With PDFs or
+/// the logo included, the bundle is a zip holding `bundle.json` and the files.
+pub fn export(args: ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load_config()?;
+    require_sqlite(&config.database_url)?;
+    let conn = Connection::open_with_flags(&config.database_url, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let mut tables = Map::new();
+    for table in TABLES {
+        tables.insert(table.to_string(), read_table(&conn, table)?);
+    }
+
+    let bundle = Bundle {
+        version: BUNDLE_VERSION,
+        created_at: chrono::offset::Local::now().to_rfc3339(),
+        schema_version: schema_version(&conn)?,
+        // The API token stays on this machine
+        config: serde_json::to_value(Config {
+            api_token: String::new(),
+            ..config.clone()
+        })?,
+        tables,
+    };
+    let json = serde_json::to_vec_pretty(&bundle)?;
+
+    if !args.pdfs && !args.logo {
+        fs::write(&args.path, json)?;
+        return Ok(());
+    }
+
+    let mut zip = zip::ZipWriter::new(fs::File::create(&args.path)?);
+    let options = zip::write::FileOptions::default();
+
+    zip.start_file(BUNDLE_FILE, options)?;
+    zip.write_all(&json)?;
+
+    if args.pdfs {
+        let invoice_path = Path::new(&config.invoice_path);
+        for pdf in find_pdfs(invoice_path) {
+            zip.start_file(format!("pdfs/{}", pdf.display()), options)?;
+            zip.write_all(&fs::read(invoice_path.join(&pdf))?)?;
+        }
+    }
+
+    if args.logo && !config.logo_path.is_empty() {
+        let logo = Path::new(&config.logo_path);
+        let name = logo
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "logo.png".to_string());
+        zip.start_file(format!("logo/{}", name), options)?;
+        zip.write_all(&fs::read(logo)?)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Column names of a table in the local database.
+fn table_columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut statement = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = statement
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect();
+    columns
+}
+
+/// Column names go into the INSERT statement as they are, so every name in
+/// the bundle has to be a column of the local table.
+fn check_columns(conn: &Connection, tables: &Map<String, Value>) -> Result<(), String> {
+    for table in TABLES {
+        let Some(rows) = tables.get(table).and_then(Value::as_array) else {
+            continue;
+        };
+        let known = table_columns(conn, table).map_err(|e| e.to_string())?;
+        let unknown = rows
+            .iter()
+            .filter_map(Value::as_object)
+            .flat_map(|row| row.keys())
+            .find(|column| !known.contains(column));
+        if let Some(column) = unknown {
+            return Err(format!(
+                "The bundle has a column '{}' that the {} table does not have",
+                column, table
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn insert_rows(conn: &Connection, table: &str, rows: &Value) -> rusqlite::Result<usize> {
+    let mut count = 0;
+    for row in rows.as_array().into_iter().flatten() {
+        let Some(object) = row.as_object() else {
+            continue;
+        };
+        let columns = object.keys().cloned().collect::<Vec<_>>();
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let values = object.values().map(to_sql).collect::<Vec<_>>();
+
+        conn.execute(
+            &format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table,
+                columns.join(", "),
+                placeholders
+            ),
+            rusqlite::params_from_iter(values),
+        )?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Replaces all data with the contents of a bundle made by `export`, after
+/// saving a backup of the current database. Local paths in the config are
+/// kept; PDFs and the logo are unpacked next to the database.
+pub fn import(path: &Path, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let data = fs::read(path)?;
+    let mut archive = data
+        .starts_with(b"PK")
+        .then(|| zip::ZipArchive::new(std::io::Cursor::new(&data)))
+        .transpose()?;
+
+    let bundle: Bundle = match archive.as_mut() {
+        Some(archive) => {
+            let mut json = String::new();
+            archive.by_name(BUNDLE_FILE)?.read_to_string(&mut json)?;
+            serde_json::from_str(&json)?
+        }
+        None => serde_json::from_slice(&data)?,
+    };
+
+    if bundle.version > BUNDLE_VERSION {
+        return Err(format!(
+            "Bundle version {} is newer than this version of cli-invoice supports ({})",
+            bundle.version, BUNDLE_VERSION
+        )
+        .into());
+    }
+
+    let mut config = config::load_config()?;
+
+    // Bring the local database up to date so it has every column in the bundle
+    run_migration(&mut *establish_connection()?)?;
+    let mut conn = Connection::open(&config.database_url)?;
+    if bundle.schema_version > schema_version(&conn)? {
+        return Err(format!(
+            "The bundle was exported from a newer database schema ({}); upgrade cli-invoice first",
+            bundle.schema_version
+        )
+        .into());
+    }
+    check_columns(&conn, &bundle.tables)?;
+
+    let count = |table: &str| {
+        bundle
+            .tables
+            .get(table)
+            .and_then(Value::as_array)
+            .map_or(0, Vec::len)
+    };
+    println!(
+        "{} was created {} and contains {} invoice(s) and {} client(s)",
+        path.display(),
+        bundle.created_at,
+        count("invoices"),
+        count("clients")
+    );
+    if !yes
+        && !confirm(&format!(
+            "This replaces everything in {}. Continue?",
+            config.database_url
+        ))
+    {
+        return Err("Import cancelled".into());
+    }
+
+    let safety = backup(None)?;
+    println!(
+        "Current database saved to {}",
+        safety.display().to_string().bright_black()
+    );
+
+    let tx = conn.transaction()?;
+    for table in TABLES.iter().rev() {
+        tx.execute(&format!("DELETE FROM {}", table), [])?;
+    }
+    for table in TABLES {
+        if let Some(rows) = bundle.tables.get(table) {
+            insert_rows(&tx, table, rows)?;
+        }
+    }
+    tx.commit()?;
+
+    // Take the settings from the bundle but keep this machine's paths,
+    // profiles and API token
+    let mut imported = bundle.config;
+    if let Some(object) = imported.as_object_mut() {
+        object.insert("api_token".into(), config.api_token.clone().into());
+        object.insert("setup_done".into(), Value::Bool(true));
+        object.insert("database_url".into(), config.database_url.clone().into());
+        object.insert("invoice_path".into(), config.invoice_path.clone().into());
+        object.insert("logo_path".into(), config.logo_path.clone().into());
+        object.insert("template_path".into(), config.template_path.clone().into());
+        object.insert(
+            "active_profile".into(),
+            serde_json::to_value(&config.active_profile)?,
+        );
+        object.insert("profiles".into(), serde_json::to_value(&config.profiles)?);
+    }
+    if let Ok(imported) = serde_json::from_value::<Config>(imported) {
+        config = imported;
+    }
+
+    if let Some(archive) = archive.as_mut() {
+        let data_dir = Path::new(&config.database_url)
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let Some(name) = file.enclosed_name().map(Path::to_path_buf) else {
+                continue;
+            };
+
+            let target = if let Ok(pdf) = name.strip_prefix("pdfs") {
+                Path::new(&config.invoice_path).join(pdf)
+            } else if let Ok(logo) = name.strip_prefix("logo") {
+                let target = data_dir.join(logo);
+                config.logo_path = target.display().to_string();
+                target
+            } else {
+                continue;
+            };
+
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents)?;
+            fs::write(&target, contents)?;
+        }
+    }
+
+    config::save_config(&config)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn connection() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE clients (id INTEGER PRIMARY KEY, name TEXT NOT NULL);")
+            .unwrap();
+        conn
+    }
+
+    fn tables(rows: Value) -> Map<String, Value> {
+        let mut tables = Map::new();
+        tables.insert("clients".to_string(), rows);
+        tables
+    }
+
+    #[test]
+    fn accepts_columns_of_the_local_table() {
+        let conn = connection();
+        let rows = json!([{"id": 1, "name": "Acme"}]);
+
+        assert_eq!(check_columns(&conn, &tables(rows.clone())), Ok(()));
+        assert_eq!(insert_rows(&conn, "clients", &rows).unwrap(), 1);
+    }
+
+    #[test]
+    fn refuses_columns_the_local_table_does_not_have() {
+        let conn = connection();
+        let rows = json!([
+            {"id": 1, "name": "Acme"},
+            {"id": 2, "name) VALUES (2, 'x'); DROP TABLE clients; --": "x"}
+        ]);
+
+        let error = check_columns(&conn, &tables(rows)).unwrap_err();
+        assert!(error.contains("DROP TABLE clients"));
+        assert!(error.contains("clients table"));
+    }
+}
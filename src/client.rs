@@ -1,11 +1,12 @@
-use crate::db::establish_connection;
+use crate::db::{repository, DbError};
 use crate::models::{Client, Company, NewClient};
-use crate::schema::{clients, companies, company_clients};
-use crate::utils::get_input;
+use crate::report::Ledger;
+use crate::service::ClientInput;
+use crate::utils::{get_input, select_index};
+use chrono::NaiveDate;
 use cli_table::{print_stdout, Cell, Style, Table};
-use diesel::prelude::*;
-use diesel::RunQueryDsl;
 use indexmap::IndexMap;
+use std::collections::HashMap;
 use text_colorizer::*;
 
 pub enum ClientSearchField {
@@ -18,7 +19,7 @@ pub enum ClientSearchField {
 fn process_results(
     results: Vec<(Client, Company)>,
     print_table: bool,
-) -> IndexMap<Client, Vec<String>> {
+) -> Result<IndexMap<Client, Vec<String>>, DbError> {
     let mut map = IndexMap::new();
     for (client, company) in results {
         let companies = map.entry(client).or_insert(Vec::new());
@@ -26,10 +27,10 @@ fn process_results(
     }
 
     if print_table {
-        client_table(&map);
+        client_table(&map)?;
     }
 
-    map
+    Ok(map)
 }
 
 impl Client {
@@ -38,7 +39,7 @@ impl Client {
         address: Option<&str>,
         email: Option<&str>,
         phone: Option<&str>,
-    ) -> Client {
+    ) -> Result<Client, DbError> {
         let new_client = NewClient {
             name,
             address,
@@ -46,24 +47,11 @@ impl Client {
             phone,
         };
 
-        let conn = &mut establish_connection();
-
-        diesel::insert_into(clients::table)
-            .values(&new_client)
-            .returning(Client::as_returning())
-            .get_result(conn)
-            .expect("Error saving new client")
+        repository()?.create_client(&new_client, &[], None)
     }
 
-    fn with_companies() -> IndexMap<Client, Vec<String>> {
-        let conn = &mut establish_connection();
-
-        let results = clients::table
-            .inner_join(company_clients::table.on(clients::id.eq(company_clients::client_id)))
-            .inner_join(companies::table.on(companies::id.eq(company_clients::company_id)))
-            .select((clients::all_columns, companies::all_columns))
-            .load::<(Client, Company)>(conn)
-            .expect("Error loading clients with companies");
+    fn with_companies() -> Result<IndexMap<Client, Vec<String>>, DbError> {
+        let results = repository()?.clients_with_companies(None)?;
 
         process_results(results, true)
     }
@@ -71,96 +59,69 @@ impl Client {
         field: ClientSearchField,
         search_value: &str,
         print_table: Option<bool>,
-    ) -> IndexMap<Client, Vec<String>> {
-        let conn = &mut establish_connection();
-
-        let mut query = clients::table
-            .inner_join(company_clients::table.on(clients::id.eq(company_clients::client_id)))
-            .inner_join(companies::table.on(companies::id.eq(company_clients::company_id)))
-            .into_boxed();
-
-        query = match field {
-            ClientSearchField::Name => {
-                query.filter(clients::name.like(format!("%{}%", search_value)))
-            }
-            ClientSearchField::Address => {
-                query.filter(clients::address.like(format!("%{}%", search_value)))
-            }
-            ClientSearchField::Company => {
-                query.filter(companies::name.like(format!("%{}%", search_value)))
-            }
-        };
-
-        let results = query
-            .select((clients::all_columns, companies::all_columns))
-            .order(clients::name.asc())
-            .load::<(Client, Company)>(conn)
-            .expect("Error loading clients with companies");
+    ) -> Result<IndexMap<Client, Vec<String>>, DbError> {
+        let results = repository()?.clients_with_companies(Some((&field, search_value)))?;
 
         let should_print = print_table.unwrap_or(true);
 
         process_results(results, should_print)
     }
 
-    pub fn find_by_id(user_id: i32) -> Option<Client> {
-        let conn = &mut establish_connection();
+    /// Sets or, with `None`, removes the client's credit limit.
+    pub fn set_credit_limit(client_id: i32, credit_limit: Option<f64>) -> Result<Client, String> {
+        repository()?
+            .set_credit_limit(client_id, credit_limit)
+            .map_err(|e| format!("Error updating client: {}", e))?
+            .ok_or_else(|| format!("Client with id {} not found", client_id))
+    }
 
-        clients::table
-            .find(user_id)
-            .first::<Client>(conn)
-            .optional()
-            .expect("Error loading client")
+    pub fn find_by_id(user_id: i32) -> Result<Option<Client>, DbError> {
+        repository()?.find_client(user_id)
     }
 
     pub fn select_client(clients: &IndexMap<Client, Vec<String>>) -> Option<&Client> {
         // Display the clients and let the user select one
         println!("{}", "Multiple clients found. \nSelect a client:".green());
-        let mut i = 1;
-        for client in clients.keys() {
+        for (i, client) in (1..).zip(clients.keys()) {
             let client_address = client.address.clone().unwrap_or_default();
 
             println!("{}: {} - {}", i, client.name, client_address);
-            i += 1;
         }
 
         // Get the user's selection
-        let mut selection_str = String::new();
-        std::io::stdin()
-            .read_line(&mut selection_str)
-            .expect("Failed to read line");
-        let selection = selection_str.trim().parse::<usize>().unwrap();
-
-        // Get the selected client
-        clients.iter().nth(selection - 1).map(|(client, _)| client)
+        select_index(clients.len())
+            .and_then(|index| clients.get_index(index))
+            .map(|(client, _)| client)
     }
 }
 
 pub fn search_by_name(
     search_name: &str,
     print_table: Option<bool>,
-) -> IndexMap<Client, Vec<String>> {
+) -> Result<IndexMap<Client, Vec<String>>, DbError> {
     Client::search_by(ClientSearchField::Name, search_name, print_table)
 }
 pub fn search_by_address(
     search_address: &str,
     print_table: Option<bool>,
-) -> IndexMap<Client, Vec<String>> {
+) -> Result<IndexMap<Client, Vec<String>>, DbError> {
     Client::search_by(ClientSearchField::Address, search_address, print_table)
 }
 
 pub fn search_by_company(
     search_company: &str,
     print_table: Option<bool>,
-) -> IndexMap<Client, Vec<String>> {
+) -> Result<IndexMap<Client, Vec<String>>, DbError> {
     Client::search_by(ClientSearchField::Company, search_company, print_table)
 }
 
-pub fn create_client_prompts(
+/// Asks for whichever client details weren't given.
+pub fn prompt_client(
     name: Option<String>,
     address: Option<String>,
     email: Option<String>,
     phone: Option<String>,
-) -> Client {
+) -> ClientInput {
     let name = match name {
         Some(name) => name,
         None => get_input("Enter client name: "),
@@ -211,40 +172,101 @@ pub fn create_client_prompts(
         }
     };
 
-    let client = Client::create(
-        &name,
-        address.as_deref(),
-        email.as_deref(),
-        phone.as_deref(),
-    );
+    ClientInput {
+        name,
+        address,
+        email,
+        phone,
+        ..Default::default()
+    }
+}
 
-    let default_company_id = crate::config::load_config().default_company;
+/// What a client owes as of a date.
+#[derive(Default)]
+pub struct ClientBalance {
+    pub outstanding: f64,
+    pub overdue: f64,
+    /// Days overdue of each overdue invoice
+    pub days_overdue: Vec<i64>,
+}
 
-    // create company client many-to-many relationship
-    crate::company_clients::create_company_client(default_company_id, client.id);
+/// Outstanding and overdue balances per client id as of today.
+pub fn balances() -> Result<HashMap<i32, ClientBalance>, DbError> {
+    let today = chrono::offset::Local::now().date_naive();
+    let terms = crate::config::load_config()?.payment_terms_days;
 
-    println!("{} ", format!("Client {} added", client.name).green());
+    Ok(balances_as_of(&Ledger::load()?, today, terms))
+}
 
-    client
+/// Balances from a loaded ledger, which leaves regenerated copies out so a
+/// client is never held to the same invoice twice.
+fn balances_as_of(ledger: &Ledger, today: NaiveDate, terms: i64) -> HashMap<i32, ClientBalance> {
+    let mut balances: HashMap<i32, ClientBalance> = HashMap::new();
+    for (invoice, due, balance) in ledger.outstanding(today, terms) {
+        let Some(client_id) = invoice.client_id else {
+            continue;
+        };
+        let entry = balances.entry(client_id).or_default();
+        entry.outstanding += balance;
+        if due < today {
+            entry.overdue += balance;
+            entry.days_overdue.push((today - due).num_days());
+        }
+    }
+
+    balances
 }
 
-pub fn list_clients() -> Vec<Client> {
-    use crate::schema::clients::dsl::*;
+/// Reasons not to give the client a new invoice of `amount`: going over their
+/// credit limit, or invoices more than `max_overdue_days` overdue.
+pub fn credit_problems(client: &Client, amount: f64) -> Result<Vec<String>, DbError> {
+    let max_overdue_days = crate::config::load_config()?.max_overdue_days;
+    let balance = balances()?.remove(&client.id).unwrap_or_default();
+
+    let mut problems = Vec::new();
+
+    if let Some(credit_limit) = client.credit_limit {
+        if balance.outstanding + amount > credit_limit + 0.005 {
+            problems.push(format!(
+                "This invoice takes {}'s balance to {:.2}, over their credit limit of {:.2}",
+                client.name,
+                balance.outstanding + amount,
+                credit_limit
+            ));
+        }
+    }
+
+    let long_overdue = balance
+        .days_overdue
+        .iter()
+        .filter(|days| **days > max_overdue_days)
+        .count();
+    if long_overdue > 0 {
+        problems.push(format!(
+            "{} has {} invoice(s) more than {} days overdue",
+            client.name, long_overdue, max_overdue_days
+        ));
+    }
 
-    let conn = &mut establish_connection();
+    Ok(problems)
+}
 
-    let list = clients.load::<Client>(conn).expect("Error loading clients");
+pub fn list_clients() -> Result<Vec<Client>, DbError> {
+    let list = repository()?.list_clients()?;
 
-    Client::with_companies();
+    Client::with_companies()?;
 
-    list
+    Ok(list)
 }
 
-fn client_table(clients: &IndexMap<Client, Vec<String>>) {
+fn client_table(clients: &IndexMap<Client, Vec<String>>) -> Result<(), DbError> {
+    let balances = balances()?;
+
     let clients = clients
         .iter()
         .map(|(client, company_names)| {
             let company_names = company_names.join(", ");
+            let balance = balances.get(&client.id);
             vec![
                 client.id.to_string().cell(),
                 client.name.clone().cell(),
@@ -252,6 +274,13 @@ fn client_table(clients: &IndexMap<Client, Vec<String>>) {
                 company_names.cell(),
                 client.email.clone().unwrap_or_default().cell(),
                 client.phone.clone().unwrap_or_default().cell(),
+                format!("{:.2}", balance.map_or(0.0, |b| b.outstanding)).cell(),
+                format!("{:.2}", balance.map_or(0.0, |b| b.overdue)).cell(),
+                client
+                    .credit_limit
+                    .map(|limit| format!("{:.2}", limit))
+                    .unwrap_or_default()
+                    .cell(),
             ]
         })
         .collect::<Vec<_>>();
@@ -265,9 +294,63 @@ fn client_table(clients: &IndexMap<Client, Vec<String>>) {
             "Companies".cell(),
             "Email".cell(),
             "Phone".cell(),
+            "Balance".cell(),
+            "Overdue".cell(),
+            "Credit Limit".cell(),
         ])
         .bold(true);
 
     // Print the table
     print_stdout(table).expect("Error printing the table");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Invoice;
+
+    fn invoice(id: i32, regenerated: bool) -> Invoice {
+        Invoice {
+            id,
+            company_id: Some(1),
+            company_name: "Acme".to_string(),
+            company_address: None,
+            company_email: None,
+            company_phone: None,
+            client_id: Some(3),
+            client_name: "Carol".to_string(),
+            client_address: None,
+            client_email: None,
+            client_phone: None,
+            date: "01 October 2026".to_string(),
+            total_amount: 80.0,
+            logo_url: None,
+            tax: None,
+            notes: None,
+            regenerated: Some(regenerated),
+            quote_id: None,
+            due_date: Some("2026-10-31".to_string()),
+            original_number: None,
+        }
+    }
+
+    #[test]
+    fn regenerated_invoices_do_not_add_to_the_balance() {
+        let ledger = Ledger::from_records(
+            vec![invoice(1, false), invoice(2, true)],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        )
+        .unwrap();
+
+        let today = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let balances = balances_as_of(&ledger, today, 30);
+
+        assert_eq!(balances[&3].outstanding, 80.0);
+        assert_eq!(balances[&3].overdue, 0.0);
+    }
 }
@@ -1,8 +1,7 @@
-use crate::db::establish_connection;
+use crate::db::{repository, DbError};
 use crate::models::{Company, NewCompany};
-use crate::schema::companies;
-use crate::utils::get_input;
-use diesel::prelude::*;
+use crate::service::CompanyInput;
+use crate::utils::{get_input, select_index};
 use text_colorizer::*;
 
 pub enum CompanySearchField {
@@ -16,7 +15,7 @@ impl Company {
         address: Option<&str>,
         email: Option<&str>,
         phone: Option<&str>,
-    ) -> Company {
+    ) -> Result<Company, DbError> {
         let new_company = NewCompany {
             name,
             address,
@@ -24,79 +23,51 @@ impl Company {
             phone,
         };
 
-        let conn = &mut establish_connection();
-
-        diesel::insert_into(companies::table)
-            .values(&new_company)
-            .returning(Company::as_returning())
-            .get_result(conn)
-            .expect("Error saving new client")
+        repository()?.create_company(&new_company)
     }
-    pub fn default_company() -> Company {
-        use crate::schema::companies::dsl::*;
-
-        let default_company_id = crate::config::load_config().default_company;
-
-        let conn = &mut establish_connection();
+    pub fn default_company() -> Result<Company, DbError> {
+        let default_company_id = crate::config::load_config()?.default_company;
 
-        companies
-            .filter(id.eq(default_company_id))
-            .first::<Company>(conn)
-            .expect("Error loading company")
+        Company::find_by_id(default_company_id)?.ok_or_else(|| {
+            DbError::NotFound(format!("Default company with id {}", default_company_id))
+        })
     }
 
-    pub fn search_by(field: CompanySearchField, search_value: &str) -> Vec<Company> {
-        let conn = &mut establish_connection();
-
-        let mut query = companies::table.into_boxed();
-
-        query = match field {
-            CompanySearchField::Name => {
-                query.filter(companies::name.like(format!("%{}%", search_value)))
-            }
-            CompanySearchField::Address => {
-                query.filter(companies::address.like(format!("%{}%", search_value)))
-            }
-        };
+    pub fn find_by_id(company_id: i32) -> Result<Option<Company>, DbError> {
+        repository()?.find_company(company_id)
+    }
 
-        query
-            .select(companies::all_columns)
-            .load::<Company>(conn)
-            .expect("Error loading clients with companies")
+    pub fn search_by(
+        field: CompanySearchField,
+        search_value: &str,
+    ) -> Result<Vec<Company>, DbError> {
+        repository()?.search_companies(&field, search_value)
     }
 
-    pub fn select_company(companies: &Vec<Company>) -> Option<&Company> {
+    pub fn select_company(companies: &[Company]) -> Option<&Company> {
         // Display the clients and let the user select one
         println!(
             "{}",
             "Multiple companies found. \nSelect a company:".green()
         );
-        let mut i = 1;
-        for company in companies {
+        for (i, company) in (1..).zip(companies.iter()) {
             let company_address = company.address.clone().unwrap_or_default();
 
             println!("{}: {} - {}", i, company.name, company_address);
-            i += 1;
         }
 
         // Get the user's selection
-        let mut selection_str = String::new();
-        std::io::stdin()
-            .read_line(&mut selection_str)
-            .expect("Failed to read line");
-        let selection = selection_str.trim().parse::<usize>().unwrap();
-
-        // Get the selected client
-        companies.get(selection - 1)
+        select_index(companies.len()).and_then(|index| companies.get(index))
     }
 }
 
-pub fn create_company_prompts(
+/// Asks for whichever company details weren't given.
+pub fn prompt_company(
     name: Option<String>,
     address: Option<String>,
     email: Option<String>,
     phone: Option<String>,
-) -> Company {
+) -> CompanyInput {
     let name = match name {
         Some(name) => name,
         None => get_input("Enter company name: "),
@@ -147,14 +118,30 @@ pub fn create_company_prompts(
         }
     };
 
+    CompanyInput {
+        name,
+        address,
+        email,
+        phone,
+    }
+}
+
+pub fn create_company_prompts(
+    name: Option<String>,
+    address: Option<String>,
+    email: Option<String>,
+    phone: Option<String>,
+) -> Result<Company, DbError> {
+    let input = prompt_company(name, address, email, phone);
+
     let company = Company::create(
-        &name,
-        address.as_deref(),
-        email.as_deref(),
-        phone.as_deref(),
-    );
+        &input.name,
+        input.address.as_deref(),
+        input.email.as_deref(),
+        input.phone.as_deref(),
+    )?;
 
     println!("{} ", format!("Company {} added", company.name).green());
 
-    company
+    Ok(company)
 }
@@ -1,45 +1,525 @@
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use text_colorizer::*;
 
-use crate::company::create_company_prompts;
-use crate::db::run_migration;
+use crate::company::{create_company_prompts, CompanySearchField};
+use crate::db::{run_migration, Backend, Pooled};
+use crate::models::Company;
+use crate::repository::Repository;
 use crate::utils::get_input;
 
-#[derive(Serialize, Deserialize)]
+/// Current layout of config.json. Older files are upgraded when loaded; any
+/// field they are missing takes its default.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Config {
+    /// Layout version the file was written with; 0 for files that predate it
+    #[serde(default)]
+    pub version: u32,
     pub setup_done: bool,
     pub database_url: String,
     pub default_company: i32,
     pub logo_path: String,
     pub invoice_path: String,
+    /// Custom invoice template; the built-in one is used when empty
+    pub template_path: String,
+    /// Number of days after the invoice date that payment is due
+    pub payment_terms_days: i64,
+    /// Invoices overdue by more than this many days put the client on credit hold
+    pub max_overdue_days: i64,
+    /// Whether tax is accounted for when invoiced (accrual) or when paid (cash)
+    pub tax_basis: TaxBasis,
+    /// Account names used when exporting to plain-text accounting ledgers
+    pub ledger_accounts: LedgerAccounts,
+    /// Bearer token `cli-invoice serve` requires on every request; the server
+    /// won't start without one
+    pub api_token: String,
+    /// Profile used when no `--profile` is given; `None` is the default profile
+    pub active_profile: Option<String>,
+    /// Named profiles, each with its own database, files and default company.
+    /// The top-level settings above form the default profile.
+    pub profiles: BTreeMap<String, Profile>,
 }
 
-pub fn is_setup_done() -> bool {
-    let config_path = Path::new("config.json");
-    let path_exists = config_path.exists();
+/// The settings that differ between profiles. Everything else in [`Config`]
+/// is shared.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Profile {
+    pub database_url: String,
+    pub default_company: i32,
+    pub logo_path: String,
+    pub invoice_path: String,
+    pub template_path: String,
+}
 
-    if !path_exists {
-        return false;
+impl Config {
+    pub fn profile(&self) -> Profile {
+        Profile {
+            database_url: self.database_url.clone(),
+            default_company: self.default_company,
+            logo_path: self.logo_path.clone(),
+            invoice_path: self.invoice_path.clone(),
+            template_path: self.template_path.clone(),
+        }
+    }
+
+    fn set_profile(&mut self, profile: Profile) {
+        self.database_url = profile.database_url;
+        self.default_company = profile.default_company;
+        self.logo_path = profile.logo_path;
+        self.invoice_path = profile.invoice_path;
+        self.template_path = profile.template_path;
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            setup_done: false,
+            database_url: String::new(),
+            default_company: -1,
+            logo_path: String::new(),
+            invoice_path: String::new(),
+            template_path: String::new(),
+            payment_terms_days: 30,
+            max_overdue_days: 30,
+            tax_basis: TaxBasis::default(),
+            ledger_accounts: LedgerAccounts::default(),
+            api_token: String::new(),
+            active_profile: None,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaxBasis {
+    #[default]
+    Accrual,
+    Cash,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LedgerAccounts {
+    pub commodity: String,
+    pub receivable: String,
+    pub income: String,
+    pub tax: String,
+    pub bank: String,
+    /// Per-company overrides, keyed by company name
+    pub companies: BTreeMap<String, CompanyAccounts>,
+    /// Income accounts for specific products, keyed by item description
+    pub products: BTreeMap<String, String>,
+}
+
+impl Default for LedgerAccounts {
+    fn default() -> Self {
+        LedgerAccounts {
+            commodity: "GBP".to_string(),
+            receivable: "Assets:Receivable".to_string(),
+            income: "Income:Sales".to_string(),
+            tax: "Liabilities:Tax".to_string(),
+            bank: "Assets:Bank".to_string(),
+            companies: BTreeMap::new(),
+            products: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CompanyAccounts {
+    pub receivable: Option<String>,
+    pub income: Option<String>,
+    pub tax: Option<String>,
+    pub bank: Option<String>,
+}
+
+impl LedgerAccounts {
+    fn company(&self, company_name: &str) -> CompanyAccounts {
+        self.companies
+            .get(company_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn receivable(&self, company_name: &str) -> String {
+        self.company(company_name)
+            .receivable
+            .unwrap_or_else(|| self.receivable.clone())
+    }
+
+    pub fn tax(&self, company_name: &str) -> String {
+        self.company(company_name)
+            .tax
+            .unwrap_or_else(|| self.tax.clone())
+    }
+
+    pub fn bank(&self, company_name: &str) -> String {
+        self.company(company_name)
+            .bank
+            .unwrap_or_else(|| self.bank.clone())
+    }
+
+    /// Income account for an item: the product's account, then the company's,
+    /// then the default.
+    pub fn income(&self, company_name: &str, description: &str) -> String {
+        self.products
+            .get(description)
+            .cloned()
+            .or_else(|| self.company(company_name).income)
+            .unwrap_or_else(|| self.income.clone())
+    }
+}
+
+/// config.json could not be found, read or written, or names a profile it
+/// doesn't have.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The platform has no config directory and no path was given
+    NoConfigDir,
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_json::Error),
+    Write(PathBuf, std::io::Error),
+    /// `--profile` or `active_profile` names a profile that isn't in `profiles`
+    ProfileNotFound(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::NoConfigDir => write!(
+                f,
+                "Config directory not found; pass --config or set CLINVOICE_CONFIG"
+            ),
+            ConfigError::Read(path, e) => write!(f, "Unable to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Unable to parse {}: {}", path.display(), e),
+            ConfigError::Write(path, e) => write!(f, "Unable to write {}: {}", path.display(), e),
+            ConfigError::ProfileNotFound(name) => write!(
+                f,
+                "Profile {} not found; pick another with cli-invoice profile switch",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for String {
+    fn from(e: ConfigError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Set from `--config`; takes precedence over `CLINVOICE_CONFIG` and the
+/// platform config directory.
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Set from `--profile`; takes precedence over `active_profile`.
+static PROFILE: Mutex<Option<String>> = Mutex::new(None);
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Settings given by environment variables (including a `.env` file) or global
+/// flags. They take precedence over config.json for this run and are never
+/// saved to it.
+#[derive(Clone, Default)]
+pub struct Overrides {
+    pub database_url: Option<String>,
+    pub invoice_path: Option<String>,
+    pub logo_path: Option<String>,
+    pub default_company: Option<i32>,
+}
+
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+impl Overrides {
+    /// Reads `CLINVOICE_DATABASE_URL`, `CLINVOICE_INVOICE_PATH`,
+    /// `CLINVOICE_LOGO_PATH` and `CLINVOICE_DEFAULT_COMPANY`. Empty variables
+    /// are ignored.
+    pub fn from_env() -> Result<Overrides, String> {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+
+        let default_company = match var("CLINVOICE_DEFAULT_COMPANY") {
+            Some(value) => Some(value.parse().map_err(|_| {
+                format!(
+                    "CLINVOICE_DEFAULT_COMPANY must be a company id, got {}",
+                    value
+                )
+            })?),
+            None => None,
+        };
+
+        Ok(Overrides {
+            database_url: var("CLINVOICE_DATABASE_URL"),
+            invoice_path: var("CLINVOICE_INVOICE_PATH"),
+            logo_path: var("CLINVOICE_LOGO_PATH"),
+            default_company,
+        })
+    }
+
+    /// Layers `flags` over these settings.
+    pub fn merge(self, flags: Overrides) -> Overrides {
+        Overrides {
+            database_url: flags.database_url.or(self.database_url),
+            invoice_path: flags.invoice_path.or(self.invoice_path),
+            logo_path: flags.logo_path.or(self.logo_path),
+            default_company: flags.default_company.or(self.default_company),
+        }
+    }
+
+    fn is_set(&self, key: &str) -> bool {
+        match key {
+            "database_url" => self.database_url.is_some(),
+            "invoice_path" => self.invoice_path.is_some(),
+            "logo_path" => self.logo_path.is_some(),
+            "default_company" => self.default_company.is_some(),
+            _ => false,
+        }
+    }
+
+    fn apply(&self, config: &mut Config) {
+        if let Some(database_url) = &self.database_url {
+            config.database_url = database_url.clone();
+        }
+        if let Some(invoice_path) = &self.invoice_path {
+            config.invoice_path = invoice_path.clone();
+        }
+        if let Some(logo_path) = &self.logo_path {
+            config.logo_path = logo_path.clone();
+        }
+        if let Some(default_company) = self.default_company {
+            config.default_company = default_company;
+        }
+    }
+
+    /// Puts back the saved values of overridden settings so they are not
+    /// written to config.json.
+    fn restore(&self, config: &mut Config, saved: &Config) {
+        if self.database_url.is_some() {
+            config.database_url = saved.database_url.clone();
+        }
+        if self.invoice_path.is_some() {
+            config.invoice_path = saved.invoice_path.clone();
+        }
+        if self.logo_path.is_some() {
+            config.logo_path = saved.logo_path.clone();
+        }
+        if self.default_company.is_some() {
+            config.default_company = saved.default_company;
+        }
+    }
+}
+
+/// Config file used by versions that kept it in the working directory.
+const LEGACY_CONFIG_PATH: &str = "config.json";
+
+/// Resolves where the config lives, copies a `./config.json` left by older
+/// versions there and upgrades it to the current layout. Must run before the
+/// config is first loaded.
+pub fn init(path: Option<PathBuf>) -> Result<(), ConfigError> {
+    if let Some(path) = path {
+        let _ = CONFIG_PATH.set(path);
+    }
+
+    let path = config_path()?;
+    let legacy = Path::new(LEGACY_CONFIG_PATH);
+    if !path.exists() && is_legacy_config(legacy) && path != legacy {
+        migrate_legacy_config(legacy, &path);
+    }
+
+    if path.exists() {
+        let config = read_config()?;
+        if config.version < CONFIG_VERSION {
+            // Missing fields were filled with defaults when loading
+            write_config(&Config {
+                version: CONFIG_VERSION,
+                ..config
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `path` is a config.json written by an older cli-invoice rather
+/// than some other tool's file that happens to share the name.
+fn is_legacy_config(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .and_then(|json| json.as_object().cloned())
+        .is_some_and(|json| json.contains_key("setup_done") && json.contains_key("database_url"))
+}
+
+/// Copies rather than moves, so the old file is still there if the working
+/// directory wasn't the one the user meant.
+fn migrate_legacy_config(legacy: &Path, path: &Path) {
+    let copied = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::copy(legacy, path));
+    match copied {
+        Ok(_) => println!(
+            "{} {}{}",
+            "Copied config.json to".yellow(),
+            path.display().to_string().bright_black(),
+            "; the old file was left in place and can be deleted".yellow()
+        ),
+        Err(e) => println!(
+            "{} {}",
+            "Unable to copy config.json:".red(),
+            format!("{}: {}", path.display(), e).bright_black()
+        ),
+    }
+}
+
+/// `--config`, then `CLINVOICE_CONFIG`, then `<config dir>/clinvoice/config.json`.
+pub fn config_path() -> Result<PathBuf, ConfigError> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return Ok(path.clone());
+    }
+    if let Some(path) = env::var_os("CLINVOICE_CONFIG").filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+
+    Ok(dirs::config_dir()
+        .ok_or(ConfigError::NoConfigDir)?
+        .join("clinvoice")
+        .join("config.json"))
+}
+
+/// Uses `overrides` for the rest of this run. Must run before the config is
+/// first loaded.
+pub fn set_overrides(overrides: Overrides) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+pub fn overrides() -> &'static Overrides {
+    OVERRIDES.get_or_init(Overrides::default)
+}
+
+pub fn is_setup_done() -> Result<bool, ConfigError> {
+    if !config_path()?.exists() {
+        return Ok(false);
+    }
+
+    Ok(read_config()?.setup_done)
+}
+
+/// Uses `name` instead of the active profile for the rest of this run.
+pub fn use_profile(name: Option<String>) {
+    *PROFILE.lock().unwrap() = name;
+    crate::db::reset_connections();
+}
+
+/// The profile in use: `--profile`, then `active_profile`, then the default.
+pub fn profile_name() -> Result<String, ConfigError> {
+    let name = PROFILE.lock().unwrap().clone();
+    Ok(match name {
+        Some(name) => name,
+        None => stored_config()?
+            .active_profile
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+    })
+}
+
+/// config.json as stored, with the default profile's settings at the top level.
+pub fn read_config() -> Result<Config, ConfigError> {
+    let path = config_path()?;
+    let config_json = fs::read_to_string(&path).map_err(|e| ConfigError::Read(path.clone(), e))?;
+    serde_json::from_str(&config_json).map_err(|e| ConfigError::Parse(path, e))
+}
+
+/// Like [`read_config`], but the defaults when there is no config.json yet,
+/// such as when running on overrides alone.
+fn stored_config() -> Result<Config, ConfigError> {
+    if config_path()?.exists() {
+        read_config()
+    } else {
+        Ok(Config::default())
     }
+}
 
-    let config_json = fs::read_to_string("config.json").expect("Unable to read file");
-    let config: Config = serde_json::from_str(&config_json).expect("Unable to parse config.json");
+/// Writes config.json as is, see [`read_config`].
+pub fn write_config(config: &Config) -> Result<(), ConfigError> {
+    let path = config_path()?;
+    let config_json =
+        serde_json::to_string(config).map_err(|e| ConfigError::Write(path.clone(), e.into()))?;
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, config_json))
+        .map_err(|e| ConfigError::Write(path, e))?;
+    // The database may have moved
+    crate::db::reset_connections();
 
-    config.setup_done
+    Ok(())
 }
 
-pub fn load_config() -> Config {
-    let config_json = fs::read_to_string("config.json").expect("Unable to read file");
-    serde_json::from_str(&config_json).expect("Unable to parse config.json")
+/// The config with the active profile's settings in place of the default's,
+/// and any [`Overrides`] on top.
+pub fn load_config() -> Result<Config, ConfigError> {
+    let mut config = saved_config()?;
+    overrides().apply(&mut config);
+    Ok(config)
 }
 
-pub fn setup() -> Config {
+/// The active profile's config as saved, without overrides.
+fn saved_config() -> Result<Config, ConfigError> {
+    let mut config = stored_config()?;
+    let name = PROFILE
+        .lock()
+        .unwrap()
+        .clone()
+        .or_else(|| config.active_profile.clone());
+
+    if let Some(name) = name.filter(|name| name != DEFAULT_PROFILE) {
+        let profile = config
+            .profiles
+            .get(&name)
+            .cloned()
+            .ok_or(ConfigError::ProfileNotFound(name))?;
+        config.set_profile(profile);
+    }
+
+    Ok(config)
+}
+
+/// Saves a config returned by [`load_config`], writing the profile settings
+/// back to the active profile.
+pub fn save_config(config: &Config) -> Result<(), ConfigError> {
+    let mut config = config.clone();
+    overrides().restore(&mut config, &saved_config()?);
+
+    let name = profile_name()?;
+    if name == DEFAULT_PROFILE || !config_path()?.exists() {
+        return write_config(&config);
+    }
+
+    let stored = read_config()?;
+    let mut updated = config.clone();
+    updated.set_profile(stored.profile());
+    updated.profiles.insert(name, config.profile());
+    write_config(&updated)
+}
+
+pub fn setup() -> Result<Config, String> {
     let default_path = dirs::home_dir()
-        .expect("Home directory not found")
+        .ok_or("Home directory not found")?
         .join("clinvoice")
         .display()
         .to_string();
@@ -53,7 +533,8 @@ pub fn setup() -> Config {
         db_url = default_path.clone();
 
         if !Path::new(&db_url).exists() {
-            fs::create_dir_all(&db_url).expect("Failed to create directory");
+            fs::create_dir_all(&db_url)
+                .map_err(|e| format!("Unable to create {}: {}", db_url, e))?;
         }
     }
 
@@ -89,26 +570,34 @@ pub fn setup() -> Config {
 
     // Ensure the invoice save directory exists
     if !Path::new(&invoice_save_path).exists() {
-        fs::create_dir_all(&invoice_save_path).expect("Failed to create invoice directory");
+        fs::create_dir_all(&invoice_save_path)
+            .map_err(|e| format!("Unable to create {}: {}", invoice_save_path, e))?;
     }
 
     // Run migrations
     let mut conn = SqliteConnection::establish(&db_file_path)
-        .unwrap_or_else(|_| panic!("{} {}", "Error connecting to".red(), &db_file_path));
+        .map_err(|e| format!("Error connecting to {}: {}", db_file_path, e))?;
 
-    run_migration(&mut conn);
+    run_migration(&mut conn)?;
 
+    // Re-running setup keeps shared settings and other profiles
+    let existing = if config_path()?.exists() {
+        load_config()?
+    } else {
+        Config::default()
+    };
     let config = Config {
         setup_done: false,
         database_url: db_file_path,
         default_company: -1,
         logo_path: String::new(),
         invoice_path: invoice_save_path,
+        ..existing
     };
-    let config_json = serde_json::to_string(&config).unwrap();
-    fs::write("config.json", config_json).unwrap();
+    save_config(&config)?;
 
-    let company = create_company_prompts(None, None, None, None);
+    let company = create_company_prompts(None, None, None, None)
+        .map_err(|e| format!("Error creating company: {}", e))?;
 
     let mut logo_path = get_input("Path to your logo (png/jpeg/svg): ");
 
@@ -120,18 +609,97 @@ pub fn setup() -> Config {
         logo_path = get_input("Path to your logo (png/jpeg/svg): ");
     }
 
-    let mut updated_config = load_config();
+    let mut updated_config = load_config()?;
     updated_config.setup_done = true;
     updated_config.default_company = company.id;
     updated_config.logo_path = logo_path;
-    let updated_config_json = serde_json::to_string(&updated_config).unwrap();
-    fs::write("config.json", updated_config_json).unwrap();
+    save_config(&updated_config)?;
+
+    println!("{}", "Setup done".green());
+    Ok(config)
+}
+
+/// Answers to the setup prompts, for [`setup_with`].
+pub struct SetupOptions {
+    /// Where the database is created (defaults to ~/clinvoice)
+    pub db_dir: Option<PathBuf>,
+    /// Where invoices are saved (defaults to `db_dir`)
+    pub invoice_dir: Option<PathBuf>,
+    pub company_name: String,
+    pub company_address: Option<String>,
+    pub company_email: Option<String>,
+    pub company_phone: Option<String>,
+    pub logo: String,
+}
+
+/// Runs the same steps as [`setup`] without prompting, for provisioning
+/// machines and CI runners. Running it again is safe: the database is only
+/// migrated, and a company with the same name is reused rather than added.
+pub fn setup_with(options: SetupOptions) -> Result<Config, String> {
+    let company_name = options.company_name.trim();
+    if company_name.is_empty() {
+        return Err("Company name cannot be empty".to_string());
+    }
+    if !is_valid_logo_path(&options.logo) {
+        return Err(format!("{} is not a png, jpeg or svg file", options.logo));
+    }
+
+    let db_dir = match options.db_dir {
+        Some(db_dir) => db_dir,
+        None => dirs::home_dir()
+            .ok_or("Home directory not found")?
+            .join("clinvoice"),
+    };
+    let invoice_dir = options.invoice_dir.unwrap_or_else(|| db_dir.clone());
+    for dir in [&db_dir, &invoice_dir] {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Unable to create {}: {}", dir.display(), e))?;
+    }
+
+    // Connecting creates the SQLite file if it doesn't exist
+    let db_file_path = db_dir.join("cli_invoice.sqlite3").display().to_string();
+    let mut conn = SqliteConnection::establish(&db_file_path)
+        .map_err(|e| format!("Error connecting to {}: {}", db_file_path, e))?;
+    run_migration(&mut conn)?;
+
+    let existing = load_config()?;
+    save_config(&Config {
+        database_url: db_file_path,
+        invoice_path: invoice_dir.display().to_string(),
+        ..existing
+    })?;
+
+    let company = Company::search_by(CompanySearchField::Name, company_name)?
+        .into_iter()
+        .find(|company| company.name == company_name);
+    let company = match company {
+        Some(company) => {
+            println!("{} {}", "Using existing company".yellow(), company.name);
+            company
+        }
+        None => {
+            let company = Company::create(
+                company_name,
+                options.company_address.as_deref(),
+                options.company_email.as_deref(),
+                options.company_phone.as_deref(),
+            )?;
+            println!("{} ", format!("Company {} added", company.name).green());
+            company
+        }
+    };
+
+    let mut config = load_config()?;
+    config.setup_done = true;
+    config.default_company = company.id;
+    config.logo_path = options.logo;
+    save_config(&config)?;
 
     println!("{}", "Setup done".green());
-    config
+    Ok(config)
 }
 
-fn is_valid_logo_path(path: &str) -> bool {
+pub fn is_valid_logo_path(path: &str) -> bool {
     if !Path::new(path).exists() {
         return false;
     }
@@ -141,3 +709,427 @@ fn is_valid_logo_path(path: &str) -> bool {
         Some("png") | Some("jpeg") | Some("jpg") | Some("svg")
     )
 }
+
+/// Flattens the config into `(key, value)` pairs, with nested settings as
+/// dotted keys such as `ledger_accounts.income`.
+fn flatten(prefix: &str, value: &serde_json::Value, out: &mut Vec<(String, String)>) {
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, out);
+            }
+        }
+        serde_json::Value::String(s) => out.push((prefix.to_string(), s.clone())),
+        serde_json::Value::Null => out.push((prefix.to_string(), String::new())),
+        other => out.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+/// Settings that are never printed, only whether they are set.
+const SECRETS: [&str; 1] = ["api_token"];
+
+fn redact(settings: &mut [(String, String)]) {
+    for (key, value) in settings {
+        if SECRETS.contains(&key.as_str()) && !value.is_empty() {
+            *value = "(set, hidden)".to_string();
+        }
+    }
+}
+
+pub fn show() -> Result<(), String> {
+    use cli_table::{print_stdout, Cell, Style, Table};
+
+    let config = serde_json::to_value(load_config()?).map_err(|e| e.to_string())?;
+    let mut settings = Vec::new();
+    flatten("", &config, &mut settings);
+    redact(&mut settings);
+
+    let table = settings
+        .into_iter()
+        .map(|(key, value)| vec![key.cell(), value.cell()])
+        .collect::<Vec<_>>()
+        .table()
+        .title(vec!["Key".cell(), "Value".cell()])
+        .bold(true);
+
+    print_stdout(table).map_err(|e| e.to_string())
+}
+
+fn pointer(key: &str) -> String {
+    format!("/{}", key.replace('.', "/"))
+}
+
+pub fn get(key: &str) -> Result<String, String> {
+    let config = serde_json::to_value(load_config()?).map_err(|e| e.to_string())?;
+    let value = config
+        .pointer(&pointer(key))
+        .ok_or_else(|| format!("Unknown setting {}", key))?;
+
+    let mut settings = Vec::new();
+    flatten(key, value, &mut settings);
+    redact(&mut settings);
+    Ok(match settings.as_slice() {
+        [(_, value)] => value.clone(),
+        _ => serde_json::to_string_pretty(value).unwrap(),
+    })
+}
+
+/// Checks settings that must point at something that exists.
+fn validate(key: &str, config: &Config) -> Result<(), String> {
+    if let Some((name, field)) = key
+        .strip_prefix("profiles.")
+        .and_then(|rest| rest.split_once('.'))
+    {
+        return validate_profile(name, field, config);
+    }
+
+    match key {
+        "database_url"
+            if Backend::of(&config.database_url) == Backend::Sqlite
+                && !Path::new(&config.database_url).is_file() =>
+        {
+            Err(format!(
+                "Database file {} does not exist",
+                config.database_url
+            ))
+        }
+        "invoice_path" if !Path::new(&config.invoice_path).is_dir() => {
+            Err(format!("Directory {} does not exist", config.invoice_path))
+        }
+        "template_path"
+            if !config.template_path.is_empty() && !Path::new(&config.template_path).is_file() =>
+        {
+            Err(format!("Template {} does not exist", config.template_path))
+        }
+        "logo_path" if !is_valid_logo_path(&config.logo_path) => Err(format!(
+            "{} is not a png, jpeg or svg file",
+            config.logo_path
+        )),
+        "default_company"
+            if crate::models::Company::find_by_id(config.default_company)?.is_none() =>
+        {
+            Err(format!(
+                "Company with id {} not found",
+                config.default_company
+            ))
+        }
+        "payment_terms_days" | "max_overdue_days" => {
+            if config.payment_terms_days < 0 || config.max_overdue_days < 0 {
+                Err(format!("{} cannot be negative", key))
+            } else {
+                Ok(())
+            }
+        }
+        "active_profile" => match &config.active_profile {
+            Some(name) if name != DEFAULT_PROFILE && !config.profiles.contains_key(name) => {
+                Err(format!(
+                    "Profile {} not found; create it with cli-invoice profile create",
+                    name
+                ))
+            }
+            _ => Ok(()),
+        },
+        "version" => Err("version is managed automatically".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Checks a setting of another profile with the same rules, against that
+/// profile's own database and files.
+fn validate_profile(name: &str, field: &str, config: &Config) -> Result<(), String> {
+    let Some(profile) = config.profiles.get(name) else {
+        return Ok(());
+    };
+    let mut scoped = config.clone();
+    scoped.set_profile(profile.clone());
+
+    if field != "default_company" {
+        return validate(field, &scoped);
+    }
+
+    // The company has to be in the profile's database, not the active one
+    validate("database_url", &scoped)?;
+    let company = match Backend::of(&scoped.database_url) {
+        Backend::Sqlite => <SqliteConnection as Pooled>::open(&scoped.database_url)?
+            .find_company(scoped.default_company)?,
+        Backend::Postgres => <PgConnection as Pooled>::open(&scoped.database_url)?
+            .find_company(scoped.default_company)?,
+    };
+    match company {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "Company with id {} not found in profile {}",
+            scoped.default_company, name
+        )),
+    }
+}
+
+/// Updates a single setting. The value is parsed to the setting's current
+/// type, and keys under `ledger_accounts.companies` and
+/// `ledger_accounts.products` may be new.
+pub fn set(key: &str, value: &str) -> Result<(), String> {
+    if overrides().is_set(key) {
+        return Err(format!(
+            "{} is overridden by an environment variable or flag for this run",
+            key
+        ));
+    }
+
+    // The active profile's settings are the top-level ones while it is active
+    if let Some(field) = key
+        .strip_prefix("profiles.")
+        .and_then(|rest| rest.strip_prefix(profile_name().ok()?.as_str()))
+        .and_then(|rest| rest.strip_prefix('.'))
+    {
+        return set(field, value);
+    }
+
+    let mut json = serde_json::to_value(load_config()?).map_err(|e| e.to_string())?;
+
+    // Company overrides are created on first use
+    if let Some((company, _)) = key
+        .strip_prefix("ledger_accounts.companies.")
+        .and_then(|rest| rest.rsplit_once('.'))
+    {
+        if let Some(companies) = json
+            .pointer_mut("/ledger_accounts/companies")
+            .and_then(serde_json::Value::as_object_mut)
+        {
+            companies
+                .entry(company)
+                .or_insert_with(|| serde_json::to_value(CompanyAccounts::default()).unwrap());
+        }
+    }
+
+    let (parent_key, field) = match key.rsplit_once('.') {
+        Some((parent, field)) => (Some(parent), field),
+        None => (None, key),
+    };
+    let parent = match parent_key {
+        Some(parent_key) => json.pointer_mut(&pointer(parent_key)),
+        None => Some(&mut json),
+    }
+    .and_then(serde_json::Value::as_object_mut)
+    .ok_or_else(|| format!("Unknown setting {}", key))?;
+
+    let new_value = match parent.get(field) {
+        Some(serde_json::Value::Object(_)) => {
+            return Err(format!(
+                "{} is a group of settings; set its keys instead",
+                key
+            ))
+        }
+        Some(serde_json::Value::String(_)) | Some(serde_json::Value::Null) | None => {
+            serde_json::Value::String(value.to_string())
+        }
+        Some(_) => serde_json::from_str(value)
+            .map_err(|_| format!("Invalid value for {}: {}", key, value))?,
+    };
+    parent.insert(field.to_string(), new_value);
+
+    let config: Config =
+        serde_json::from_value(json).map_err(|e| format!("Invalid value for {}: {}", key, e))?;
+
+    // Keys the config does not know about are dropped when deserializing
+    if serde_json::to_value(&config)
+        .unwrap()
+        .pointer(&pointer(key))
+        .is_none()
+    {
+        return Err(format!("Unknown setting {}", key));
+    }
+
+    validate(key, &config)?;
+    save_config(&config)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_overrides() -> Overrides {
+        Overrides {
+            database_url: Some("/tmp/override.sqlite3".to_string()),
+            invoice_path: None,
+            logo_path: None,
+            default_company: Some(7),
+        }
+    }
+
+    #[test]
+    fn flags_take_precedence_over_environment_variables() {
+        let env = Overrides {
+            invoice_path: Some("/env/invoices".to_string()),
+            ..env_overrides()
+        };
+        let flags = Overrides {
+            database_url: Some("/flag.sqlite3".to_string()),
+            invoice_path: None,
+            logo_path: None,
+            default_company: None,
+        };
+
+        let merged = env.merge(flags);
+        assert_eq!(merged.database_url.as_deref(), Some("/flag.sqlite3"));
+        assert_eq!(merged.invoice_path.as_deref(), Some("/env/invoices"));
+        assert_eq!(merged.default_company, Some(7));
+        assert!(merged.is_set("invoice_path"));
+        assert!(!merged.is_set("logo_path"));
+        assert!(!merged.is_set("payment_terms_days"));
+    }
+
+    #[test]
+    fn overrides_apply_for_the_run_and_are_not_saved() {
+        let saved = Config {
+            database_url: "/saved.sqlite3".to_string(),
+            default_company: 1,
+            logo_path: "/logo.png".to_string(),
+            ..Config::default()
+        };
+        let mut config = saved.clone();
+
+        env_overrides().apply(&mut config);
+        assert_eq!(config.database_url, "/tmp/override.sqlite3");
+        assert_eq!(config.default_company, 7);
+        assert_eq!(config.logo_path, "/logo.png");
+
+        config.logo_path = "/new-logo.png".to_string();
+        env_overrides().restore(&mut config, &saved);
+        assert_eq!(config.database_url, "/saved.sqlite3");
+        assert_eq!(config.default_company, 1);
+        assert_eq!(config.logo_path, "/new-logo.png");
+    }
+
+    #[test]
+    fn flattens_nested_settings_to_dotted_keys() {
+        let mut config = Config {
+            api_token: "secret".to_string(),
+            ..Config::default()
+        };
+        config.profiles.insert(
+            "work".to_string(),
+            Profile {
+                invoice_path: "/work".to_string(),
+                ..Profile::default()
+            },
+        );
+
+        let mut settings = Vec::new();
+        flatten("", &serde_json::to_value(&config).unwrap(), &mut settings);
+        redact(&mut settings);
+        let value = |key: &str| {
+            settings
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.as_str())
+        };
+
+        assert_eq!(value("ledger_accounts.income"), Some("Income:Sales"));
+        assert_eq!(value("profiles.work.invoice_path"), Some("/work"));
+        assert_eq!(value("payment_terms_days"), Some("30"));
+        assert_eq!(value("active_profile"), Some(""));
+        assert_eq!(value("api_token"), Some("(set, hidden)"));
+        assert_eq!(
+            pointer("profiles.work.logo_path"),
+            "/profiles/work/logo_path"
+        );
+    }
+
+    #[test]
+    fn validates_profile_settings_with_the_same_rules() {
+        let dir = env::temp_dir();
+        let mut config = Config {
+            invoice_path: dir.display().to_string(),
+            ..Config::default()
+        };
+        config.profiles.insert(
+            "work".to_string(),
+            Profile {
+                invoice_path: dir.join("missing-invoices").display().to_string(),
+                logo_path: dir.join("missing-logo.png").display().to_string(),
+                database_url: dir.join("missing.sqlite3").display().to_string(),
+                ..Profile::default()
+            },
+        );
+
+        assert_eq!(validate("invoice_path", &config), Ok(()));
+        assert!(validate("profiles.work.invoice_path", &config)
+            .unwrap_err()
+            .contains("missing-invoices"));
+        assert!(validate("profiles.work.logo_path", &config)
+            .unwrap_err()
+            .contains("not a png"));
+        assert!(validate("profiles.work.default_company", &config)
+            .unwrap_err()
+            .contains("missing.sqlite3"));
+        assert_eq!(validate("profiles.work.template_path", &config), Ok(()));
+    }
+
+    #[test]
+    fn rejects_negative_day_counts() {
+        let config = Config {
+            max_overdue_days: -1,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            validate("max_overdue_days", &config),
+            Err("max_overdue_days cannot be negative".to_string())
+        );
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("clinvoice-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn recognises_only_cli_invoice_legacy_configs() {
+        let dir = temp_dir("legacy");
+        let ours = dir.join("ours.json");
+        let other = dir.join("other.json");
+        fs::write(
+            &ours,
+            r#"{"setup_done": true, "database_url": "db.sqlite3"}"#,
+        )
+        .unwrap();
+        fs::write(&other, r#"{"name": "some-package", "version": "1.0.0"}"#).unwrap();
+
+        assert!(is_legacy_config(&ours));
+        assert!(!is_legacy_config(&other));
+        assert!(!is_legacy_config(&dir.join("missing.json")));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn copies_the_legacy_config_and_leaves_the_old_file() {
+        let dir = temp_dir("migrate");
+        let legacy = dir.join("config.json");
+        let path = dir.join("config-dir").join("clinvoice").join("config.json");
+        fs::write(
+            &legacy,
+            r#"{"setup_done": true, "database_url": "db.sqlite3"}"#,
+        )
+        .unwrap();
+
+        migrate_legacy_config(&legacy, &path);
+
+        assert!(legacy.exists());
+        let config: Config = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(config.database_url, "db.sqlite3");
+        // Older files have no version and take defaults for newer settings
+        assert_eq!(config.version, 0);
+        assert_eq!(config.payment_terms_days, 30);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
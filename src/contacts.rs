@@ -0,0 +1,449 @@
+use crate::db::{establish_connection, repository};
+use crate::models::{Client, NewClient};
+use crate::pdf;
+use crate::schema::clients;
+use cli_table::{print_stdout, Cell, Style, Table};
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use text_colorizer::*;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ContactFormat {
+    Csv,
+    Vcard,
+}
+
+impl ContactFormat {
+    /// Picks the format from the file extension, defaulting to CSV.
+    pub fn detect(path: &str) -> ContactFormat {
+        match Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_deref()
+        {
+            Some("vcf") | Some("vcard") => ContactFormat::Vcard,
+            _ => ContactFormat::Csv,
+        }
+    }
+}
+
+/// A client read from an import file.
+#[derive(Default, Debug)]
+struct Contact {
+    name: String,
+    address: Option<String>,
+    email: Option<String>,
+    phone: Option<String>,
+    credit_limit: Option<f64>,
+}
+
+const FIELDS: [&str; 5] = ["name", "address", "email", "phone", "credit_limit"];
+
+/// Candidate CSV header names for each field, matched case-insensitively.
+fn default_headers(field: &str) -> &'static [&'static str] {
+    match field {
+        "name" => &["name", "client", "client name", "full name", "company"],
+        "address" => &["address", "street address", "billing address"],
+        "email" => &["email", "e-mail", "email address", "work email"],
+        "phone" => &["phone", "telephone", "phone number", "mobile"],
+        "credit_limit" => &["credit_limit", "credit limit"],
+        _ => &[],
+    }
+}
+
+/// Parses `--column "field=Header"` arguments into a lookup keyed by field.
+fn parse_columns(columns: &[String]) -> Result<HashMap<String, String>, String> {
+    columns
+        .iter()
+        .map(|column| {
+            let (field, header) = column
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid column {}, expected FIELD=HEADER", column))?;
+            let field = field.trim().to_lowercase();
+            if !FIELDS.contains(&field.as_str()) {
+                return Err(format!(
+                    "Unknown field {}, expected one of {}",
+                    field,
+                    FIELDS.join(", ")
+                ));
+            }
+            Ok((field, header.trim().to_string()))
+        })
+        .collect()
+}
+
+fn read_csv(file: &str, columns: &[String]) -> Result<Vec<Contact>, String> {
+    let columns = parse_columns(columns)?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(file)
+        .map_err(|e| format!("Unable to read {}: {}", file, e))?;
+
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Unable to read headers: {}", e))?
+        .clone();
+
+    let index = |field: &str| match columns.get(field) {
+        Some(header) => headers
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(header))
+            .map(Some)
+            .ok_or_else(|| format!("Column {} not found in {}", header, file)),
+        None => Ok(default_headers(field).iter().find_map(|name| {
+            headers
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+        })),
+    };
+
+    let name = index("name")?.ok_or("Missing name column, map one with --column name=HEADER")?;
+    let address = index("address")?;
+    let email = index("email")?;
+    let phone = index("phone")?;
+    let credit_limit = index("credit_limit")?;
+
+    let mut contacts = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Unable to read row {}: {}", line + 2, e))?;
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| record.get(i))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        let credit_limit = field(credit_limit)
+            .map(|limit| {
+                limit
+                    .parse::<f64>()
+                    .map_err(|_| format!("Invalid credit limit {} on row {}", limit, line + 2))
+            })
+            .transpose()?;
+
+        contacts.push(Contact {
+            name: field(Some(name)).unwrap_or_default(),
+            address: field(address),
+            email: field(email),
+            phone: field(phone),
+            credit_limit,
+        });
+    }
+
+    Ok(contacts)
+}
+
+// One pass from the left, so an escaped backslash followed by `n` stays a
+// backslash and an `n`
+fn unescape_vcard(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                chars.next();
+                unescaped.push('\n');
+            }
+            ('\\', Some(escaped @ (',' | ';' | '\\'))) => {
+                chars.next();
+                unescaped.push(escaped);
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn escape_vcard(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+}
+
+/// Reads the FN/N/ORG, ADR, EMAIL and TEL properties of every card. Only the
+/// first email, phone and address of a card are kept.
+fn read_vcard(file: &str) -> Result<Vec<Contact>, String> {
+    let content =
+        fs::read_to_string(file).map_err(|e| format!("Unable to read {}: {}", file, e))?;
+
+    // Unfold continuation lines, which start with a space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut contacts = Vec::new();
+    let mut card: Option<(Contact, Option<String>)> = None;
+
+    for line in lines {
+        let Some((property, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Drop parameters (`EMAIL;TYPE=work`) and group prefixes (`item1.EMAIL`)
+        let property = property.split(';').next().unwrap_or_default();
+        let property = property
+            .rsplit('.')
+            .next()
+            .unwrap_or_default()
+            .to_uppercase();
+
+        match property.as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VCARD") => {
+                card = Some((Contact::default(), None));
+            }
+            "END" if value.eq_ignore_ascii_case("VCARD") => {
+                if let Some((mut contact, org)) = card.take() {
+                    if contact.name.is_empty() {
+                        contact.name = org.unwrap_or_default();
+                    }
+                    contacts.push(contact);
+                }
+            }
+            _ => {
+                let Some((contact, org)) = card.as_mut() else {
+                    continue;
+                };
+                let value = value.trim();
+                match property.as_str() {
+                    "FN" => contact.name = unescape_vcard(value),
+                    "N" if contact.name.is_empty() => {
+                        // N is family;given;additional;prefix;suffix
+                        let parts = value.split(';').map(unescape_vcard).collect::<Vec<_>>();
+                        contact.name = [parts.get(1), parts.first()]
+                            .into_iter()
+                            .flatten()
+                            .filter(|part| !part.is_empty())
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                    }
+                    "ORG" => {
+                        *org = Some(unescape_vcard(value.split(';').next().unwrap_or_default()))
+                    }
+                    "EMAIL" if contact.email.is_none() => {
+                        contact.email = Some(unescape_vcard(value))
+                    }
+                    "TEL" if contact.phone.is_none() => contact.phone = Some(unescape_vcard(value)),
+                    "ADR" if contact.address.is_none() => {
+                        let address = value
+                            .split(';')
+                            .map(unescape_vcard)
+                            .map(|part| part.replace('\n', ", "))
+                            .filter(|part| !part.trim().is_empty())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        contact.address = Some(address).filter(|a| !a.is_empty());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(contacts)
+}
+
+pub struct ImportArgs {
+    pub file: String,
+    pub format: Option<ContactFormat>,
+    pub columns: Vec<String>,
+    pub company_name: Option<String>,
+    pub dry_run: bool,
+}
+
+/// Imports clients from a CSV or vCard file, skipping any whose name or email
+/// matches an existing client or an earlier row, and links the new clients to
+/// the chosen company. Returns the number of clients created.
+pub fn import(args: ImportArgs) -> Result<usize, Box<dyn std::error::Error>> {
+    let format = args
+        .format
+        .unwrap_or_else(|| ContactFormat::detect(&args.file));
+    let contacts = match format {
+        ContactFormat::Csv => read_csv(&args.file, &args.columns)?,
+        ContactFormat::Vcard => read_vcard(&args.file)?,
+    };
+
+    let company = pdf::get_company(args.company_name)?;
+
+    let repo = &mut *repository()?;
+    let existing = repo.list_clients()?;
+
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut emails: HashMap<String, String> = HashMap::new();
+    for client in &existing {
+        let label = format!("client #{}", client.id);
+        names.insert(client.name.trim().to_lowercase(), label.clone());
+        if let Some(email) = &client.email {
+            emails.insert(email.trim().to_lowercase(), label);
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut created = 0;
+
+    for (i, contact) in contacts.iter().enumerate() {
+        let name_key = contact.name.trim().to_lowercase();
+        let email_key = contact.email.as_ref().map(|e| e.trim().to_lowercase());
+
+        let duplicate_of = names
+            .get(&name_key)
+            .or_else(|| email_key.as_ref().and_then(|email| emails.get(email)));
+
+        let action = if contact.name.trim().is_empty() {
+            "skip: no name".to_string()
+        } else if let Some(duplicate_of) = duplicate_of {
+            format!("skip: duplicate of {}", duplicate_of)
+        } else {
+            let label = format!("row {}", i + 1);
+            names.insert(name_key, label.clone());
+            if let Some(email) = email_key {
+                emails.insert(email, label);
+            }
+
+            if !args.dry_run {
+                // The client, its credit limit and company link are saved together
+                repo.create_client(
+                    &NewClient {
+                        name: contact.name.trim(),
+                        address: contact.address.as_deref(),
+                        email: contact.email.as_deref(),
+                        phone: contact.phone.as_deref(),
+                    },
+                    &[company.id],
+                    contact.credit_limit,
+                )?;
+            }
+            created += 1;
+            "create".to_string()
+        };
+
+        rows.push(vec![
+            (i + 1).to_string().cell(),
+            contact.name.clone().cell(),
+            contact.address.clone().unwrap_or_default().cell(),
+            contact.email.clone().unwrap_or_default().cell(),
+            contact.phone.clone().unwrap_or_default().cell(),
+            action.cell(),
+        ]);
+    }
+
+    let table = rows
+        .table()
+        .title(vec![
+            "Row".cell(),
+            "Name".cell(),
+            "Address".cell(),
+            "Email".cell(),
+            "Phone".cell(),
+            "Action".cell(),
+        ])
+        .bold(true);
+    print_stdout(table)?;
+
+    if args.dry_run {
+        println!(
+            "{}",
+            format!(
+                "Dry run: {} of {} clients would be added to {}",
+                created,
+                contacts.len(),
+                company.name
+            )
+            .yellow()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(
+                "Added {} of {} clients to {}",
+                created,
+                contacts.len(),
+                company.name
+            )
+            .green()
+        );
+    }
+
+    Ok(created)
+}
+
+/// Writes every client to a CSV or vCard file. Returns the number exported.
+pub fn export(
+    file: &str,
+    format: Option<ContactFormat>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let format = format.unwrap_or_else(|| ContactFormat::detect(file));
+
+    let conn = &mut *establish_connection()?;
+    let clients = clients::table
+        .order(clients::name.asc())
+        .load::<Client>(conn)?;
+
+    match format {
+        ContactFormat::Csv => {
+            let mut writer = csv::Writer::from_path(file)?;
+            writer.write_record(["id", "name", "address", "email", "phone", "credit_limit"])?;
+            for client in &clients {
+                writer.write_record([
+                    client.id.to_string(),
+                    client.name.clone(),
+                    client.address.clone().unwrap_or_default(),
+                    client.email.clone().unwrap_or_default(),
+                    client.phone.clone().unwrap_or_default(),
+                    client
+                        .credit_limit
+                        .map(|limit| limit.to_string())
+                        .unwrap_or_default(),
+                ])?;
+            }
+            writer.flush()?;
+        }
+        ContactFormat::Vcard => {
+            let mut out = String::new();
+            for client in &clients {
+                out.push_str("BEGIN:VCARD\r\nVERSION:3.0\r\n");
+                out.push_str(&format!("FN:{}\r\n", escape_vcard(&client.name)));
+                out.push_str(&format!("N:{};;;;\r\n", escape_vcard(&client.name)));
+                if let Some(address) = &client.address {
+                    out.push_str(&format!(
+                        "ADR;TYPE=WORK:;;{};;;;\r\n",
+                        escape_vcard(address)
+                    ));
+                }
+                if let Some(email) = &client.email {
+                    out.push_str(&format!("EMAIL;TYPE=INTERNET:{}\r\n", escape_vcard(email)));
+                }
+                if let Some(phone) = &client.phone {
+                    out.push_str(&format!("TEL;TYPE=WORK:{}\r\n", escape_vcard(phone)));
+                }
+                out.push_str("END:VCARD\r\n");
+            }
+            fs::write(file, out)?;
+        }
+    }
+
+    Ok(clients.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_vcard_reads_escapes_left_to_right() {
+        assert_eq!(
+            unescape_vcard(r"C:\\new\nline\, one\; two"),
+            "C:\\new\nline, one; two"
+        );
+        assert_eq!(unescape_vcard(&escape_vcard("a\\nb\nc")), "a\\nb\nc");
+    }
+}
@@ -0,0 +1,328 @@
+use crate::db::{establish_connection, repository, DbError};
+use crate::models::{CreditNote, CreditNoteItem, Invoice, Item, NewCreditNote, NewCreditNoteItem};
+use crate::pdf;
+use crate::schema::{credit_note_items, credit_notes, invoices, items};
+use chrono::NaiveDate;
+use cli_table::{print_stdout, Cell, Style, Table};
+use diesel::prelude::*;
+use serde_json::json;
+use std::collections::HashMap;
+use text_colorizer::*;
+
+pub struct CreditNoteArgs {
+    pub invoice_id: i32,
+    pub items: Option<Vec<String>>,
+    pub full: bool,
+    pub reason: Option<String>,
+}
+
+pub fn format_credit_note_number(id: i32) -> String {
+    format!("CN-{:05}", id)
+}
+
+/// Total credited against each invoice, as a positive amount.
+pub fn credited_by_invoice() -> Result<HashMap<i32, f64>, DbError> {
+    repository()?.credited_by_invoice()
+}
+
+pub fn create(args: CreditNoteArgs) -> Result<i32, Box<dyn std::error::Error>> {
+    let conn = &mut *establish_connection()?;
+
+    let invoice = invoices::table
+        .find(args.invoice_id)
+        .first::<Invoice>(conn)
+        .optional()?
+        .ok_or_else(|| format!("Invoice with id {} not found", args.invoice_id))?;
+    crate::invoice::check_not_regenerated(&invoice)?;
+
+    // Credit note lines always carry negative prices
+    let lines: Vec<pdf::Item> = if args.full {
+        items::table
+            .filter(items::invoice_id.eq(invoice.id))
+            .load::<Item>(conn)?
+            .into_iter()
+            .map(|item| pdf::Item {
+                description: item.description,
+                quantity: item.quantity as u32,
+                price: -item.unit_price.abs(),
+                tax_rate: item.tax_rate,
+            })
+            .collect()
+    } else {
+        args.items
+            .unwrap_or_default()
+            .iter()
+            .map(|item| {
+                serde_json::from_str::<pdf::Item>(item)
+                    .map(|item| pdf::Item {
+                        price: -item.price.abs(),
+                        ..item
+                    })
+                    .map_err(|e| format!("Error parsing item {}: {}", item, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    if lines.is_empty() {
+        return Err("Provide the items to credit or use --full to reverse the invoice".into());
+    }
+
+    let total_amount = pdf::get_total_amount(&lines);
+    let paid = crate::payment::paid_by_invoice()?
+        .get(&invoice.id)
+        .copied()
+        .unwrap_or_default();
+    let credited = credited_by_invoice()?
+        .get(&invoice.id)
+        .copied()
+        .unwrap_or_default();
+    check_credit(
+        &invoice,
+        -total_amount,
+        invoice.total_amount - paid - credited,
+    )?;
+
+    let date = chrono::offset::Local::now().date_naive();
+
+    // The credit note and its lines are saved together, then rendered under
+    // the number of the saved record
+    let credit_note = conn.transaction(|conn| {
+        let credit_note = diesel::insert_into(credit_notes::table)
+            .values(&NewCreditNote {
+                invoice_id: invoice.id,
+                date: date.format("%d %B %Y").to_string(),
+                reason: args.reason,
+                total_amount,
+            })
+            .returning(CreditNote::as_returning())
+            .get_result(conn)?;
+
+        for line in &lines {
+            diesel::insert_into(credit_note_items::table)
+                .values(&NewCreditNoteItem {
+                    credit_note_id: credit_note.id,
+                    description: line.description.clone(),
+                    quantity: line.quantity as i32,
+                    unit_price: line.price,
+                    total: line.net(),
+                    tax_rate: line.tax_rate,
+                })
+                .execute(conn)?;
+        }
+
+        Ok::<_, DbError>(credit_note)
+    })?;
+
+    print!("{}", "Generating credit note... \n".yellow());
+
+    if let Err(e) = render(&credit_note, &invoice, &lines, date) {
+        conn.transaction(|conn| {
+            diesel::delete(
+                credit_note_items::table
+                    .filter(credit_note_items::credit_note_id.eq(credit_note.id)),
+            )
+            .execute(conn)?;
+            diesel::delete(credit_notes::table.find(credit_note.id)).execute(conn)
+        })
+        .map_err(DbError::from)?;
+        return Err(e);
+    }
+
+    Ok(credit_note.id)
+}
+
+/// Refuses a credit of `amount` that is more than the `outstanding` balance,
+/// which is what is left after payments and earlier credit notes.
+fn check_credit(invoice: &Invoice, amount: f64, outstanding: f64) -> Result<(), String> {
+    // Anything under half a cent is float noise from the sums
+    if amount > outstanding + 0.005 {
+        return Err(format!(
+            "Credit of {:.2} exceeds the {:.2} still outstanding on invoice {}",
+            amount,
+            outstanding.max(0.0),
+            invoice.number()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Renders a saved credit note under its own number.
+fn render(
+    credit_note: &CreditNote,
+    invoice: &Invoice,
+    lines: &[pdf::Item],
+    date: NaiveDate,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = json!({
+        "document_title": "CREDIT NOTE",
+        "number_label": "Credit note #",
+        "invoice_number": format_credit_note_number(credit_note.id),
+        "reference": invoice.number(),
+        "created_date": credit_note.date,
+        "client_name": invoice.client_name,
+        "client_address": invoice.client_address.clone().unwrap_or_default(),
+        "client_email": invoice.client_email.clone().unwrap_or_default(),
+        "client_phone": invoice.client_phone.clone().unwrap_or_default(),
+        "company_name": invoice.company_name,
+        "company_address": invoice.company_address.clone().unwrap_or_default(),
+        "company_email": invoice.company_email.clone().unwrap_or_default(),
+        "company_phone": invoice.company_phone.clone().unwrap_or_default(),
+        "items": lines,
+        "total": credit_note.total_amount,
+        "logo_url": pdf::get_image_data_url()?,
+        "tax": format!("{:.2}", pdf::get_tax_amount(lines)),
+        "notes": credit_note.reason.clone().unwrap_or_default(),
+    });
+
+    pdf::render_document(data, format_credit_note_number(credit_note.id), date)
+}
+
+pub fn list(invoice_id: Option<i32>) -> Result<Vec<CreditNote>, DbError> {
+    let conn = &mut *establish_connection()?;
+
+    let mut query = credit_notes::table.into_boxed();
+
+    if let Some(invoice_id) = invoice_id {
+        query = query.filter(credit_notes::invoice_id.eq(invoice_id));
+    }
+
+    let list = query
+        .order(credit_notes::id.asc())
+        .load::<CreditNote>(conn)?;
+
+    credit_note_table(&list, &crate::invoice::numbers()?);
+
+    Ok(list)
+}
+
+pub fn show(id: i32) -> Result<(), DbError> {
+    let conn = &mut *establish_connection()?;
+
+    let credit_note = credit_notes::table
+        .find(id)
+        .first::<CreditNote>(conn)
+        .optional()?;
+
+    let Some(credit_note) = credit_note else {
+        println!("{} {}", "No credit note found using id:".red(), id);
+        return Ok(());
+    };
+
+    let lines = credit_note_items::table
+        .filter(credit_note_items::credit_note_id.eq(id))
+        .load::<CreditNoteItem>(conn)?;
+
+    credit_note_table(
+        std::slice::from_ref(&credit_note),
+        &crate::invoice::numbers()?,
+    );
+    crate::invoice::items_table(
+        &lines
+            .into_iter()
+            .map(|line| Item {
+                id: line.id,
+                invoice_id: credit_note.invoice_id,
+                description: line.description,
+                quantity: line.quantity,
+                unit_price: line.unit_price,
+                total: line.total,
+                tax_rate: line.tax_rate,
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    Ok(())
+}
+
+/// Prints credit notes with the number of the invoice each one credits,
+/// looked up in `invoice_numbers`.
+fn credit_note_table(credit_notes: &[CreditNote], invoice_numbers: &HashMap<i32, String>) {
+    let credit_notes = credit_notes
+        .iter()
+        .map(|credit_note| {
+            vec![
+                credit_note.id.to_string().cell(),
+                format_credit_note_number(credit_note.id).cell(),
+                invoice_numbers
+                    .get(&credit_note.invoice_id)
+                    .cloned()
+                    .unwrap_or_default()
+                    .cell(),
+                credit_note.date.clone().cell(),
+                format!("{:.2}", credit_note.total_amount).cell(),
+                credit_note.reason.clone().unwrap_or_default().cell(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let table = credit_notes
+        .table()
+        .title(vec![
+            "ID".cell(),
+            "Number".cell(),
+            "Invoice".cell(),
+            "Date".cell(),
+            "Total Amount".cell(),
+            "Reason".cell(),
+        ])
+        .bold(true);
+
+    print_stdout(table).expect("Error printing the table");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(regenerated: bool) -> Invoice {
+        Invoice {
+            id: 4,
+            company_id: Some(1),
+            company_name: "Acme".to_string(),
+            company_address: None,
+            company_email: None,
+            company_phone: None,
+            client_id: Some(3),
+            client_name: "Carol".to_string(),
+            client_address: None,
+            client_email: None,
+            client_phone: None,
+            date: "01 October 2026".to_string(),
+            total_amount: 100.0,
+            logo_url: None,
+            tax: None,
+            notes: None,
+            regenerated: Some(regenerated),
+            quote_id: None,
+            due_date: Some("2026-10-31".to_string()),
+            original_number: None,
+        }
+    }
+
+    #[test]
+    fn credits_up_to_the_outstanding_balance() {
+        let invoice = invoice(false);
+        assert!(check_credit(&invoice, 100.0, 100.0).is_ok());
+        // 100 invoiced, 60 paid and 15 credited before
+        assert!(check_credit(&invoice, 25.0, 100.0 - 60.0 - 15.0).is_ok());
+        assert!(check_credit(&invoice, 25.004, 25.0).is_ok());
+    }
+
+    #[test]
+    fn refuses_credits_over_the_balance_after_payments() {
+        let invoice = invoice(false);
+        let error = check_credit(&invoice, 30.0, 100.0 - 80.0).unwrap_err();
+        assert!(error.contains("Credit of 30.00 exceeds the 20.00 still outstanding"));
+        let error = check_credit(&invoice, 1.0, 100.0 - 100.0 - 5.0).unwrap_err();
+        assert!(error.contains("exceeds the 0.00 still outstanding on invoice 00004"));
+    }
+
+    #[test]
+    fn refuses_regenerated_copies() {
+        assert!(crate::invoice::check_not_regenerated(&invoice(false)).is_ok());
+        assert!(crate::invoice::check_not_regenerated(&invoice(true))
+            .unwrap_err()
+            .contains("regenerated copy"));
+    }
+}
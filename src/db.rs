@@ -1,18 +1,286 @@
-use crate::config;
+use crate::config::{self, ConfigError};
+use crate::repository::Repository;
+use diesel::connection::SimpleConnection;
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
-use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use text_colorizer::*;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
-pub fn establish_connection() -> SqliteConnection {
-    let config = config::load_config();
+/// Errors from the data layer.
+#[derive(Debug)]
+pub enum DbError {
+    /// config.json, which names the database, could not be loaded
+    Config(ConfigError),
+    /// The database could not be opened or configured
+    Connection(String),
+    /// Pending migrations could not be applied
+    Migration(String),
+    /// A record that was asked for by id does not exist
+    NotFound(String),
+    /// The database has migrations this binary doesn't know about
+    SchemaTooNew(String),
+    /// The feature only works with a SQLite database so far
+    SqliteOnly,
+    /// A stored record can't be used, such as a date in an unknown format
+    InvalidData(String),
+    Query(diesel::result::Error),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::Config(e) => write!(f, "{}", e),
+            DbError::Connection(e) => write!(f, "Error connecting to {}", e),
+            DbError::Migration(e) => write!(f, "Error running migrations: {}", e),
+            DbError::NotFound(what) => write!(f, "{} not found", what),
+            DbError::SchemaTooNew(version) => write!(
+                f,
+                "The database schema ({}) is newer than this version of cli-invoice supports; upgrade cli-invoice first",
+                version
+            ),
+            DbError::SqliteOnly => write!(
+                f,
+                "This command needs a SQLite database; PostgreSQL supports companies, clients, invoices and reports"
+            ),
+            DbError::InvalidData(e) => write!(f, "{}", e),
+            DbError::Query(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<ConfigError> for DbError {
+    fn from(e: ConfigError) -> Self {
+        DbError::Config(e)
+    }
+}
+
+impl From<diesel::result::Error> for DbError {
+    fn from(e: diesel::result::Error) -> Self {
+        DbError::Query(e)
+    }
+}
+
+// Most commands report errors as strings
+impl From<DbError> for String {
+    fn from(e: DbError) -> Self {
+        e.to_string()
+    }
+}
+
+/// The database behind a URL: PostgreSQL for `postgres://` and
+/// `postgresql://` URLs, otherwise a SQLite file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+impl Backend {
+    pub fn of(database_url: &str) -> Backend {
+        if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            Backend::Postgres
+        } else {
+            Backend::Sqlite
+        }
+    }
+}
+
+/// For the features that read the SQLite file directly, such as backups.
+pub fn require_sqlite(database_url: &str) -> Result<(), DbError> {
+    match Backend::of(database_url) {
+        Backend::Sqlite => Ok(()),
+        Backend::Postgres => Err(DbError::SqliteOnly),
+    }
+}
+
+/// Applied to every new SQLite connection. SQLite leaves foreign keys
+/// unenforced unless asked, WAL lets readers carry on while another process
+/// writes, and the busy timeout waits for that writer instead of failing
+/// straight away.
+const PRAGMAS: &str =
+    "PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;";
+
+/// Idle connections to the active profile's database, reused for the rest of
+/// the process.
+static SQLITE_POOL: Mutex<Vec<SqliteConnection>> = Mutex::new(Vec::new());
+static PG_POOL: Mutex<Vec<PgConnection>> = Mutex::new(Vec::new());
+
+/// The active profile's database URL, resolved from the config once and kept
+/// with the pools until [`reset_connections`].
+static DATABASE_URL: Mutex<Option<String>> = Mutex::new(None);
+
+/// Bumped by [`reset_connections`] so connections opened before a settings
+/// change are closed instead of returned to the pool.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// A connection type with its own pool.
+pub trait Pooled: Connection + Sized + 'static {
+    fn pool() -> &'static Mutex<Vec<Self>>;
+    fn open(database_url: &str) -> Result<Self, DbError>;
+}
+
+impl Pooled for SqliteConnection {
+    fn pool() -> &'static Mutex<Vec<Self>> {
+        &SQLITE_POOL
+    }
+
+    fn open(database_url: &str) -> Result<Self, DbError> {
+        let mut conn = SqliteConnection::establish(database_url)
+            .map_err(|e| DbError::Connection(format!("{}: {}", database_url, e)))?;
+        conn.batch_execute(PRAGMAS)?;
+
+        Ok(conn)
+    }
+}
+
+impl Pooled for PgConnection {
+    fn pool() -> &'static Mutex<Vec<Self>> {
+        &PG_POOL
+    }
+
+    fn open(database_url: &str) -> Result<Self, DbError> {
+        // The URL may hold a password, so only the error is shown
+        PgConnection::establish(database_url)
+            .map_err(|e| DbError::Connection(format!("PostgreSQL: {}", e)))
+    }
+}
+
+/// A connection borrowed from the pool; it goes back when dropped.
+pub struct PooledConnection<C: Pooled = SqliteConnection> {
+    conn: Option<C>,
+    generation: usize,
+}
+
+impl<C: Pooled> PooledConnection<C> {
+    fn take(database_url: &str) -> Result<Self, DbError> {
+        let generation = GENERATION.load(Ordering::SeqCst);
+
+        let conn = match C::pool().lock().unwrap().pop() {
+            Some(conn) => conn,
+            None => C::open(database_url)?,
+        };
+
+        Ok(PooledConnection {
+            conn: Some(conn),
+            generation,
+        })
+    }
+}
+
+impl<C: Pooled> Deref for PooledConnection<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.conn
+            .as_ref()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl<C: Pooled> DerefMut for PooledConnection<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.conn
+            .as_mut()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl<C: Pooled> Drop for PooledConnection<C> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            if self.generation == GENERATION.load(Ordering::SeqCst) {
+                C::pool().lock().unwrap().push(conn);
+            }
+        }
+    }
+}
+
+/// Connects to the active profile's SQLite database, reusing an idle
+/// connection when there is one. A new connection is only opened when none is
+/// free, such as the first call or a nested one.
+///
+/// Features built on [`repository`] work with either backend; the rest need
+/// SQLite and get [`DbError::SqliteOnly`] for a PostgreSQL URL.
+pub fn establish_connection() -> Result<PooledConnection, DbError> {
+    let database_url = database_url()?;
+    require_sqlite(&database_url)?;
+
+    PooledConnection::take(&database_url)
+}
 
-    SqliteConnection::establish(&config.database_url)
-        .unwrap_or_else(|_| panic!("{} {}", "Error connecting to".red(), config.database_url))
+/// A pooled connection to whichever backend the database URL names, used
+/// through [`Repository`].
+pub enum Repo {
+    Sqlite(PooledConnection<SqliteConnection>),
+    Postgres(PooledConnection<PgConnection>),
+}
+
+impl Deref for Repo {
+    type Target = dyn Repository;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Repo::Sqlite(conn) => &**conn,
+            Repo::Postgres(conn) => &**conn,
+        }
+    }
+}
+
+impl DerefMut for Repo {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Repo::Sqlite(conn) => &mut **conn,
+            Repo::Postgres(conn) => &mut **conn,
+        }
+    }
+}
+
+fn database_url() -> Result<String, DbError> {
+    let mut cached = DATABASE_URL.lock().unwrap();
+    if let Some(database_url) = cached.as_ref() {
+        return Ok(database_url.clone());
+    }
+
+    let database_url = config::load_config()?.database_url;
+    *cached = Some(database_url.clone());
+    Ok(database_url)
+}
+
+pub fn repository() -> Result<Repo, DbError> {
+    let database_url = database_url()?;
+
+    Ok(match Backend::of(&database_url) {
+        Backend::Sqlite => Repo::Sqlite(PooledConnection::take(&database_url)?),
+        Backend::Postgres => Repo::Postgres(PooledConnection::take(&database_url)?),
+    })
+}
+
+/// Closes pooled connections so the next one is opened with the current
+/// settings, after the database or profile changes.
+pub fn reset_connections() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    *DATABASE_URL.lock().unwrap() = None;
+    SQLITE_POOL.lock().unwrap().clear();
+    PG_POOL.lock().unwrap().clear();
 }
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+pub const PG_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations_postgres");
+
+/// A migration embedded in this binary, and whether the database has it.
+pub struct MigrationState {
+    pub version: String,
+    pub name: String,
+    pub applied: bool,
+}
 
-pub fn run_migration(conn: &mut SqliteConnection) {
-    conn.run_pending_migrations(MIGRATIONS).unwrap();
+/// Applies pending migrations and returns the names of those applied.
+pub fn run_migration(conn: &mut dyn Repository) -> Result<Vec<String>, DbError> {
+    conn.run_migrations()
 }
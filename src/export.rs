@@ -0,0 +1,831 @@
+use crate::config::{self, LedgerAccounts};
+use crate::credit_note::format_credit_note_number;
+use crate::models::{Invoice, Item};
+use crate::pdf;
+use crate::report::{self, DateRange, Ledger};
+use chrono::format::{Item as FormatItem, StrftimeItems};
+use chrono::NaiveDate;
+use indexmap::IndexMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum LedgerFormat {
+    #[default]
+    Hledger,
+    Ledger,
+    Beancount,
+}
+
+/// A balanced transaction. Amounts are in cents so postings always sum to zero.
+struct Transaction {
+    date: NaiveDate,
+    /// Stable id derived from the document number, e.g. `INV-00012`
+    id: String,
+    payee: String,
+    narration: String,
+    postings: Vec<(String, i64)>,
+}
+
+fn cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+fn format_cents(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+/// Receivable, income and tax postings for invoice or credit note lines given
+/// as `(description, net, tax rate)`. Credit note lines are negative, which
+/// reverses every posting.
+fn sales_postings(
+    accounts: &LedgerAccounts,
+    company_name: &str,
+    lines: Vec<(&str, f64, Option<f64>)>,
+) -> Vec<(String, i64)> {
+    let mut income: IndexMap<String, i64> = IndexMap::new();
+    let mut tax = 0.0;
+
+    for (description, net, tax_rate) in lines {
+        *income
+            .entry(accounts.income(company_name, description))
+            .or_default() -= cents(net);
+        tax += net * tax_rate.unwrap_or_default() / 100.0;
+    }
+
+    let tax = -cents(tax);
+    let receivable = -(income.values().sum::<i64>() + tax);
+
+    let mut postings = vec![(accounts.receivable(company_name), receivable)];
+    postings.extend(income.into_iter().filter(|(_, amount)| *amount != 0));
+    if tax != 0 {
+        postings.push((accounts.tax(company_name), tax));
+    }
+
+    postings
+}
+
+/// `INV-` and the invoice number, keeping only the characters beancount
+/// allows in a link so imported numbers such as `2024 / 17` still make a
+/// valid id. An imported number that already starts with `INV-` keeps it once.
+fn invoice_transaction_id(invoice: &Invoice) -> String {
+    let number = invoice.number();
+    let number = number.strip_prefix("INV-").unwrap_or(&number);
+    let number = number
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_/.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+
+    format!("INV-{}", number)
+}
+
+fn transactions(ledger: &Ledger, accounts: &LedgerAccounts, range: DateRange) -> Vec<Transaction> {
+    let mut transactions = Vec::new();
+
+    for (invoice, date) in ledger.invoices.iter().filter(|(_, d)| range.contains(*d)) {
+        let items = ledger.items.get(&invoice.id).cloned().unwrap_or_default();
+        let lines = if items.is_empty() {
+            vec![("", invoice.total_amount, None)]
+        } else {
+            items
+                .iter()
+                .map(|item| (item.description.as_str(), item.total, item.tax_rate))
+                .collect()
+        };
+
+        transactions.push(Transaction {
+            date: *date,
+            id: invoice_transaction_id(invoice),
+            payee: invoice.client_name.clone(),
+            narration: format!("Invoice {}", invoice.number()),
+            postings: sales_postings(accounts, &invoice.company_name, lines),
+        });
+    }
+
+    for (note, date) in ledger
+        .credit_notes
+        .iter()
+        .filter(|(_, d)| range.contains(*d))
+    {
+        let Some((invoice, _)) = ledger
+            .invoices
+            .iter()
+            .find(|(invoice, _)| invoice.id == note.invoice_id)
+        else {
+            continue;
+        };
+
+        let items = ledger
+            .credit_note_items
+            .get(&note.id)
+            .cloned()
+            .unwrap_or_default();
+        let lines = items
+            .iter()
+            .map(|item| (item.description.as_str(), item.total, item.tax_rate))
+            .collect();
+
+        transactions.push(Transaction {
+            date: *date,
+            id: format_credit_note_number(note.id),
+            payee: invoice.client_name.clone(),
+            narration: format!(
+                "Credit note {} for invoice {}",
+                format_credit_note_number(note.id),
+                invoice.number()
+            ),
+            postings: sales_postings(accounts, &invoice.company_name, lines),
+        });
+    }
+
+    for (payment, date) in ledger.payments.iter().filter(|(_, d)| range.contains(*d)) {
+        let Some((invoice, _)) = ledger
+            .invoices
+            .iter()
+            .find(|(invoice, _)| invoice.id == payment.invoice_id)
+        else {
+            continue;
+        };
+
+        let amount = cents(payment.amount);
+        transactions.push(Transaction {
+            date: *date,
+            id: format!("PAY-{:05}", payment.id),
+            payee: invoice.client_name.clone(),
+            narration: format!("Payment for invoice {}", invoice.number()),
+            postings: vec![
+                (accounts.bank(&invoice.company_name), amount),
+                (accounts.receivable(&invoice.company_name), -amount),
+            ],
+        });
+    }
+
+    // Sort by date, keeping invoices before credit notes before payments on a day
+    transactions.sort_by_key(|t| {
+        let kind = match t.id.split('-').next() {
+            Some("INV") => 0,
+            Some("CN") => 1,
+            _ => 2,
+        };
+        (t.date, kind, t.id.clone())
+    });
+
+    transactions
+}
+
+fn render_postings(out: &mut String, indent: &str, postings: &[(String, i64)], commodity: &str) {
+    let width = postings
+        .iter()
+        .map(|(account, _)| account.len())
+        .max()
+        .unwrap_or_default();
+
+    for (account, amount) in postings {
+        out.push_str(&format!(
+            "{}{:<width$}  {:>12} {}\n",
+            indent,
+            account,
+            format_cents(*amount),
+            commodity,
+            width = width
+        ));
+    }
+}
+
+fn render(
+    format: LedgerFormat,
+    transactions: &[Transaction],
+    commodity: &str,
+    open_accounts: bool,
+) -> String {
+    let mut out = String::new();
+
+    // Beancount rejects an account opened twice, so journals built up from
+    // several exports only want these once
+    if let (LedgerFormat::Beancount, true, Some(first)) =
+        (format, open_accounts, transactions.first())
+    {
+        let accounts = transactions
+            .iter()
+            .flat_map(|t| t.postings.iter().map(|(account, _)| account))
+            .collect::<BTreeSet<_>>();
+        for account in accounts {
+            out.push_str(&format!("{} open {}\n", first.date, account));
+        }
+        out.push('\n');
+    }
+
+    for t in transactions {
+        match format {
+            LedgerFormat::Hledger => {
+                out.push_str(&format!(
+                    "{} * ({}) {} | {}  ; txid:{}\n",
+                    t.date, t.id, t.payee, t.narration, t.id
+                ));
+                render_postings(&mut out, "    ", &t.postings, commodity);
+            }
+            LedgerFormat::Ledger => {
+                out.push_str(&format!("{} * ({}) {}\n", t.date, t.id, t.payee));
+                out.push_str(&format!("    ; {}\n", t.narration));
+                out.push_str(&format!("    ; txid: {}\n", t.id));
+                render_postings(&mut out, "    ", &t.postings, commodity);
+            }
+            LedgerFormat::Beancount => {
+                out.push_str(&format!(
+                    "{} * \"{}\" \"{}\" ^{}\n",
+                    t.date,
+                    t.payee.replace('"', "\\\""),
+                    t.narration.replace('"', "\\\""),
+                    t.id
+                ));
+                out.push_str(&format!("  txid: \"{}\"\n", t.id));
+                render_postings(&mut out, "  ", &t.postings, commodity);
+            }
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Exports invoices, credit notes and payments as plain-text accounting
+/// transactions. Ids derive from the document numbers, so re-exporting the same
+/// range produces identical output. Beancount `open` directives are only
+/// written with `open_accounts`. Returns the number of transactions.
+pub fn ledger(
+    format: LedgerFormat,
+    range: DateRange,
+    output: Option<PathBuf>,
+    open_accounts: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let accounts = config::load_config()?.ledger_accounts;
+    let ledger = Ledger::load()?;
+
+    let transactions = transactions(&ledger, &accounts, range);
+    let rendered = render(format, &transactions, &accounts.commodity, open_accounts);
+
+    match output {
+        Some(path) => fs::write(path, rendered)?,
+        None => print!("{}", rendered),
+    }
+
+    Ok(transactions.len())
+}
+
+pub struct CsvExportArgs {
+    pub range: DateRange,
+    /// Only export invoices from companies whose name contains this
+    pub company: Option<String>,
+    pub output_dir: PathBuf,
+    /// Write a single file with one row per item instead of two files
+    pub joined: bool,
+    pub delimiter: u8,
+    pub date_format: String,
+    /// Bundle the CSVs and invoice PDFs into this zip instead of writing files
+    pub zip: Option<PathBuf>,
+}
+
+/// `--delimiter` parser accepting a single ASCII character or `tab`.
+pub fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ if value.len() == 1 && value.is_ascii() => Ok(value.as_bytes()[0]),
+        _ => Err("Delimiter must be a single ASCII character or 'tab'".to_string()),
+    }
+}
+
+/// `--date-format` parser rejecting invalid strftime patterns.
+pub fn parse_date_format(value: &str) -> Result<String, String> {
+    if StrftimeItems::new(value).any(|item| matches!(item, FormatItem::Error)) {
+        return Err(format!("Invalid date format {}", value));
+    }
+    Ok(value.to_string())
+}
+
+const INVOICE_HEADERS: [&str; 25] = [
+    "id",
+    "original_number",
+    "company_id",
+    "company_name",
+    "company_address",
+    "company_email",
+    "company_phone",
+    "client_id",
+    "client_name",
+    "client_address",
+    "client_email",
+    "client_phone",
+    "date",
+    "due_date",
+    "currency",
+    "total_amount",
+    "tax",
+    "credited",
+    "paid",
+    "balance",
+    "status",
+    "notes",
+    "regenerated",
+    "quote_id",
+    "logo_url",
+];
+
+const ITEM_HEADERS: [&str; 8] = [
+    "id",
+    "invoice_id",
+    "description",
+    "quantity",
+    "unit_price",
+    "total",
+    "tax_rate",
+    "tax",
+];
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+fn invoice_status(total: f64, credited: f64, paid: f64, overdue: bool) -> &'static str {
+    if credited > 0.005 && credited >= total - 0.005 {
+        "credited"
+    } else if total - credited - paid <= 0.005 {
+        "paid"
+    } else if overdue {
+        "overdue"
+    } else if paid > 0.0 {
+        "partially paid"
+    } else {
+        "unpaid"
+    }
+}
+
+/// Settings shared by every exported invoice row.
+struct RowFormat<'a> {
+    currency: &'a str,
+    date_format: &'a str,
+    payment_terms_days: i64,
+    today: NaiveDate,
+}
+
+/// One invoice row. `ledger_totals` holds what was credited and paid, and is
+/// `None` for a regenerated copy, whose balance lives on the original.
+fn invoice_row(
+    invoice: &Invoice,
+    date: NaiveDate,
+    ledger_totals: Option<(f64, f64)>,
+    format: &RowFormat,
+) -> Vec<String> {
+    let due = report::due_date(invoice, date, format.payment_terms_days);
+    let (credited, paid, balance, status) = match ledger_totals {
+        Some((credited, paid)) => (
+            format!("{:.2}", credited),
+            format!("{:.2}", paid),
+            format!("{:.2}", invoice.total_amount - credited - paid),
+            invoice_status(invoice.total_amount, credited, paid, due < format.today),
+        ),
+        None => (String::new(), String::new(), String::new(), "regenerated"),
+    };
+
+    vec![
+        invoice.id.to_string(),
+        optional(&invoice.original_number),
+        optional(&invoice.company_id),
+        invoice.company_name.clone(),
+        optional(&invoice.company_address),
+        optional(&invoice.company_email),
+        optional(&invoice.company_phone),
+        optional(&invoice.client_id),
+        invoice.client_name.clone(),
+        optional(&invoice.client_address),
+        optional(&invoice.client_email),
+        optional(&invoice.client_phone),
+        date.format(format.date_format).to_string(),
+        due.format(format.date_format).to_string(),
+        format.currency.to_string(),
+        format!("{:.2}", invoice.total_amount),
+        invoice
+            .tax
+            .map(|tax| format!("{:.2}", tax))
+            .unwrap_or_default(),
+        credited,
+        paid,
+        balance,
+        status.to_string(),
+        optional(&invoice.notes),
+        optional(&invoice.regenerated),
+        optional(&invoice.quote_id),
+        optional(&invoice.logo_url),
+    ]
+}
+
+fn item_row(item: &Item) -> Vec<String> {
+    vec![
+        item.id.to_string(),
+        item.invoice_id.to_string(),
+        item.description.clone(),
+        item.quantity.to_string(),
+        format!("{:.2}", item.unit_price),
+        format!("{:.2}", item.total),
+        optional(&item.tax_rate),
+        format!(
+            "{:.2}",
+            item.total * item.tax_rate.unwrap_or_default() / 100.0
+        ),
+    ]
+}
+
+fn to_csv(
+    delimiter: u8,
+    headers: &[String],
+    rows: &[Vec<String>],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+
+    writer.write_record(headers)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+/// Exports invoices and their items as CSV, either as files in
+/// `output_dir` or bundled with the invoice PDFs into a zip. Returns the paths
+/// written.
+pub fn csv(args: CsvExportArgs) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let config = config::load_config()?;
+    let row_format = RowFormat {
+        currency: &config.ledger_accounts.commodity,
+        date_format: &args.date_format,
+        payment_terms_days: config.payment_terms_days,
+        today: chrono::offset::Local::now().date_naive(),
+    };
+    let ledger = Ledger::load()?;
+    let credited = ledger.credited(None);
+    let paid = ledger.paid(None);
+
+    // Regenerated copies are exported too, flagged and without a balance
+    let mut invoices = ledger
+        .invoices
+        .iter()
+        .chain(&ledger.regenerated)
+        .filter(|(_, date)| args.range.contains(*date))
+        .filter(|(invoice, _)| {
+            args.company.as_ref().is_none_or(|company| {
+                invoice
+                    .company_name
+                    .to_lowercase()
+                    .contains(&company.to_lowercase())
+            })
+        })
+        .collect::<Vec<_>>();
+    invoices.sort_by_key(|(invoice, _)| invoice.id);
+
+    let invoice_rows = invoices
+        .iter()
+        .map(|(invoice, date)| {
+            let totals = (invoice.regenerated != Some(true)).then(|| {
+                (
+                    credited.get(&invoice.id).copied().unwrap_or_default(),
+                    paid.get(&invoice.id).copied().unwrap_or_default(),
+                )
+            });
+            let row = invoice_row(invoice, *date, totals, &row_format);
+            let items = ledger.items.get(&invoice.id).cloned().unwrap_or_default();
+            (row, items)
+        })
+        .collect::<Vec<_>>();
+
+    let files: Vec<(&str, Vec<u8>)> = if args.joined {
+        let mut headers = INVOICE_HEADERS
+            .iter()
+            .map(|h| format!("invoice_{}", h))
+            .collect::<Vec<_>>();
+        headers.extend(
+            ITEM_HEADERS
+                .iter()
+                .filter(|h| **h != "invoice_id")
+                .map(|h| format!("item_{}", h)),
+        );
+
+        // Invoices without items (such as imported history) still get a row,
+        // with the item columns left empty
+        let no_items = vec![String::new(); ITEM_HEADERS.len() - 1];
+        let rows = invoice_rows
+            .iter()
+            .flat_map(|(invoice_row, items)| {
+                let item_rows = if items.is_empty() {
+                    vec![no_items.clone()]
+                } else {
+                    items
+                        .iter()
+                        .map(|item| {
+                            let mut item_row = item_row(item);
+                            item_row.remove(1);
+                            item_row
+                        })
+                        .collect()
+                };
+                item_rows.into_iter().map(move |item_row| {
+                    let mut row = invoice_row.clone();
+                    row.extend(item_row);
+                    row
+                })
+            })
+            .collect::<Vec<_>>();
+
+        vec![(
+            "invoice_items.csv",
+            to_csv(args.delimiter, &headers, &rows)?,
+        )]
+    } else {
+        let invoice_headers = INVOICE_HEADERS.map(String::from);
+        let item_headers = ITEM_HEADERS.map(String::from);
+        let invoices = invoice_rows
+            .iter()
+            .map(|(row, _)| row.clone())
+            .collect::<Vec<_>>();
+        let items = invoice_rows
+            .iter()
+            .flat_map(|(_, items)| items.iter().map(item_row))
+            .collect::<Vec<_>>();
+
+        vec![
+            (
+                "invoices.csv",
+                to_csv(args.delimiter, &invoice_headers, &invoices)?,
+            ),
+            ("items.csv", to_csv(args.delimiter, &item_headers, &items)?),
+        ]
+    };
+
+    let Some(zip_path) = args.zip else {
+        fs::create_dir_all(&args.output_dir)?;
+        let mut written = Vec::new();
+        for (name, data) in files {
+            let path = args.output_dir.join(name);
+            fs::write(&path, data)?;
+            written.push(path);
+        }
+        return Ok(written);
+    };
+
+    let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path)?);
+    let options = zip::write::FileOptions::default();
+
+    for (name, data) in files {
+        zip.start_file(name, options)?;
+        zip.write_all(&data)?;
+    }
+
+    let mut missing = Vec::new();
+    for (invoice, date) in &invoices {
+        let pdf_path = pdf::invoice_pdf_path(&config.invoice_path, invoice.id, *date);
+        match fs::read(&pdf_path) {
+            Ok(data) => {
+                let name = pdf_path
+                    .strip_prefix(&config.invoice_path)
+                    .unwrap_or(&pdf_path)
+                    .display()
+                    .to_string();
+                zip.start_file(format!("pdfs/{}", name), options)?;
+                zip.write_all(&data)?;
+            }
+            Err(_) => missing.push(invoice.number()),
+        }
+    }
+
+    zip.finish()?;
+
+    if !missing.is_empty() {
+        println!(
+            "No PDF found for invoice(s) {}; they are listed in the CSV only",
+            missing.join(", ")
+        );
+    }
+
+    Ok(vec![zip_path])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreditNote, CreditNoteItem, Payment};
+
+    fn invoice(id: i32) -> Invoice {
+        Invoice {
+            id,
+            company_id: Some(1),
+            company_name: "Acme".to_string(),
+            company_address: None,
+            company_email: None,
+            company_phone: None,
+            client_id: Some(3),
+            client_name: "Carol \"CJ\" Jones".to_string(),
+            client_address: None,
+            client_email: None,
+            client_phone: None,
+            date: "01 October 2026".to_string(),
+            total_amount: 120.0,
+            logo_url: None,
+            tax: Some(20.0),
+            notes: None,
+            regenerated: Some(false),
+            quote_id: None,
+            due_date: Some("2026-10-31".to_string()),
+            original_number: None,
+        }
+    }
+
+    fn ledger() -> Ledger {
+        let item = Item {
+            id: 1,
+            invoice_id: 12,
+            description: "Website".to_string(),
+            quantity: 1,
+            unit_price: 100.0,
+            total: 100.0,
+            tax_rate: Some(20.0),
+        };
+        let note = CreditNote {
+            id: 3,
+            invoice_id: 12,
+            date: "05 October 2026".to_string(),
+            reason: None,
+            total_amount: -12.0,
+        };
+        let note_item = CreditNoteItem {
+            id: 1,
+            credit_note_id: 3,
+            description: "Website".to_string(),
+            quantity: 1,
+            unit_price: -10.0,
+            total: -10.0,
+            tax_rate: Some(20.0),
+        };
+        let payment = Payment {
+            id: 7,
+            invoice_id: 12,
+            amount: 108.0,
+            date: "2026-10-05".to_string(),
+            method: None,
+            reference: None,
+            date_unknown: false,
+        };
+
+        Ledger::from_records(
+            vec![invoice(12)],
+            vec![note],
+            vec![payment],
+            vec![item],
+            vec![note_item],
+        )
+        .unwrap()
+    }
+
+    fn all_dates() -> DateRange {
+        DateRange {
+            from: None,
+            to: None,
+        }
+    }
+
+    #[test]
+    fn postings_balance_and_order_by_document() {
+        let transactions = transactions(&ledger(), &LedgerAccounts::default(), all_dates());
+
+        let ids = transactions
+            .iter()
+            .map(|t| t.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["INV-00012", "CN-00003", "PAY-00007"]);
+        for t in &transactions {
+            assert_eq!(t.postings.iter().map(|(_, cents)| cents).sum::<i64>(), 0);
+        }
+        assert_eq!(
+            transactions[0].postings,
+            vec![
+                ("Assets:Receivable".to_string(), 12000),
+                ("Income:Sales".to_string(), -10000),
+                ("Liabilities:Tax".to_string(), -2000),
+            ]
+        );
+        assert_eq!(
+            transactions[1].postings[0],
+            ("Assets:Receivable".to_string(), -1200)
+        );
+    }
+
+    #[test]
+    fn renders_each_journal_format() {
+        let transactions = transactions(&ledger(), &LedgerAccounts::default(), all_dates());
+
+        let hledger = render(LedgerFormat::Hledger, &transactions, "GBP", false);
+        assert!(hledger.starts_with(
+            "2026-10-01 * (INV-00012) Carol \"CJ\" Jones | Invoice 00012  ; txid:INV-00012\n"
+        ));
+        assert!(hledger.contains("    Assets:Receivable        120.00 GBP\n"));
+
+        let ledger_cli = render(LedgerFormat::Ledger, &transactions, "GBP", false);
+        assert!(ledger_cli.contains("2026-10-05 * (PAY-00007) Carol \"CJ\" Jones\n    ; Payment for invoice 00012\n    ; txid: PAY-00007\n"));
+
+        let beancount = render(LedgerFormat::Beancount, &transactions, "GBP", false);
+        assert!(beancount.starts_with(
+            "2026-10-01 * \"Carol \\\"CJ\\\" Jones\" \"Invoice 00012\" ^INV-00012\n  txid: \"INV-00012\"\n"
+        ));
+        assert!(!beancount.contains(" open "));
+    }
+
+    #[test]
+    fn opens_beancount_accounts_only_when_asked() {
+        let transactions = transactions(&ledger(), &LedgerAccounts::default(), all_dates());
+        let beancount = render(LedgerFormat::Beancount, &transactions, "GBP", true);
+
+        assert!(beancount.starts_with(
+            "2026-10-01 open Assets:Bank\n2026-10-01 open Assets:Receivable\n2026-10-01 open Income:Sales\n2026-10-01 open Liabilities:Tax\n\n"
+        ));
+        assert_eq!(beancount.matches(" open ").count(), 4);
+    }
+
+    #[test]
+    fn exports_only_the_requested_range() {
+        let range = DateRange {
+            from: NaiveDate::from_ymd_opt(2026, 10, 2),
+            to: None,
+        };
+        let transactions = transactions(&ledger(), &LedgerAccounts::default(), range);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].id, "CN-00003");
+    }
+
+    fn row_format() -> RowFormat<'static> {
+        RowFormat {
+            currency: "GBP",
+            date_format: "%d/%m/%Y",
+            payment_terms_days: 30,
+            today: NaiveDate::from_ymd_opt(2026, 11, 15).unwrap(),
+        }
+    }
+
+    #[test]
+    fn invoice_rows_have_every_column() {
+        let mut invoice = invoice(12);
+        invoice.original_number = Some("2024-017".to_string());
+        let date = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap();
+
+        let row = invoice_row(&invoice, date, Some((20.0, 40.0)), &row_format());
+        assert_eq!(row.len(), INVOICE_HEADERS.len());
+        let column = |name: &str| {
+            let index = INVOICE_HEADERS.iter().position(|h| *h == name).unwrap();
+            row[index].as_str()
+        };
+        assert_eq!(column("original_number"), "2024-017");
+        assert_eq!(column("date"), "01/10/2026");
+        assert_eq!(column("due_date"), "31/10/2026");
+        assert_eq!(column("balance"), "60.00");
+        assert_eq!(column("status"), "overdue");
+        assert_eq!(column("regenerated"), "false");
+    }
+
+    #[test]
+    fn regenerated_copies_have_no_balance() {
+        let mut copy = invoice(13);
+        copy.regenerated = Some(true);
+        let date = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap();
+
+        let row = invoice_row(&copy, date, None, &row_format());
+        assert_eq!(&row[17..21], ["", "", "", "regenerated"]);
+        assert_eq!(row[22], "true");
+
+        let ledger = Ledger::from_records(
+            vec![invoice(12), copy],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(ledger.invoices.len(), 1);
+        assert_eq!(ledger.regenerated[0].0.id, 13);
+    }
+
+    #[test]
+    fn statuses_follow_credits_and_payments() {
+        assert_eq!(invoice_status(100.0, 100.0, 0.0, true), "credited");
+        assert_eq!(invoice_status(100.0, 30.0, 70.0, true), "paid");
+        assert_eq!(invoice_status(100.0, 0.0, 99.999, true), "paid");
+        assert_eq!(invoice_status(100.0, 0.0, 10.0, true), "overdue");
+        assert_eq!(invoice_status(100.0, 0.0, 10.0, false), "partially paid");
+        assert_eq!(invoice_status(100.0, 0.0, 0.0, false), "unpaid");
+    }
+}
@@ -0,0 +1,605 @@
+use crate::config;
+use crate::db::establish_connection;
+use crate::models::{
+    Client, Company, NewCreditNote, NewCreditNoteItem, NewInvoice, NewItem, NewPayment,
+};
+use crate::pdf;
+use crate::schema::{
+    clients, companies, credit_note_items, credit_notes, invoices, items, payments,
+};
+use crate::utils::parse_date;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use text_colorizer::*;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum HistoryFormat {
+    Csv,
+    Json,
+}
+
+impl HistoryFormat {
+    /// Picks the format from the file extension, defaulting to CSV.
+    pub fn detect(path: &str) -> HistoryFormat {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => HistoryFormat::Json,
+            _ => HistoryFormat::Csv,
+        }
+    }
+}
+
+/// Numbers can be plain (`42`) or carry the other tool's prefix (`INV-2021-0042`).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum InvoiceNumber {
+    Number(i64),
+    Text(String),
+}
+
+impl InvoiceNumber {
+    fn label(&self) -> String {
+        match self {
+            InvoiceNumber::Number(number) => number.to_string(),
+            InvoiceNumber::Text(text) => text.trim().to_string(),
+        }
+    }
+}
+
+/// The running number at the end of an invoice number: 42 for `42`, `0042`
+/// or `INV-2021-0042`.
+fn sequence_number(number: &str) -> Option<i32> {
+    let prefix = number.trim_end_matches(|c: char| c.is_ascii_digit());
+    number[prefix.len()..].parse().ok()
+}
+
+#[derive(QueryableByName)]
+struct Sequence {
+    #[diesel(sql_type = Integer)]
+    seq: i32,
+}
+
+/// Moves the invoice id sequence past an imported number, so invoices issued
+/// here carry on from the other tool's numbering. Needs an invoice to have
+/// been inserted already, which creates the sequence.
+fn continue_numbering(
+    conn: &mut SqliteConnection,
+    number: &str,
+) -> Result<(), diesel::result::Error> {
+    if let Some(sequence) = sequence_number(number) {
+        diesel::sql_query("UPDATE sqlite_sequence SET seq = MAX(seq, ?) WHERE name = 'invoices'")
+            .bind::<Integer, _>(sequence)
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// The number the next invoice issued here will get: SQLite never reuses an
+/// id, so it follows the highest id handed out, including deleted ones.
+fn next_invoice_number(conn: &mut SqliteConnection) -> Result<i32, diesel::result::Error> {
+    let issued = diesel::sql_query("SELECT seq FROM sqlite_sequence WHERE name = 'invoices'")
+        .get_result::<Sequence>(conn)
+        .optional()?
+        .map_or(0, |sequence| sequence.seq);
+    let latest = invoices::table
+        .select(diesel::dsl::max(invoices::id))
+        .first::<Option<i32>>(conn)?
+        .unwrap_or(0);
+
+    Ok(issued.max(latest) + 1)
+}
+
+#[derive(Deserialize, Debug)]
+struct HistoryItem {
+    description: String,
+    #[serde(default = "default_quantity")]
+    quantity: u32,
+    #[serde(alias = "price")]
+    unit_price: f64,
+    tax_rate: Option<f64>,
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+/// An invoice as it appears in a JSON history file.
+#[derive(Deserialize, Debug)]
+struct HistoryInvoice {
+    number: InvoiceNumber,
+    date: String,
+    due_date: Option<String>,
+    client: String,
+    client_address: Option<String>,
+    client_email: Option<String>,
+    company: Option<String>,
+    total: Option<f64>,
+    tax: Option<f64>,
+    status: Option<String>,
+    paid: Option<f64>,
+    paid_date: Option<String>,
+    notes: Option<String>,
+    pdf: Option<String>,
+    #[serde(default)]
+    items: Vec<HistoryItem>,
+}
+
+/// One line of a CSV history file. Invoices with several items span several
+/// rows sharing the same number; invoice fields are read from the first one.
+#[derive(Deserialize, Debug)]
+struct HistoryRow {
+    number: InvoiceNumber,
+    date: String,
+    due_date: Option<String>,
+    client: String,
+    client_address: Option<String>,
+    client_email: Option<String>,
+    company: Option<String>,
+    total: Option<f64>,
+    tax: Option<f64>,
+    status: Option<String>,
+    paid: Option<f64>,
+    paid_date: Option<String>,
+    notes: Option<String>,
+    pdf: Option<String>,
+    description: Option<String>,
+    quantity: Option<u32>,
+    #[serde(alias = "price")]
+    unit_price: Option<f64>,
+    tax_rate: Option<f64>,
+}
+
+fn read_csv(file: &str) -> Result<Vec<HistoryInvoice>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(file)
+        .map_err(|e| format!("Unable to read {}: {}", file, e))?;
+
+    let mut invoices: Vec<HistoryInvoice> = Vec::new();
+    for (line, row) in reader.deserialize::<HistoryRow>().enumerate() {
+        let row = row.map_err(|e| format!("Unable to read row {}: {}", line + 2, e))?;
+
+        let item = match (row.description, row.unit_price) {
+            (Some(description), Some(unit_price)) if !description.is_empty() => Some(HistoryItem {
+                description,
+                quantity: row.quantity.unwrap_or(1),
+                unit_price,
+                tax_rate: row.tax_rate,
+            }),
+            _ => None,
+        };
+
+        match invoices
+            .last_mut()
+            .filter(|invoice| invoice.number.label() == row.number.label())
+        {
+            Some(invoice) => invoice.items.extend(item),
+            None => invoices.push(HistoryInvoice {
+                number: row.number,
+                date: row.date,
+                due_date: row.due_date,
+                client: row.client,
+                client_address: row.client_address,
+                client_email: row.client_email,
+                company: row.company,
+                total: row.total,
+                tax: row.tax,
+                status: row.status,
+                paid: row.paid,
+                paid_date: row.paid_date,
+                notes: row.notes,
+                pdf: row.pdf,
+                items: item.into_iter().collect(),
+            }),
+        }
+    }
+
+    Ok(invoices)
+}
+
+fn read_json(file: &str) -> Result<Vec<HistoryInvoice>, String> {
+    let content =
+        fs::read_to_string(file).map_err(|e| format!("Unable to read {}: {}", file, e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Unable to parse {}: {}", file, e))
+}
+
+/// How much of an imported invoice was settled, derived from its status and
+/// the optional `paid` amount.
+enum Settlement {
+    Open,
+    Paid(f64),
+    Void,
+}
+
+fn settlement(invoice: &HistoryInvoice, total: f64) -> Result<Settlement, String> {
+    let status = invoice
+        .status
+        .as_deref()
+        .map(|status| status.trim().to_lowercase())
+        .unwrap_or_default();
+
+    match status.as_str() {
+        "void" | "voided" | "cancelled" | "canceled" | "credited" => Ok(Settlement::Void),
+        "paid" => Ok(Settlement::Paid(invoice.paid.unwrap_or(total))),
+        "" | "unpaid" | "open" | "sent" | "draft" | "overdue" | "partially paid" | "partial" => {
+            Ok(match invoice.paid {
+                Some(paid) if paid > 0.0 => Settlement::Paid(paid),
+                _ => Settlement::Open,
+            })
+        }
+        other => Err(format!("unknown status {}", other)),
+    }
+}
+
+pub struct HistoryImportArgs {
+    pub file: String,
+    pub format: Option<HistoryFormat>,
+    pub pdf_dir: Option<PathBuf>,
+}
+
+/// Imports invoices from another tool with their dates, totals and statuses.
+/// They get new ids here; the other tool's number is kept in
+/// `original_number` and shown in place of the id, and invoices issued
+/// afterwards are numbered on from the highest imported one. No PDFs are
+/// rendered; existing ones can be copied in from a `pdf` column or
+/// `--pdf-dir`. Returns the number of invoices imported.
+pub fn import(args: HistoryImportArgs) -> Result<usize, Box<dyn std::error::Error>> {
+    let format = args
+        .format
+        .unwrap_or_else(|| HistoryFormat::detect(&args.file));
+    let history = match format {
+        HistoryFormat::Csv => read_csv(&args.file)?,
+        HistoryFormat::Json => read_json(&args.file)?,
+    };
+
+    let config = config::load_config()?;
+    let base_dir = Path::new(&args.file)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    let conn = &mut *establish_connection()?;
+
+    let clients = clients::table
+        .load::<Client>(conn)?
+        .into_iter()
+        .map(|client| (client.name.to_lowercase(), client))
+        .collect::<HashMap<_, _>>();
+    let companies = companies::table
+        .load::<Company>(conn)?
+        .into_iter()
+        .map(|company| (company.name.to_lowercase(), company))
+        .collect::<HashMap<_, _>>();
+    let default_company = Company::default_company()?;
+
+    // Numbers of invoices imported before, so running the same import twice
+    // doesn't duplicate them
+    let mut existing = invoices::table
+        .select(invoices::original_number)
+        .filter(invoices::original_number.is_not_null())
+        .load::<Option<String>>(conn)?
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>();
+    let mut imported = 0;
+    let mut skipped = 0;
+    let mut missing_pdfs = Vec::new();
+
+    for invoice in &history {
+        let number = invoice.number.label();
+        let skip = |reason: String| {
+            println!("{} {}: {}", "Skipping invoice".red(), number, reason);
+        };
+
+        if number.is_empty() {
+            skip("no invoice number".to_string());
+            skipped += 1;
+            continue;
+        }
+        if existing.contains(&number) {
+            skip("already imported".to_string());
+            skipped += 1;
+            continue;
+        }
+        let Some(date) = parse_date(&invoice.date) else {
+            skip(format!("invalid date {}", invoice.date));
+            skipped += 1;
+            continue;
+        };
+
+        let lines = invoice
+            .items
+            .iter()
+            .map(|item| pdf::Item {
+                description: item.description.clone(),
+                quantity: item.quantity,
+                price: item.unit_price,
+                tax_rate: item.tax_rate,
+            })
+            .collect::<Vec<_>>();
+
+        let total = invoice
+            .total
+            .unwrap_or_else(|| pdf::get_total_amount(&lines));
+        let tax = invoice.tax.unwrap_or_else(|| pdf::get_tax_amount(&lines));
+        let settlement = match settlement(invoice, total) {
+            Ok(settlement) => settlement,
+            Err(e) => {
+                skip(e);
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let due_date = invoice
+            .due_date
+            .as_deref()
+            .and_then(parse_date)
+            .unwrap_or(date + chrono::Duration::days(config.payment_terms_days));
+        let paid_date = invoice.paid_date.as_deref().and_then(parse_date);
+
+        let client = clients.get(&invoice.client.trim().to_lowercase());
+        let company = match &invoice.company {
+            Some(name) => companies.get(&name.trim().to_lowercase()),
+            None => Some(&default_company),
+        };
+
+        let id = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            let id = diesel::insert_into(invoices::table)
+                .values((
+                    invoices::original_number.eq(&number),
+                    &NewInvoice {
+                        company_id: company.map(|company| company.id),
+                        company_name: company
+                            .map(|company| company.name.clone())
+                            .or_else(|| invoice.company.clone())
+                            .unwrap_or_default(),
+                        company_address: company.and_then(|company| company.address.clone()),
+                        company_email: company.and_then(|company| company.email.clone()),
+                        company_phone: company.and_then(|company| company.phone.clone()),
+                        client_id: client.map(|client| client.id),
+                        client_name: invoice.client.trim().to_string(),
+                        client_address: invoice
+                            .client_address
+                            .clone()
+                            .or_else(|| client.and_then(|client| client.address.clone())),
+                        client_email: invoice
+                            .client_email
+                            .clone()
+                            .or_else(|| client.and_then(|client| client.email.clone())),
+                        client_phone: client.and_then(|client| client.phone.clone()),
+                        date: date.format("%d %B %Y").to_string(),
+                        total_amount: total,
+                        tax: Some(tax),
+                        notes: invoice.notes.clone(),
+                        regenerated: None,
+                        quote_id: None,
+                        due_date: Some(due_date.format("%Y-%m-%d").to_string()),
+                    },
+                ))
+                .returning(invoices::id)
+                .get_result::<i32>(conn)?;
+
+            continue_numbering(conn, &number)?;
+
+            for line in &lines {
+                diesel::insert_into(items::table)
+                    .values(&NewItem {
+                        invoice_id: id,
+                        description: line.description.clone(),
+                        quantity: line.quantity as i32,
+                        unit_price: line.price,
+                        total: line.net(),
+                        tax_rate: line.tax_rate,
+                    })
+                    .execute(conn)?;
+            }
+
+            match settlement {
+                Settlement::Open => {}
+                Settlement::Paid(amount) => {
+                    // Without a paid date the payment is booked on the due
+                    // date but kept out of days-to-pay
+                    diesel::insert_into(payments::table)
+                        .values((
+                            &NewPayment {
+                                invoice_id: id,
+                                amount,
+                                date: paid_date.unwrap_or(due_date).format("%Y-%m-%d").to_string(),
+                                method: None,
+                                reference: Some(format!("Imported {}", number)),
+                            },
+                            payments::date_unknown.eq(paid_date.is_none()),
+                        ))
+                        .execute(conn)?;
+                }
+                Settlement::Void => {
+                    // Voided invoices are reversed with a credit note, as they
+                    // would have been had they been issued here
+                    let credit_note_id = diesel::insert_into(credit_notes::table)
+                        .values(&NewCreditNote {
+                            invoice_id: id,
+                            date: date.format("%d %B %Y").to_string(),
+                            reason: Some(format!(
+                                "Imported as {}",
+                                invoice.status.as_deref().unwrap_or_default().trim()
+                            )),
+                            total_amount: -total,
+                        })
+                        .returning(credit_notes::id)
+                        .get_result::<i32>(conn)?;
+
+                    for line in &lines {
+                        diesel::insert_into(credit_note_items::table)
+                            .values(&NewCreditNoteItem {
+                                credit_note_id,
+                                description: line.description.clone(),
+                                quantity: line.quantity as i32,
+                                unit_price: -line.price,
+                                total: -line.net(),
+                                tax_rate: line.tax_rate,
+                            })
+                            .execute(conn)?;
+                    }
+                }
+            }
+
+            Ok(id)
+        })?;
+
+        existing.insert(number.clone());
+        imported += 1;
+
+        let source = invoice
+            .pdf
+            .as_ref()
+            .map(|pdf| args.pdf_dir.as_deref().unwrap_or(&base_dir).join(pdf))
+            .or_else(|| {
+                args.pdf_dir
+                    .as_ref()
+                    .map(|dir| dir.join(format!("{}.pdf", number)))
+            });
+        if let Some(source) = source {
+            let target = pdf::invoice_pdf_path(&config.invoice_path, id, date);
+            let copied = target
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::copy(&source, &target));
+            if copied.is_err() {
+                missing_pdfs.push(source.display().to_string());
+            }
+        }
+    }
+
+    if !missing_pdfs.is_empty() {
+        println!(
+            "{} {}",
+            "Could not attach PDF(s):".yellow(),
+            missing_pdfs.join(", ")
+        );
+    }
+
+    println!(
+        "{}",
+        format!(
+            "Imported {} invoice(s), skipped {}. The next invoice will be {:05}",
+            imported,
+            skipped,
+            next_invoice_number(conn)?
+        )
+        .green()
+    );
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_invoice(status: Option<&str>, paid: Option<f64>) -> HistoryInvoice {
+        HistoryInvoice {
+            number: InvoiceNumber::Text("INV-0042".to_string()),
+            date: "2024-01-05".to_string(),
+            due_date: None,
+            client: "Carol".to_string(),
+            client_address: None,
+            client_email: None,
+            company: None,
+            total: Some(100.0),
+            tax: None,
+            status: status.map(ToString::to_string),
+            paid,
+            paid_date: None,
+            notes: None,
+            pdf: None,
+            items: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn settles_from_status_and_paid_amount() {
+        let settle = |status, paid| settlement(&history_invoice(status, paid), 100.0);
+
+        assert!(matches!(settle(Some("Paid"), None), Ok(Settlement::Paid(p)) if p == 100.0));
+        assert!(matches!(settle(Some("paid"), Some(80.0)), Ok(Settlement::Paid(p)) if p == 80.0));
+        assert!(matches!(
+            settle(Some("partially paid"), Some(30.0)),
+            Ok(Settlement::Paid(p)) if p == 30.0
+        ));
+        assert!(matches!(
+            settle(Some("sent"), Some(0.0)),
+            Ok(Settlement::Open)
+        ));
+        assert!(matches!(settle(None, None), Ok(Settlement::Open)));
+        assert!(matches!(
+            settle(Some(" Cancelled "), None),
+            Ok(Settlement::Void)
+        ));
+        assert_eq!(
+            settle(Some("disputed"), None).err().as_deref(),
+            Some("unknown status disputed")
+        );
+    }
+
+    #[test]
+    fn reads_the_running_number_at_the_end() {
+        assert_eq!(sequence_number("42"), Some(42));
+        assert_eq!(sequence_number("0042"), Some(42));
+        assert_eq!(sequence_number("INV-2021-0042"), Some(42));
+        assert_eq!(sequence_number("2024/17"), Some(17));
+        assert_eq!(sequence_number("DRAFT"), None);
+        assert_eq!(sequence_number("99999999999"), None);
+    }
+
+    #[test]
+    fn numbering_continues_after_the_highest_imported_number() {
+        let conn = &mut SqliteConnection::establish(":memory:").unwrap();
+        diesel::sql_query("CREATE TABLE invoices (id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT)")
+            .execute(conn)
+            .unwrap();
+        assert_eq!(next_invoice_number(conn).unwrap(), 1);
+
+        let import = |conn: &mut SqliteConnection, number: &str| {
+            diesel::sql_query("INSERT INTO invoices DEFAULT VALUES")
+                .execute(conn)
+                .unwrap();
+            continue_numbering(conn, number).unwrap();
+        };
+        import(conn, "INV-2023-0117");
+        import(conn, "INV-2022-0009");
+        // The second import took id 118, after the first moved the sequence on
+        assert_eq!(next_invoice_number(conn).unwrap(), 119);
+
+        import(conn, "DRAFT");
+        assert_eq!(next_invoice_number(conn).unwrap(), 120);
+    }
+
+    #[test]
+    fn groups_csv_rows_into_invoices_by_number() {
+        let path = std::env::temp_dir().join(format!("history-{}.csv", std::process::id()));
+        fs::write(
+            &path,
+            "number,date,client,status,description,quantity,unit_price\n\
+             7,2024-01-05,Carol,paid,Design,2,50\n\
+             7,2024-01-05,Carol,paid,Hosting,1,20\n\
+             INV-8,2024-02-05,Dan,,,,\n",
+        )
+        .unwrap();
+
+        let invoices = read_csv(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(invoices.len(), 2);
+        assert_eq!(invoices[0].number.label(), "7");
+        assert_eq!(invoices[0].items.len(), 2);
+        assert_eq!(invoices[0].items[0].quantity, 2);
+        assert_eq!(invoices[1].number.label(), "INV-8");
+        assert!(invoices[1].items.is_empty());
+    }
+}
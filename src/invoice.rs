@@ -1,24 +1,27 @@
-use crate::db::establish_connection;
-use crate::models::{Invoice, Item, NewInvoice, NewItem};
+use crate::db::{repository, DbError};
+use crate::models::{Invoice, Item};
 use crate::pdf::CompanyPdf;
-use crate::schema::{invoices, items};
+use crate::repository::InvoiceFilter;
+use crate::service::GeneratedInvoice;
 use crate::utils::get_input;
-use crate::{pdf, InvoiceGenerateArgs};
+use crate::{pdf, service};
 use cli_table::{print_stdout, Cell, Style, Table};
-use diesel::prelude::*;
-use diesel::RunQueryDsl;
+use serde::Serialize;
+use std::collections::HashMap;
 use text_colorizer::*;
 
-#[derive(Clone, Debug)]
-pub struct InvoiceArgs {
-    pub company: Option<String>,
-    pub client: Option<String>,
-    pub item: Option<Vec<String>>,
-    pub notes: Option<String>,
-    pub custom: Option<bool>,
+impl Invoice {
+    /// The number shown for the invoice: the other tool's number for
+    /// imported history, otherwise the zero-padded id.
+    pub fn number(&self) -> String {
+        match &self.original_number {
+            Some(number) => number.clone(),
+            None => format!("{:05}", self.id),
+        }
+    }
 }
 
-fn collect_items(used_args: bool) -> Vec<String> {
+pub fn collect_items(used_args: bool) -> Vec<String> {
     if used_args {
         println!("{}", "Items cannot be empty".red());
     }
@@ -68,21 +71,89 @@ fn collect_items(used_args: bool) -> Vec<String> {
             }
         };
 
-        let item = format!(
-            "{{\"description\": \"{}\",\"quantity\": {}, \"price\": {}}}",
-            item_name, item_quantity, item_price
-        );
+        let item_tax_rate = loop {
+            let tax_rate = get_input(&format!(
+                "Enter item tax rate %: {}:",
+                "(leave empty if exempt)".bright_black()
+            ));
+
+            if tax_rate.trim().is_empty() {
+                break None;
+            }
+
+            match tax_rate.trim().parse::<f64>() {
+                Ok(tax_rate) if tax_rate >= 0.0 => break Some(tax_rate),
+                _ => {
+                    println!("{}", "Please enter a valid tax rate".red());
+                    continue;
+                }
+            }
+        };
+
+        let item = match item_tax_rate {
+            Some(tax_rate) => format!(
+                "{{\"description\": \"{}\",\"quantity\": {}, \"price\": {}, \"tax_rate\": {}}}",
+                item_name, item_quantity, item_price, tax_rate
+            ),
+            None => format!(
+                "{{\"description\": \"{}\",\"quantity\": {}, \"price\": {}}}",
+                item_name, item_quantity, item_price
+            ),
+        };
         items.push(item);
     }
 
     items
 }
 
+#[derive(clap::Args)]
+pub struct InvoiceGenerateArgs {
+    /// The name of the company
+    #[arg(long)]
+    pub company_name: Option<String>,
+
+    #[arg(long)]
+    pub company_address: Option<String>,
+
+    #[arg(long)]
+    pub company_email: Option<String>,
+
+    #[arg(long)]
+    pub company_number: Option<String>,
+
+    /// The name of the client
+    #[arg(long)]
+    pub client_name: Option<String>,
+
+    #[arg(long)]
+    pub client_address: Option<String>,
+
+    #[arg(long)]
+    pub client_email: Option<String>,
+
+    #[arg(long)]
+    pub client_number: Option<String>,
+
+    /// The items for the invoice in JSON format
+    #[arg(long)]
+    pub item: Option<Vec<String>>,
+
+    #[arg(long)]
+    pub notes: Option<String>,
+
+    #[arg(long, default_value = "false")]
+    pub custom: Option<bool>,
+
+    /// Refuse to invoice a client over their credit limit or with long overdue invoices
+    #[arg(long)]
+    pub strict: bool,
+}
+
 pub fn generate_invoice(
     mut args: InvoiceGenerateArgs,
     used_args: bool,
     custom: bool,
-) -> Result<(), String> {
+) -> Result<GeneratedInvoice, String> {
     if args.company_name.is_none() && args.client_name.is_none() {
         let company_name = get_input(&format!(
             "Enter the company name: {}:",
@@ -184,158 +255,89 @@ pub fn generate_invoice(
         }
     }
 
-    let items_result: Result<Vec<pdf::Item>, _> = args
-        .item
-        .unwrap()
-        .iter()
-        .map(|item| {
-            serde_json::from_str(item).map_err(|e| {
-                format!(
-                    "{} parsing item: {}. Check your input and try again. \n{}",
-                    "Error".red(),
-                    item.blue(),
-                    format!("Error: {}", e).red()
-                )
-            })
-        })
-        .collect();
-
-    match items_result {
-        Ok(items) => {
-            let company = args.company_name;
-
-            let company = CompanyPdf {
-                name: company,
-                address: args.company_address,
-                email: args.company_email,
-                phone: args.company_number,
-            };
-            let client = args
-                .client_name
-                .expect("Client name should have been initialized by now");
-
-            let client = pdf::ClientPdf {
-                name: client,
-                address: args.client_address,
-                email: args.client_email,
-                phone: args.client_number,
-            };
-
-            let custom = args.custom.unwrap_or_default();
-
-            match pdf::generate_pdf(company, client, items, args.notes, custom, false) {
-                Ok(_) => Ok(()),
-                Err(e) => {
-                    println!("Error: {}", e);
-                    Err(e.to_string())
-                }
-            }
-        }
-        Err(e) => {
-            println!("An error occurred: {}", e);
-            Err(e)
-        }
-    }
-}
+    let items = parse_items(&args.item.unwrap_or_default())?;
+    let client = args.client_name.unwrap_or_default();
 
-pub fn create_invoice_with_items(new_invoice: &NewInvoice, items: Vec<NewItem>) {
-    let conn = &mut establish_connection();
-
-    diesel::insert_into(invoices::table)
-        .values(new_invoice)
-        .execute(conn)
-        .expect("Error saving new client");
+    if !args.custom.unwrap_or_default() {
+        return generate_for_client(args.company_name, client, items, args.notes, args.strict);
+    }
 
-    let invoice_id = latest_id();
+    let company = CompanyPdf {
+        id: None,
+        name: args.company_name,
+        address: args.company_address,
+        email: args.company_email,
+        phone: args.company_number,
+    };
+    let client = pdf::ClientPdf {
+        id: None,
+        name: client,
+        address: args.client_address,
+        email: args.client_email,
+        phone: args.client_number,
+    };
 
-    for mut item in items {
-        item.invoice_id = invoice_id;
-        diesel::insert_into(items::table)
-            .values(&item)
-            .execute(conn)
-            .expect("Error saving new item");
-    }
+    pdf::generate_pdf(company, client, items, args.notes, false, None).map_err(|e| e.to_string())
 }
 
-pub fn latest_id() -> i32 {
-    let conn = &mut establish_connection();
-
-    match invoices::table
-        .order(invoices::id.desc())
-        .select(invoices::id)
-        .first::<i32>(conn)
-    {
-        Ok(id) => id,
-        Err(diesel::result::Error::NotFound) => 0,
-        Err(e) => panic!("Error retrieving latest ID: {:?}", e),
-    }
+/// Invoices a saved client from a saved company, picking each by name.
+fn generate_for_client(
+    company_name: Option<String>,
+    client_name: String,
+    items: Vec<pdf::Item>,
+    notes: Option<String>,
+    strict: bool,
+) -> Result<GeneratedInvoice, String> {
+    let company = pdf::get_company(company_name).map_err(|e| e.to_string())?;
+    let client = pdf::get_client(client_name).map_err(|e| e.to_string())?;
+
+    service::generate_invoice(service::InvoiceRequest {
+        company_id: Some(company.id),
+        client_id: client.id,
+        items,
+        notes,
+        date: None,
+        strict,
+    })
+    .map_err(|e| e.to_string())
 }
 
-pub enum InvoiceSearchField {
-    Id,
-    Client,
+pub fn latest_id() -> Result<i32, DbError> {
+    repository()?.latest_invoice_id()
 }
 
-pub fn search_by(field: InvoiceSearchField, search_value: &str, print_table: Option<bool>) {
-    let conn = &mut establish_connection();
-
-    let mut query = invoices::table.into_boxed();
-
-    query = match field {
-        InvoiceSearchField::Id => {
-            query.filter(invoices::id.eq(search_value.parse::<i32>().unwrap_or(-1)))
-        }
-        InvoiceSearchField::Client => query.filter(invoices::client_name.eq(search_value)),
-    };
-
-    let invoice_results = query
-        .select(invoices::all_columns)
-        .load(conn)
-        .expect("Error loading clients with companies");
+/// The displayed number of every invoice, keyed by id.
+pub fn numbers() -> Result<HashMap<i32, String>, DbError> {
+    Ok(repository()?
+        .list_invoices(&InvoiceFilter::default())?
+        .iter()
+        .map(|invoice| (invoice.id, invoice.number()))
+        .collect())
+}
 
-    if invoice_results.is_empty() {
-        let query_type = match field {
-            InvoiceSearchField::Id => "id".red(),
-            InvoiceSearchField::Client => "client".red(),
-        };
-        println!(
-            "{} {}{} {}",
-            "No invoice found using".red(),
-            query_type,
-            ":".red(),
-            search_value
-        );
-        return;
+/// Refuses a regenerated copy where an issued invoice is needed. Copies are
+/// left out of balances and don't link back to their original, so anything
+/// recorded against one would never settle the original.
+pub fn check_not_regenerated(invoice: &Invoice) -> Result<(), String> {
+    if invoice.regenerated == Some(true) {
+        return Err(format!(
+            "Invoice {} is a regenerated copy; use the id of the original invoice",
+            invoice.number()
+        ));
     }
 
-    if print_table.unwrap_or(true) {
-        invoice_table(&invoice_results);
-
-        if invoice_results.len() == 1 {
-            // After printing the invoice, we need to fetch and print its items
-            let invoice_id = invoice_results.first().map(|i| i.id).unwrap_or_default();
-            let item_results = items::table
-                .filter(items::invoice_id.eq(invoice_id))
-                .load::<Item>(conn)
-                .expect("Error loading items for the invoice");
-
-            items_table(&item_results);
-        }
-    }
+    Ok(())
 }
 
-pub fn regenerate(id: i32) -> Result<(), Box<dyn std::error::Error>> {
-    let conn = &mut establish_connection();
+/// Loads an invoice together with its items, formatted for `pdf::generate_pdf`.
+pub fn load_with_items(id: i32) -> Result<(Invoice, Vec<pdf::Item>), DbError> {
+    let repo = &mut *repository()?;
 
-    let invoice = invoices::table
-        .find(id)
-        .first::<Invoice>(conn)
-        .expect("Error loading invoice");
+    let invoice = repo
+        .find_invoice(id)?
+        .ok_or_else(|| DbError::NotFound(format!("Invoice with id {}", id)))?;
 
-    let items = items::table
-        .filter(items::invoice_id.eq(id))
-        .load::<Item>(conn)
-        .expect("Error loading items for the invoice");
+    let items = repo.invoice_items(Some(id))?;
 
     // format items std::vec::Vec<pfd::Item>
     let items = items
@@ -343,43 +345,193 @@ pub fn regenerate(id: i32) -> Result<(), Box<dyn std::error::Error>> {
         .map(|item| pdf::Item {
             description: item.description.clone(),
             quantity: item.quantity as u32,
-            price: item.unit_price as f64,
+            price: item.unit_price,
+            tax_rate: item.tax_rate,
         })
         .collect::<Vec<_>>();
 
+    Ok((invoice, items))
+}
+
+/// Issues a fresh copy of an invoice, marked as regenerated.
+pub fn regenerate(id: i32) -> Result<GeneratedInvoice, Box<dyn std::error::Error>> {
+    let (invoice, items) = load_with_items(id)?;
+
     let company = CompanyPdf {
+        id: invoice.company_id,
         name: Some(invoice.company_name),
         address: invoice.company_address,
         email: invoice.company_email,
         phone: invoice.company_phone,
     };
     let client = pdf::ClientPdf {
+        id: invoice.client_id,
         name: invoice.client_name,
         address: invoice.client_address,
         email: invoice.client_email,
         phone: invoice.client_phone,
     };
 
-    match pdf::generate_pdf(company, client, items, invoice.notes, true, true) {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            println!("Error: {}", e);
+    Ok(pdf::generate_pdf(
+        company,
+        client,
+        items,
+        invoice.notes,
+        true,
+        None,
+    )?)
+}
+
+pub struct CloneArgs {
+    pub client_name: Option<String>,
+    pub items: Option<Vec<String>>,
+    pub notes: Option<String>,
+    pub interactive: bool,
+}
+
+/// Issues a new invoice, numbered and dated today, from a copy of an existing one.
+pub fn clone_invoice(
+    id: i32,
+    args: CloneArgs,
+) -> Result<GeneratedInvoice, Box<dyn std::error::Error>> {
+    let (invoice, mut items) = load_with_items(id)?;
+
+    let mut client = pdf::ClientPdf {
+        id: invoice.client_id,
+        name: invoice.client_name,
+        address: invoice.client_address,
+        email: invoice.client_email,
+        phone: invoice.client_phone,
+    };
 
-            Err(format!("{}", e).into())
+    let client_name = args.client_name.or_else(|| {
+        if !args.interactive {
+            return None;
         }
+        let client_name = get_input(&format!(
+            "Enter the client name: {}:",
+            format!("(leave empty to keep {})", client.name).bright_black()
+        ));
+        (!client_name.trim().is_empty()).then_some(client_name)
+    });
+
+    if let Some(client_name) = client_name {
+        let found = pdf::get_client(client_name)?;
+        client = pdf::ClientPdf {
+            id: Some(found.id),
+            name: found.name,
+            address: found.address,
+            email: found.email,
+            phone: found.phone,
+        };
+    }
+
+    if let Some(new_items) = args.items {
+        items = parse_items(&new_items)?;
+    } else if args.interactive {
+        println!("{}", "Items on the original invoice:".green());
+        for item in &items {
+            println!("  {} x{} @ {}", item.description, item.quantity, item.price);
+        }
+        let keep = get_input(&format!("Keep these items? {}:", "(Y/n)".bright_black()));
+        if keep.eq_ignore_ascii_case("n") {
+            items = parse_items(&collect_items(false))?;
+        }
+    }
+
+    let notes = match args.notes {
+        Some(notes) => Some(notes),
+        None if args.interactive => {
+            let notes = get_input(&format!(
+                "Enter notes for the invoice: {}:",
+                "(leave empty to keep the original)".bright_black()
+            ));
+            if notes.trim().is_empty() {
+                invoice.notes
+            } else {
+                Some(notes)
+            }
+        }
+        None => invoice.notes,
+    };
+
+    if items.is_empty() {
+        return Err("An invoice needs at least one item".into());
     }
+
+    let company = CompanyPdf {
+        id: invoice.company_id,
+        name: Some(invoice.company_name),
+        address: invoice.company_address,
+        email: invoice.company_email,
+        phone: invoice.company_phone,
+    };
+
+    Ok(pdf::generate_pdf(
+        company, client, items, notes, false, None,
+    )?)
 }
 
-fn invoice_table(invoices: &[Invoice]) {
-    let invoices = invoices
+fn parse_items(items: &[String]) -> Result<Vec<pdf::Item>, String> {
+    items
         .iter()
+        .map(|item| {
+            serde_json::from_str(item).map_err(|e| {
+                format!(
+                    "Error parsing item {}: {}. Check your input and try again.",
+                    item, e
+                )
+            })
+        })
+        .collect()
+}
+
+/// An invoice with what has been credited and paid against it.
+#[derive(Serialize, Debug, Clone)]
+pub struct InvoiceSummary {
+    pub number: String,
+    #[serde(flatten)]
+    pub invoice: Invoice,
+    pub credited: f64,
+    pub paid: f64,
+    pub balance: f64,
+}
+
+pub fn summarize(invoices: Vec<Invoice>) -> Result<Vec<InvoiceSummary>, DbError> {
+    let credited = crate::credit_note::credited_by_invoice()?;
+    let paid = crate::payment::paid_by_invoice()?;
+
+    Ok(invoices
+        .into_iter()
         .map(|invoice| {
+            let credited = credited.get(&invoice.id).copied().unwrap_or_default();
+            let paid = paid.get(&invoice.id).copied().unwrap_or_default();
+            InvoiceSummary {
+                number: invoice.number(),
+                balance: invoice.total_amount - credited - paid,
+                invoice,
+                credited,
+                paid,
+            }
+        })
+        .collect())
+}
+
+pub fn summary_table(summaries: &[InvoiceSummary]) {
+    let invoices = summaries
+        .iter()
+        .map(|summary| {
+            let invoice = &summary.invoice;
             vec![
                 invoice.id.to_string().cell(),
+                summary.number.clone().cell(),
                 invoice.client_name.clone().cell(),
                 invoice.company_name.clone().cell(),
                 invoice.date.clone().cell(),
                 invoice.total_amount.to_string().cell(),
+                format!("{:.2}", summary.credited).cell(),
+                format!("{:.2}", summary.paid).cell(),
+                format!("{:.2}", summary.balance).cell(),
                 invoice.notes.clone().unwrap_or_default().cell(),
             ]
         })
@@ -389,10 +541,14 @@ fn invoice_table(invoices: &[Invoice]) {
         .table()
         .title(vec![
             "ID".cell(),
+            "Number".cell(),
             "Client Name".cell(),
             "Company Name".cell(),
             "Date".cell(),
             "Total Amount".cell(),
+            "Credited".cell(),
+            "Paid".cell(),
+            "Balance".cell(),
             "Notes".cell(),
         ])
         .bold(true);
@@ -401,7 +557,7 @@ fn invoice_table(invoices: &[Invoice]) {
     print_stdout(table).expect("Error printing the table");
 }
 
-fn items_table(items: &[Item]) {
+pub fn items_table(items: &[Item]) {
     let items = items
         .iter()
         .map(|item| {
@@ -410,6 +566,10 @@ fn items_table(items: &[Item]) {
                 item.description.clone().cell(),
                 item.quantity.to_string().cell(),
                 item.unit_price.to_string().cell(),
+                item.tax_rate
+                    .map(|rate| format!("{}%", rate))
+                    .unwrap_or_else(|| "Exempt".to_string())
+                    .cell(),
                 format!("{:.2}", item.total).cell(),
             ]
         })
@@ -422,6 +582,7 @@ fn items_table(items: &[Item]) {
             "Description".cell(),
             "Quantity".cell(),
             "Unit Price".cell(),
+            "Tax".cell(),
             "Total".cell(),
         ])
         .bold(true);
@@ -433,17 +594,3 @@ fn items_table(items: &[Item]) {
     // Print the table
     print_stdout(table).expect("Error printing the table");
 }
-
-pub fn list_invoices() -> Vec<Invoice> {
-    use crate::schema::invoices::dsl::*;
-
-    let conn = &mut establish_connection();
-
-    let list = invoices
-        .load::<Invoice>(conn)
-        .expect("Error loading invoices");
-
-    invoice_table(&list);
-
-    list
-}
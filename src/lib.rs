@@ -0,0 +1,41 @@
+//! Invoicing for companies and their clients, backed by SQLite or PostgreSQL.
+//!
+//! [`service`] is the API for embedding: it returns data and typed errors and
+//! never prints or prompts. The other modules also drive the `cli-invoice`
+//! binary and may print tables or ask for input.
+//!
+//! ```no_run
+//! use clinvoice::{config, service};
+//!
+//! config::init(None)?;
+//! let invoices = service::list_invoices(&service::InvoiceFilter::default())?;
+//! let pdf = service::render_invoice(invoices[0].invoice.id)?;
+//! # Ok::<(), service::Error>(())
+//! ```
+
+pub mod backup;
+pub mod client;
+pub mod company;
+pub mod company_clients;
+pub mod config;
+pub mod contacts;
+pub mod credit_note;
+pub mod db;
+pub mod export;
+pub mod history;
+pub mod invoice;
+pub mod migrate;
+pub mod models;
+pub mod payment;
+pub mod pdf;
+pub mod profile;
+pub mod quote;
+pub mod recurring;
+pub mod report;
+pub mod repository;
+pub mod schema;
+pub mod server;
+pub mod service;
+pub mod statement;
+pub mod time_entry;
+pub mod utils;
@@ -1,20 +1,11 @@
-mod client;
-mod company;
-mod company_clients;
-mod config;
-mod db;
-mod invoice;
-mod models;
-mod pdf;
-mod schema;
-mod utils;
-
 use clap::{Args, Parser, Subcommand};
-use invoice::generate_invoice;
+use clinvoice::invoice::{generate_invoice, InvoiceGenerateArgs};
+use clinvoice::{
+    backup, client, company, config, contacts, credit_note, export, history, invoice, migrate,
+    models, payment, profile, quote, recurring, report, server, service, statement, time_entry,
+};
 use text_colorizer::*;
 
-use crate::invoice::InvoiceSearchField;
-
 #[derive(Parser)]
 #[command(name = "CLInvoice")]
 #[command(author = "David Hough")]
@@ -24,6 +15,24 @@ use crate::invoice::InvoiceSearchField;
     help_template = "{author} {about-section}Version: {version} \n\n {usage-heading} {usage} \n\n {all-args} \n {tab}"
 )]
 struct Cli {
+    /// Path to the config file (defaults to CLINVOICE_CONFIG, then the platform config directory)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+    /// Profile to use instead of the active one
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// SQLite file or postgres:// URL to use (overrides CLINVOICE_DATABASE_URL and the config)
+    #[arg(long, global = true, value_name = "URL")]
+    database_url: Option<String>,
+    /// Directory for invoice PDFs (overrides CLINVOICE_INVOICE_PATH and the config)
+    #[arg(long, global = true, value_name = "PATH")]
+    invoice_path: Option<String>,
+    /// Logo to use (overrides CLINVOICE_LOGO_PATH and the config)
+    #[arg(long, global = true, value_name = "PATH")]
+    logo_path: Option<String>,
+    /// Id of the company to invoice from (overrides CLINVOICE_DEFAULT_COMPANY and the config)
+    #[arg(long, global = true, value_name = "ID")]
+    default_company: Option<i32>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -31,7 +40,13 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Manage configuration
-    Setup,
+    Setup(SetupArgs),
+    #[command(subcommand)]
+    /// View and change settings
+    Config(ConfigCommands),
+    #[command(subcommand)]
+    /// Manage profiles, each with its own database and company
+    Profile(ProfileCommands),
     #[command(subcommand)]
     /// Manage companies
     Company(CompanyCommands),
@@ -41,6 +56,120 @@ enum Commands {
     #[command(subcommand)]
     /// Manage invoices
     Invoice(InvoiceCommands),
+    #[command(subcommand)]
+    /// Manage imported time entries
+    Time(TimeCommands),
+    #[command(subcommand)]
+    /// Manage recurring invoice schedules
+    Recurring(RecurringCommands),
+    #[command(subcommand)]
+    /// Manage quotes and estimates
+    Quote(QuoteCommands),
+    #[command(subcommand)]
+    /// Manage credit notes against issued invoices
+    CreditNote(CreditNoteCommands),
+    #[command(subcommand)]
+    /// Revenue and outstanding reports
+    Report(ReportCommands),
+    #[command(subcommand)]
+    /// Export data to other tools
+    Export(ExportCommands),
+    #[command(subcommand)]
+    /// Back up, restore and move the database
+    Db(DbCommands),
+    /// Serve the JSON API for dashboards and scripts
+    Serve(ServeArgs),
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    bind: String,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Show every setting
+    Show,
+    /// Print a single setting, e.g. `ledger_accounts.income`
+    Get(ConfigGetArgs),
+    /// Change a single setting
+    Set(ConfigSetArgs),
+    /// Print the location of the config file
+    Path,
+}
+
+#[derive(Args)]
+struct ConfigGetArgs {
+    /// The setting to print
+    key: String,
+}
+
+#[derive(Args)]
+struct ConfigSetArgs {
+    /// The setting to change
+    key: String,
+    /// The new value
+    value: String,
+}
+
+#[derive(Args)]
+struct SetupArgs {
+    /// Run without prompting, using the flags below
+    #[arg(long, requires_all = ["company_name", "logo"])]
+    yes: bool,
+    /// Directory for the database (defaults to ~/clinvoice)
+    #[arg(long, requires = "yes")]
+    db_dir: Option<std::path::PathBuf>,
+    /// Directory for invoice PDFs (defaults to the database directory)
+    #[arg(long, requires = "yes")]
+    invoice_dir: Option<std::path::PathBuf>,
+    /// Name of your company; an existing company with this name is reused
+    #[arg(long, requires = "yes")]
+    company_name: Option<String>,
+    /// Your company's address, printed on invoices
+    #[arg(long, requires = "yes")]
+    company_address: Option<String>,
+    /// Your company's email address
+    #[arg(long, requires = "yes")]
+    company_email: Option<String>,
+    /// Your company's phone number
+    #[arg(long, requires = "yes")]
+    company_phone: Option<String>,
+    /// Path to your logo (png/jpeg/svg)
+    #[arg(long, requires = "yes")]
+    logo: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// List profiles
+    List,
+    /// Create a profile with a new database
+    Create(ProfileCreateArgs),
+    /// Make a profile the active one
+    Switch(ProfileSwitchArgs),
+}
+
+#[derive(Args)]
+struct ProfileCreateArgs {
+    /// Name of the profile. The database (default ~/clinvoice/<name>/cli_invoice.sqlite3),
+    /// invoice directory (default ~/clinvoice/<name>) and logo (default the current one)
+    /// are taken from --database-url, --invoice-path and --logo-path
+    name: String,
+    /// Custom invoice template (defaults to the built-in template)
+    #[arg(long)]
+    template_path: Option<String>,
+    /// Name of the profile's company (prompts for the details)
+    #[arg(long)]
+    company: Option<String>,
+}
+
+#[derive(Args)]
+struct ProfileSwitchArgs {
+    /// Name of the profile, or "default"
+    name: String,
 }
 
 #[derive(Subcommand)]
@@ -55,6 +184,14 @@ enum ClientCommands {
     Add(ClientAddArgs),
     /// #[arg(short, long)]
     List(ClientListArgs),
+    /// Generate a statement of account PDF for a client
+    Statement(ClientStatementArgs),
+    /// Set or clear a client's credit limit
+    CreditLimit(ClientCreditLimitArgs),
+    /// Import clients from a CSV or vCard file
+    Import(ClientImportArgs),
+    /// Export all clients to a CSV or vCard file
+    Export(ClientExportArgs),
 }
 
 #[derive(Subcommand)]
@@ -66,6 +203,78 @@ enum InvoiceCommands {
     List(InvoiceListArgs),
     /// regenerate an invoice
     Regen(InvoiceRegenArgs),
+    /// Issue a new invoice based on an existing one
+    Clone(InvoiceCloneArgs),
+    /// Record a payment against an invoice
+    Pay(InvoicePayArgs),
+    /// List recorded payments
+    Payments(InvoicePaymentsArgs),
+    /// Import past invoices from another tool (CSV or JSON)
+    Import(InvoiceImportArgs),
+}
+
+#[derive(Subcommand)]
+enum TimeCommands {
+    /// Stage time entries from a CSV export; invoices are not created from them
+    Import(TimeImportArgs),
+    /// List time entries staged for invoicing
+    List(TimeListArgs),
+}
+
+#[derive(Subcommand)]
+enum RecurringCommands {
+    /// Add a recurring invoice schedule
+    Add(RecurringAddArgs),
+    /// List recurring invoice schedules
+    List,
+    /// Generate every recurring invoice due up to today
+    Run(RecurringRunArgs),
+    /// Delete a recurring invoice schedule
+    Delete(RecurringDeleteArgs),
+}
+
+#[derive(Subcommand)]
+enum QuoteCommands {
+    /// Create a quote
+    Create(QuoteCreateArgs),
+    /// List quotes
+    List(QuoteListArgs),
+    /// Show a quote and its items
+    Show(QuoteIdArgs),
+    /// Mark a quote as accepted
+    Accept(QuoteIdArgs),
+    /// Mark a quote as declined
+    Decline(QuoteIdArgs),
+    /// Convert a quote into an invoice
+    Convert(QuoteIdArgs),
+}
+
+#[derive(Subcommand)]
+enum CreditNoteCommands {
+    /// Issue a credit note against an invoice
+    Create(CreditNoteCreateArgs),
+    /// List credit notes
+    List(CreditNoteListArgs),
+    /// Show a credit note and its items
+    Show(CreditNoteShowArgs),
+}
+
+#[derive(Subcommand)]
+enum ReportCommands {
+    /// Revenue per month, quarter or year
+    Revenue(ReportRevenueArgs),
+    /// Revenue per client
+    Clients(ReportArgs),
+    /// Revenue per company
+    Companies(ReportArgs),
+    /// Clients ranked by net revenue
+    TopClients(ReportTopClientsArgs),
+    /// Average number of days between invoicing and full payment
+    DaysToPay(ReportArgs),
+    /// Outstanding balances bucketed by days overdue
+    Aging(ReportAgingArgs),
+    /// Tax (VAT/GST) return summary for a period
+    Tax(ReportTaxArgs),
 }
 
 #[derive(Args)]
@@ -90,6 +299,48 @@ struct ClientAddArgs {
     email: Option<String>,
     /// Phone number of the client
     phone: Option<String>,
+    /// Maximum outstanding balance allowed for the client
+    #[arg(long)]
+    credit_limit: Option<f64>,
+}
+
+#[derive(Args)]
+struct ClientCreditLimitArgs {
+    /// The id of the client
+    id: i32,
+    /// The new credit limit
+    #[arg(required_unless_present = "clear")]
+    amount: Option<f64>,
+    /// Remove the credit limit
+    #[arg(long, conflicts_with = "amount")]
+    clear: bool,
+}
+
+#[derive(Args)]
+struct ClientImportArgs {
+    /// Path to the CSV or vCard (.vcf) file
+    file: String,
+    /// File format (detected from the extension by default)
+    #[arg(long, value_enum)]
+    format: Option<contacts::ContactFormat>,
+    /// Map a field to a CSV header, e.g. "email=Work Email"
+    #[arg(long)]
+    column: Vec<String>,
+    /// Company to link the imported clients to (defaults to the default company)
+    #[arg(long)]
+    company: Option<String>,
+    /// Preview the import without saving anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct ClientExportArgs {
+    /// Path of the file to write
+    file: String,
+    /// File format (detected from the extension by default)
+    #[arg(long, value_enum)]
+    format: Option<contacts::ContactFormat>,
 }
 
 #[derive(Args)]
@@ -105,6 +356,30 @@ struct ClientListArgs {
     address: Option<String>,
 }
 
+#[derive(Args)]
+struct ClientStatementArgs {
+    /// The id of the client
+    id: i32,
+    /// Start of the statement period (YYYY-MM-DD); earlier activity forms the opening balance
+    #[arg(long)]
+    from: Option<chrono::NaiveDate>,
+    /// End of the statement period (YYYY-MM-DD, defaults to today)
+    #[arg(long)]
+    to: Option<chrono::NaiveDate>,
+}
+
+#[derive(Args)]
+struct InvoiceImportArgs {
+    /// Path to the CSV or JSON file
+    file: String,
+    /// File format (detected from the extension by default)
+    #[arg(long, value_enum)]
+    format: Option<history::HistoryFormat>,
+    /// Directory holding existing PDFs, named by their original invoice number
+    #[arg(long)]
+    pdf_dir: Option<std::path::PathBuf>,
+}
+
 #[derive(Args)]
 struct InvoiceListArgs {
     // Client id
@@ -128,89 +403,610 @@ struct InvoiceRegenArgs {
 }
 
 #[derive(Args)]
-pub struct InvoiceGenerateArgs {
-    /// The name of the company
+struct TimeImportArgs {
+    /// Path to the CSV file
+    file: String,
+    /// The tool the CSV was exported from
+    #[arg(long, value_enum, default_value = "generic")]
+    format: time_entry::TimeImportFormat,
+    /// strftime format of the dates in the file, if not the tool's usual one
+    #[arg(long, value_parser = export::parse_date_format)]
+    date_format: Option<String>,
+    /// Map a client name in the file to a client id, e.g. "Acme Ltd=3"
     #[arg(long)]
-    company_name: Option<String>,
+    map: Vec<String>,
+}
 
-    #[arg(long)]
-    company_address: Option<String>,
+#[derive(Args)]
+struct TimeListArgs {
+    /// Only show entries for this client id
+    #[arg(long, short)]
+    client: Option<i32>,
+}
 
+#[derive(Args)]
+struct RecurringAddArgs {
+    /// The id of the client to bill
     #[arg(long)]
-    company_email: Option<String>,
+    client: i32,
+    /// The id of the company issuing the invoice (defaults to the default company)
+    #[arg(long)]
+    company: Option<i32>,
+    /// The items for the invoice in JSON format
+    #[arg(long)]
+    item: Option<Vec<String>>,
+    #[arg(long)]
+    notes: Option<String>,
+    /// How often the invoice is issued
+    #[arg(long, value_enum, default_value = "monthly")]
+    interval: recurring::RecurringInterval,
+    /// Day of the month the invoice is issued on
+    #[arg(long, default_value = "1")]
+    day: u32,
+    /// First date the schedule is active (YYYY-MM-DD, defaults to today)
+    #[arg(long)]
+    start: Option<chrono::NaiveDate>,
+    /// Last date the schedule is active (YYYY-MM-DD)
+    #[arg(long)]
+    end: Option<chrono::NaiveDate>,
+}
 
+#[derive(Args)]
+struct RecurringRunArgs {
+    /// Show which invoices would be generated without generating them
     #[arg(long)]
-    company_number: Option<String>,
+    dry_run: bool,
+}
 
+#[derive(Args)]
+struct RecurringDeleteArgs {
+    /// The id of the recurring invoice schedule
+    id: i32,
+}
+
+#[derive(Args)]
+struct QuoteCreateArgs {
+    /// The name of the company
+    #[arg(long)]
+    company_name: Option<String>,
     /// The name of the client
     #[arg(long)]
     client_name: Option<String>,
+    /// The items for the quote in JSON format
+    #[arg(long)]
+    item: Option<Vec<String>>,
+    #[arg(long)]
+    notes: Option<String>,
+    /// Number of days the quote is valid for
+    #[arg(long, default_value = "30")]
+    valid_days: i64,
+}
+
+#[derive(Args)]
+struct QuoteListArgs {
+    /// Only show quotes with this status (pending, accepted, declined)
+    #[arg(long, short)]
+    status: Option<String>,
+}
 
+#[derive(Args)]
+struct QuoteIdArgs {
+    /// The id of the quote
+    id: i32,
+}
+
+#[derive(Args)]
+struct CreditNoteCreateArgs {
+    /// The id of the invoice being credited
+    invoice_id: i32,
+    /// Reverse every item on the invoice
+    #[arg(long, conflicts_with = "item")]
+    full: bool,
+    /// The items to credit in JSON format
     #[arg(long)]
-    client_address: Option<String>,
+    item: Option<Vec<String>>,
+    /// Reason for the credit, shown in the notes
+    #[arg(long)]
+    reason: Option<String>,
+}
+
+#[derive(Args)]
+struct CreditNoteListArgs {
+    /// Only show credit notes for this invoice id
+    #[arg(long, short)]
+    invoice: Option<i32>,
+}
+
+#[derive(Args)]
+struct CreditNoteShowArgs {
+    /// The id of the credit note
+    id: i32,
+}
 
+#[derive(Args)]
+struct InvoiceCloneArgs {
+    /// The id of the invoice to copy
+    id: i32,
+    /// Bill a different client
+    #[arg(long)]
+    client_name: Option<String>,
+    /// Replace the items, in JSON format
+    #[arg(long)]
+    item: Option<Vec<String>>,
+    /// Replace the notes printed on the invoice
     #[arg(long)]
-    client_email: Option<String>,
+    notes: Option<String>,
+    /// Skip the prompts and keep anything not given as an option
+    #[arg(long, short)]
+    yes: bool,
+}
 
+#[derive(Args)]
+struct InvoicePayArgs {
+    /// The id of the invoice being paid
+    id: i32,
+    /// Amount paid (defaults to the outstanding balance)
     #[arg(long)]
-    client_number: Option<String>,
+    amount: Option<f64>,
+    /// Date of the payment (YYYY-MM-DD, defaults to today)
+    #[arg(long)]
+    date: Option<chrono::NaiveDate>,
+    /// Payment method, e.g. bank transfer
+    #[arg(long)]
+    method: Option<String>,
+    /// Payment reference
+    #[arg(long)]
+    reference: Option<String>,
+}
 
-    /// The items for the invoice in JSON format
+#[derive(Args)]
+struct InvoicePaymentsArgs {
+    /// Only show payments for this invoice id
+    #[arg(long, short)]
+    id: Option<i32>,
+}
+
+#[derive(Subcommand)]
+enum ExportCommands {
+    /// Export invoices, credit notes and payments to a plain-text accounting journal
+    Ledger(ExportLedgerArgs),
+    /// Export invoices and items as CSV, optionally zipped with the invoice PDFs
+    Csv(ExportCsvArgs),
+}
+
+#[derive(Args)]
+struct ExportLedgerArgs {
+    /// Journal format
+    #[arg(long, value_enum, default_value = "hledger")]
+    format: export::LedgerFormat,
+    /// Only export transactions dated on or after this date (YYYY-MM-DD)
     #[arg(long)]
-    item: Option<Vec<String>>,
+    from: Option<chrono::NaiveDate>,
+    /// Only export transactions dated on or before this date (YYYY-MM-DD)
+    #[arg(long)]
+    to: Option<chrono::NaiveDate>,
+    /// Write the journal to this file instead of stdout
+    #[arg(long, short)]
+    output: Option<std::path::PathBuf>,
+    /// Start a beancount journal with `open` directives for every account used;
+    /// leave off when appending to a journal that already opens them
+    #[arg(long)]
+    open_accounts: bool,
+}
 
+#[derive(Args)]
+struct ExportCsvArgs {
+    /// Only export invoices dated on or after this date (YYYY-MM-DD)
     #[arg(long)]
-    notes: Option<String>,
+    from: Option<chrono::NaiveDate>,
+    /// Only export invoices dated on or before this date (YYYY-MM-DD)
+    #[arg(long)]
+    to: Option<chrono::NaiveDate>,
+    /// Only export invoices from companies whose name contains this
+    #[arg(long)]
+    company: Option<String>,
+    /// Directory to write the CSV files to
+    #[arg(long, short, default_value = ".")]
+    output_dir: std::path::PathBuf,
+    /// Write one joined file with a row per item instead of invoices.csv and items.csv
+    #[arg(long)]
+    joined: bool,
+    /// Field delimiter, a single character or 'tab'
+    #[arg(long, default_value = ",", value_parser = export::parse_delimiter)]
+    delimiter: u8,
+    /// strftime format for dates
+    #[arg(long, default_value = "%Y-%m-%d", value_parser = export::parse_date_format)]
+    date_format: String,
+    /// Bundle the CSVs and the invoice PDFs into this zip file
+    #[arg(long)]
+    zip: Option<std::path::PathBuf>,
+}
 
-    #[arg(long, default_value = "false")]
-    custom: Option<bool>,
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Save a consistent snapshot of the database
+    Backup(DbBackupArgs),
+    /// Replace the database with a backup
+    Restore(DbRestoreArgs),
+    /// Export all data and settings to a portable bundle
+    Export(DbExportArgs),
+    /// Replace all data and settings with an exported bundle
+    Import(DbImportArgs),
+    /// Show, apply or revert schema migrations
+    #[command(subcommand)]
+    Migrate(DbMigrateCommands),
 }
-fn main() {
-    if !config::is_setup_done() {
-        println!("{}", "Setup not done".red());
-        config::setup();
+
+#[derive(Subcommand)]
+enum DbMigrateCommands {
+    /// List migrations and whether each is applied
+    Status,
+    /// Apply pending migrations (done automatically on start)
+    Up,
+    /// Revert the latest migrations, dropping the data they added
+    Down(DbMigrateDownArgs),
+}
+
+#[derive(Args)]
+struct DbMigrateDownArgs {
+    /// Number of migrations to revert
+    #[arg(long, default_value = "1")]
+    steps: usize,
+    /// Don't ask for confirmation
+    #[arg(long, short)]
+    yes: bool,
+}
+
+#[derive(Args)]
+struct DbBackupArgs {
+    /// Where to save the backup (defaults to backups/ next to the database)
+    path: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct DbRestoreArgs {
+    /// The backup to restore
+    path: std::path::PathBuf,
+    /// Don't ask for confirmation
+    #[arg(long, short)]
+    yes: bool,
+}
+
+#[derive(Args)]
+struct DbExportArgs {
+    /// Path of the bundle to write
+    path: std::path::PathBuf,
+    /// Include the invoice PDFs (writes a zip)
+    #[arg(long)]
+    pdfs: bool,
+    /// Include the logo (writes a zip)
+    #[arg(long)]
+    logo: bool,
+}
+
+#[derive(Args)]
+struct DbImportArgs {
+    /// The bundle to import
+    path: std::path::PathBuf,
+    /// Don't ask for confirmation
+    #[arg(long, short)]
+    yes: bool,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// Only include records dated on or after this date (YYYY-MM-DD)
+    #[arg(long)]
+    from: Option<chrono::NaiveDate>,
+    /// Only include records dated on or before this date (YYYY-MM-DD)
+    #[arg(long)]
+    to: Option<chrono::NaiveDate>,
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    format: report::ReportFormat,
+}
+
+#[derive(Args)]
+struct ReportRevenueArgs {
+    /// Group revenue by month, quarter or year
+    #[arg(long, value_enum, default_value = "month")]
+    by: report::ReportPeriod,
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+#[derive(Args)]
+struct ReportTopClientsArgs {
+    /// Number of clients to show
+    #[arg(long, default_value = "10")]
+    limit: usize,
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+#[derive(Args)]
+struct ReportTaxArgs {
+    /// Tax period (YYYY-Qn, YYYY-MM or YYYY)
+    #[arg(long, value_parser = report::parse_tax_period)]
+    period: report::DateRange,
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    format: report::ReportFormat,
+}
+
+#[derive(Args)]
+struct ReportAgingArgs {
+    /// Date to age balances at (YYYY-MM-DD, defaults to today)
+    #[arg(long)]
+    as_of: Option<chrono::NaiveDate>,
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    format: report::ReportFormat,
+}
+
+/// Prints the error and exits with a non-zero status, so scripts can tell
+/// that the command failed.
+fn fail(e: impl std::fmt::Display) -> ! {
+    eprintln!("{} {}", "Error:".red(), e);
+    std::process::exit(1);
+}
+
+/// Credit warnings and where the PDF went, after an invoice is issued.
+fn print_generated(generated: &service::GeneratedInvoice) {
+    for warning in &generated.warnings {
+        println!("{} {}", "Warning:".yellow(), warning);
     }
+    println!(
+        "PDF saved to: {}",
+        generated.path.display().to_string().bright_black()
+    );
+}
 
+fn main() {
     let cli = Cli::parse();
 
+    // Variables already set in the environment win over .env
+    dotenvy::dotenv().ok();
+
+    if let Err(e) = config::init(cli.config) {
+        fail(e);
+    }
+
+    let flags = config::Overrides {
+        database_url: cli.database_url.clone(),
+        invoice_path: cli.invoice_path.clone(),
+        logo_path: cli.logo_path.clone(),
+        default_company: cli.default_company,
+    };
+    match config::Overrides::from_env() {
+        Ok(env) => config::set_overrides(env.merge(flags)),
+        Err(e) => {
+            fail(e);
+        }
+    }
+
+    if let Commands::Config(ConfigCommands::Path) = cli.command {
+        match config::config_path() {
+            Ok(path) => println!("{}", path.display()),
+            Err(e) => fail(e),
+        }
+        return;
+    }
+
+    // Containers and tests can point at a fresh database without running setup;
+    // it is migrated below like any other
+    if config::overrides().database_url.is_none() && !matches!(cli.command, Commands::Setup(_)) {
+        match config::is_setup_done() {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("{}", "Setup not done".red());
+                if let Err(e) = config::setup() {
+                    fail(e);
+                }
+            }
+            Err(e) => {
+                fail(e);
+            }
+        }
+    }
+
+    if let Some(name) = cli.profile {
+        match profile::exists(&name) {
+            Ok(true) => config::use_profile(Some(name)),
+            Ok(false) => {
+                fail(format!("Profile {} not found", name));
+            }
+            Err(e) => {
+                fail(e);
+            }
+        }
+    }
+
+    // Commands that migrate themselves, or don't touch the database
+    let migrates = !matches!(
+        cli.command,
+        Commands::Setup(_)
+            | Commands::Config(_)
+            | Commands::Profile(_)
+            | Commands::Db(DbCommands::Restore(_) | DbCommands::Migrate(_))
+    );
+    if migrates {
+        if let Err(e) = migrate::on_startup() {
+            fail(e);
+        }
+    }
+
     match cli.command {
-        Commands::Setup => {
-            config::setup();
+        Commands::Setup(args) => {
+            if args.yes {
+                let options = config::SetupOptions {
+                    db_dir: args.db_dir,
+                    invoice_dir: args.invoice_dir,
+                    company_name: args.company_name.unwrap_or_default(),
+                    company_address: args.company_address,
+                    company_email: args.company_email,
+                    company_phone: args.company_phone,
+                    logo: args.logo.unwrap_or_default(),
+                };
+                if let Err(e) = config::setup_with(options) {
+                    fail(e);
+                }
+            } else if let Err(e) = config::setup() {
+                fail(e);
+            }
         }
+        Commands::Config(config_command) => match config_command {
+            ConfigCommands::Show => {
+                let shown = config::config_path()
+                    .map_err(String::from)
+                    .and_then(|path| {
+                        println!("Settings in {}", path.display());
+                        config::show()
+                    });
+                if let Err(e) = shown {
+                    fail(e);
+                }
+            }
+            ConfigCommands::Get(args) => match config::get(&args.key) {
+                Ok(value) => println!("{}", value),
+                Err(e) => fail(e),
+            },
+            ConfigCommands::Set(args) => match config::set(&args.key, &args.value) {
+                Ok(()) => println!("{}", format!("{} set to {}", args.key, args.value).green()),
+                Err(e) => fail(e),
+            },
+            ConfigCommands::Path => unreachable!("handled before setup"),
+        },
+        Commands::Profile(profile_command) => match profile_command {
+            ProfileCommands::List => {
+                println!("Profiles");
+                if let Err(e) = profile::list() {
+                    fail(e);
+                }
+            }
+            ProfileCommands::Create(args) => {
+                let name = args.name.clone();
+                let create_args = profile::CreateArgs {
+                    name: args.name,
+                    database_url: cli.database_url.map(Into::into),
+                    invoice_path: cli.invoice_path.map(Into::into),
+                    logo_path: cli.logo_path,
+                    template_path: args.template_path,
+                    company_name: args.company,
+                };
+                match profile::create(create_args) {
+                    Ok(()) => println!(
+                        "{}",
+                        format!(
+                            "Profile {} created; use `profile switch {}` or --profile {}",
+                            name, name, name
+                        )
+                        .green()
+                    ),
+                    Err(e) => fail(e),
+                }
+            }
+            ProfileCommands::Switch(args) => match profile::switch(&args.name) {
+                Ok(()) => println!("{}", format!("Switched to profile {}", args.name).green()),
+                Err(e) => fail(e),
+            },
+        },
         Commands::Company(company_command) => match company_command {
             CompanyCommands::Add(args) => {
                 println!("Add Company");
-                company::create_company_prompts(args.name, args.address, args.email, args.phone);
+                let input =
+                    company::prompt_company(args.name, args.address, args.email, args.phone);
+                match service::create_company(input) {
+                    Ok(company) => {
+                        println!("{} ", format!("Company {} added", company.name).green())
+                    }
+                    Err(e) => fail(e),
+                }
             }
         },
         Commands::Client(client_command) => match client_command {
             ClientCommands::Add(args) => {
                 println!("Add Client");
-                client::create_client_prompts(args.name, args.address, args.email, args.phone);
+                let input = client::prompt_client(args.name, args.address, args.email, args.phone);
+                let input = service::ClientInput {
+                    credit_limit: args.credit_limit,
+                    ..input
+                };
+                match service::create_client(input) {
+                    Ok(client) => println!("{} ", format!("Client {} added", client.name).green()),
+                    Err(e) => fail(e),
+                }
             }
             ClientCommands::List(args) => {
                 let count = args.name.is_some() as i32
                     + args.address.is_some() as i32
                     + args.company.is_some() as i32;
                 if count > 1 {
-                    println!("Please provide only one of name, address, or company");
-                    return;
+                    fail("Please provide only one of name, address, or company");
                 }
 
                 if let Some(name) = args.name {
                     println!("Clients with name containing {}\n", name.green());
-                    client::search_by_name(&name, None);
+                    if let Err(e) = client::search_by_name(&name, None) {
+                        fail(e);
+                    }
                 } else if let Some(company) = args.company {
                     println!("Clients with company containing {}\n", company.green());
-                    client::search_by_company(&company, None);
+                    if let Err(e) = client::search_by_company(&company, None) {
+                        fail(e);
+                    }
                 } else if let Some(address) = args.address {
                     println!("Clients with address containing {}\n", address.green());
-                    client::search_by_address(&address, None);
+                    if let Err(e) = client::search_by_address(&address, None) {
+                        fail(e);
+                    }
                 } else {
                     println!("Full list of clients");
-                    client::list_clients();
+                    if let Err(e) = client::list_clients() {
+                        fail(e);
+                    }
+                }
+            }
+            ClientCommands::CreditLimit(args) => {
+                match models::Client::set_credit_limit(args.id, args.amount) {
+                    Ok(client) => match client.credit_limit {
+                        Some(limit) => println!(
+                            "{}",
+                            format!("Credit limit for {} set to {:.2}", client.name, limit).green()
+                        ),
+                        None => println!(
+                            "{}",
+                            format!("Credit limit for {} removed", client.name).green()
+                        ),
+                    },
+                    Err(e) => fail(e),
+                }
+            }
+            ClientCommands::Statement(args) => {
+                if let Err(e) = statement::generate(args.id, args.from, args.to) {
+                    fail(e);
                 }
             }
+            ClientCommands::Import(args) => {
+                println!("Importing clients from {}", args.file);
+                let import_args = contacts::ImportArgs {
+                    file: args.file,
+                    format: args.format,
+                    columns: args.column,
+                    company_name: args.company,
+                    dry_run: args.dry_run,
+                };
+                if let Err(e) = contacts::import(import_args) {
+                    fail(e);
+                }
+            }
+            ClientCommands::Export(args) => match contacts::export(&args.file, args.format) {
+                Ok(count) => println!(
+                    "{}",
+                    format!("Exported {} clients to {}", count, args.file).green()
+                ),
+                Err(e) => fail(e),
+            },
         },
         Commands::Invoice(invoice_command) => match invoice_command {
             InvoiceCommands::Generate(args) => {
@@ -228,8 +1024,11 @@ fn main() {
                 let used_args = count > 0;
                 let is_custom = args.custom.unwrap_or_default();
                 match generate_invoice(args, used_args, is_custom) {
-                    Ok(_) => println!("{}", "Invoice generation complete.".green()),
-                    Err(e) => println!("Error: {}", e),
+                    Ok(generated) => {
+                        print_generated(&generated);
+                        println!("{}", "Invoice generation complete.".green());
+                    }
+                    Err(e) => fail(e),
                 }
             }
 
@@ -239,29 +1038,391 @@ fn main() {
                     + args.address.is_some() as i32
                     + args.company.is_some() as i32;
                 if count > 1 {
-                    println!("Please provide only one of id, name, address, or company");
-                    return;
+                    fail("Please provide only one of id, name, address, or company");
                 }
 
                 if let Some(id) = args.id {
                     println!("Invoice with id {}\n", id.to_string().green());
-                    invoice::search_by(InvoiceSearchField::Id, &id.to_string(), Some(true));
-                } else if let Some(client) = args.client {
-                    println!("Invoices with name containing {}\n", client.green());
-                    invoice::search_by(InvoiceSearchField::Client, &client, Some(true));
+                } else if let Some(client) = &args.client {
+                    println!("Invoices for client {}\n", client.green());
                 } else {
                     println!("Full list of invoices");
-                    invoice::list_invoices();
+                }
+
+                let filter = service::InvoiceFilter {
+                    id: args.id,
+                    client_name: args.client,
+                    ..Default::default()
+                };
+                match service::list_invoices(&filter) {
+                    Ok(list) if list.is_empty() => println!("{}", "No invoices found".red()),
+                    Ok(list) => {
+                        invoice::summary_table(&list);
+
+                        // A single invoice is shown with its items
+                        if let Some(id) = args.id {
+                            match service::get_invoice(id) {
+                                Ok(detail) => invoice::items_table(&detail.items),
+                                Err(e) => fail(e),
+                            }
+                        }
+                    }
+                    Err(e) => fail(e),
                 }
             }
 
             InvoiceCommands::Regen(args) => {
                 println!("Regenerating invoice with id {}", args.id);
                 match invoice::regenerate(args.id) {
-                    Ok(_) => println!("{}", "Invoice regenerated".green()),
-                    Err(e) => println!("Error: {}", e),
+                    Ok(generated) => {
+                        print_generated(&generated);
+                        println!("{}", "Invoice regenerated".green());
+                    }
+                    Err(e) => fail(e),
+                };
+            }
+
+            InvoiceCommands::Clone(args) => {
+                println!("Cloning invoice with id {}", args.id);
+                let clone_args = invoice::CloneArgs {
+                    client_name: args.client_name,
+                    items: args.item,
+                    notes: args.notes,
+                    interactive: !args.yes,
+                };
+                match invoice::clone_invoice(args.id, clone_args) {
+                    Ok(generated) => {
+                        print_generated(&generated);
+                        println!("{} {:05}", "Invoice created:".green(), generated.invoice.id);
+                    }
+                    Err(e) => fail(e),
                 };
             }
+
+            InvoiceCommands::Pay(args) => {
+                let payment_args = payment::PaymentArgs {
+                    invoice_id: args.id,
+                    amount: args.amount,
+                    date: args.date,
+                    method: args.method,
+                    reference: args.reference,
+                };
+                match payment::record(payment_args) {
+                    Ok((payment, invoice)) => println!(
+                        "{} {:.2} against invoice {}",
+                        "Payment recorded:".green(),
+                        payment.amount,
+                        invoice.number()
+                    ),
+                    Err(e) => fail(e),
+                }
+            }
+
+            InvoiceCommands::Payments(args) => {
+                println!("Recorded payments");
+                if let Err(e) = payment::list(args.id) {
+                    fail(e);
+                }
+            }
+            InvoiceCommands::Import(args) => {
+                println!("Importing invoices from {}", args.file);
+                let import_args = history::HistoryImportArgs {
+                    file: args.file,
+                    format: args.format,
+                    pdf_dir: args.pdf_dir,
+                };
+                if let Err(e) = history::import(import_args) {
+                    fail(e);
+                }
+            }
         },
+        Commands::Time(time_command) => match time_command {
+            TimeCommands::Import(args) => {
+                println!("Importing time entries from {}", args.file);
+                if let Err(e) = time_entry::import(
+                    &args.file,
+                    args.format,
+                    args.date_format.as_deref(),
+                    &args.map,
+                ) {
+                    fail(e);
+                }
+            }
+            TimeCommands::List(args) => {
+                println!("Time entries staged for invoicing");
+                if let Err(e) = time_entry::list_staged(args.client) {
+                    fail(e);
+                }
+            }
+        },
+        Commands::Recurring(recurring_command) => match recurring_command {
+            RecurringCommands::Add(args) => {
+                let recurring_args = recurring::RecurringArgs {
+                    company_id: args.company,
+                    client_id: args.client,
+                    items: args.item,
+                    notes: args.notes,
+                    interval: args.interval,
+                    day_of_month: args.day,
+                    start_date: args.start,
+                    end_date: args.end,
+                };
+                match recurring::create(recurring_args) {
+                    Ok(recurring) => println!(
+                        "{} next invoice on {}",
+                        "Recurring invoice added,".green(),
+                        recurring.next_run_date
+                    ),
+                    Err(e) => fail(e),
+                }
+            }
+            RecurringCommands::List => {
+                println!("Recurring invoices");
+                if let Err(e) = recurring::list() {
+                    fail(e);
+                }
+            }
+            RecurringCommands::Run(args) => {
+                let today = chrono::offset::Local::now().date_naive();
+                match recurring::run(today, args.dry_run) {
+                    Ok(count) if args.dry_run => {
+                        println!("{} {}", "Recurring invoices due:".green(), count)
+                    }
+                    Ok(count) => println!("{} {}", "Recurring invoices generated:".green(), count),
+                    Err(e) => {
+                        fail(e);
+                    }
+                }
+            }
+            RecurringCommands::Delete(args) => match recurring::delete(args.id) {
+                Ok(_) => println!("{}", "Recurring invoice deleted".green()),
+                Err(e) => fail(e),
+            },
+        },
+        Commands::Quote(quote_command) => match quote_command {
+            QuoteCommands::Create(args) => {
+                let quote_args = quote::QuoteArgs {
+                    company_name: args.company_name,
+                    client_name: args.client_name,
+                    items: args.item,
+                    notes: args.notes,
+                    valid_days: args.valid_days,
+                };
+                match quote::create(quote_args) {
+                    Ok(id) => println!(
+                        "{} {}",
+                        "Quote created:".green(),
+                        quote::format_quote_number(id)
+                    ),
+                    Err(e) => fail(e),
+                }
+            }
+            QuoteCommands::List(args) => {
+                println!("Full list of quotes");
+                if let Err(e) = quote::list(args.status) {
+                    fail(e);
+                }
+            }
+            QuoteCommands::Show(args) => {
+                if let Err(e) = quote::show(args.id) {
+                    fail(e);
+                }
+            }
+            QuoteCommands::Accept(args) => {
+                match quote::set_status(args.id, quote::STATUS_ACCEPTED) {
+                    Ok(_) => println!("{}", "Quote accepted".green()),
+                    Err(e) => fail(e),
+                }
+            }
+            QuoteCommands::Decline(args) => {
+                match quote::set_status(args.id, quote::STATUS_DECLINED) {
+                    Ok(_) => println!("{}", "Quote declined".green()),
+                    Err(e) => fail(e),
+                }
+            }
+            QuoteCommands::Convert(args) => {
+                println!("Converting quote with id {}", args.id);
+                match quote::convert(args.id) {
+                    Ok(invoice_id) => println!(
+                        "{} {:05}",
+                        "Quote converted into invoice".green(),
+                        invoice_id
+                    ),
+                    Err(e) => fail(e),
+                }
+            }
+        },
+        Commands::CreditNote(credit_note_command) => match credit_note_command {
+            CreditNoteCommands::Create(args) => {
+                let credit_note_args = credit_note::CreditNoteArgs {
+                    invoice_id: args.invoice_id,
+                    items: args.item,
+                    full: args.full,
+                    reason: args.reason,
+                };
+                match credit_note::create(credit_note_args) {
+                    Ok(id) => println!(
+                        "{} {}",
+                        "Credit note issued:".green(),
+                        credit_note::format_credit_note_number(id)
+                    ),
+                    Err(e) => fail(e),
+                }
+            }
+            CreditNoteCommands::List(args) => {
+                println!("Full list of credit notes");
+                if let Err(e) = credit_note::list(args.invoice) {
+                    fail(e);
+                }
+            }
+            CreditNoteCommands::Show(args) => {
+                if let Err(e) = credit_note::show(args.id) {
+                    fail(e);
+                }
+            }
+        },
+        Commands::Report(report_command) => {
+            let loaded = config::load_config()
+                .map_err(String::from)
+                .and_then(|config| Ok((config, report::Ledger::load()?)));
+            let (config, ledger) = match loaded {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    fail(e);
+                }
+            };
+            let range = |args: &ReportArgs| report::DateRange {
+                from: args.from,
+                to: args.to,
+            };
+
+            let (report, format) = match report_command {
+                ReportCommands::Revenue(args) => (
+                    report::revenue_by_period(&ledger, args.by, range(&args.report)),
+                    args.report.format,
+                ),
+                ReportCommands::Clients(args) => (
+                    report::revenue_by_client(&ledger, range(&args)),
+                    args.format,
+                ),
+                ReportCommands::Companies(args) => (
+                    report::revenue_by_company(&ledger, range(&args)),
+                    args.format,
+                ),
+                ReportCommands::TopClients(args) => (
+                    report::top_clients(&ledger, range(&args.report), args.limit),
+                    args.report.format,
+                ),
+                ReportCommands::DaysToPay(args) => {
+                    (report::days_to_pay(&ledger, range(&args)), args.format)
+                }
+                ReportCommands::Aging(args) => {
+                    let as_of = args
+                        .as_of
+                        .unwrap_or_else(|| chrono::offset::Local::now().date_naive());
+                    (
+                        report::aging(&ledger, as_of, config.payment_terms_days),
+                        args.format,
+                    )
+                }
+                ReportCommands::Tax(args) => (
+                    report::tax_return(&ledger, args.period, config.tax_basis),
+                    args.format,
+                ),
+            };
+
+            if let Err(e) = report.print(format) {
+                fail(e);
+            }
+        }
+        Commands::Export(export_command) => match export_command {
+            ExportCommands::Ledger(args) => {
+                let range = report::DateRange {
+                    from: args.from,
+                    to: args.to,
+                };
+                let to_file = args.output.is_some();
+
+                match export::ledger(args.format, range, args.output, args.open_accounts) {
+                    Ok(count) if to_file => {
+                        println!("{}", format!("Exported {} transactions", count).green())
+                    }
+                    Ok(_) => {}
+                    Err(e) => fail(e),
+                }
+            }
+            ExportCommands::Csv(args) => {
+                let args = export::CsvExportArgs {
+                    range: report::DateRange {
+                        from: args.from,
+                        to: args.to,
+                    },
+                    company: args.company,
+                    output_dir: args.output_dir,
+                    joined: args.joined,
+                    delimiter: args.delimiter,
+                    date_format: args.date_format,
+                    zip: args.zip,
+                };
+
+                match export::csv(args) {
+                    Ok(paths) => {
+                        for path in paths {
+                            println!("{} {}", "Exported".green(), path.display());
+                        }
+                    }
+                    Err(e) => fail(e),
+                }
+            }
+        },
+        Commands::Db(db_command) => match db_command {
+            DbCommands::Backup(args) => match backup::backup(args.path) {
+                Ok(path) => println!("{} {}", "Backup saved to".green(), path.display()),
+                Err(e) => fail(e),
+            },
+            DbCommands::Restore(args) => match backup::restore(&args.path, args.yes) {
+                Ok(()) => println!("{} {}", "Restored".green(), args.path.display()),
+                Err(e) => fail(e),
+            },
+            DbCommands::Export(args) => {
+                let path = args.path.clone();
+                let export_args = backup::ExportArgs {
+                    path: args.path,
+                    pdfs: args.pdfs,
+                    logo: args.logo,
+                };
+                match backup::export(export_args) {
+                    Ok(()) => println!("{} {}", "Exported".green(), path.display()),
+                    Err(e) => fail(e),
+                }
+            }
+            DbCommands::Import(args) => match backup::import(&args.path, args.yes) {
+                Ok(()) => println!("{} {}", "Imported".green(), args.path.display()),
+                Err(e) => fail(e),
+            },
+            DbCommands::Migrate(migrate_command) => match migrate_command {
+                DbMigrateCommands::Status => {
+                    if let Err(e) = migrate::status() {
+                        fail(e);
+                    }
+                }
+                DbMigrateCommands::Up => match migrate::up() {
+                    Ok(applied) if applied.is_empty() => {
+                        println!("{}", "Database is up to date".green())
+                    }
+                    Ok(applied) => println!("{} {}", "Applied".green(), applied.join(", ")),
+                    Err(e) => fail(e),
+                },
+                DbMigrateCommands::Down(args) => match migrate::down(args.steps, args.yes) {
+                    Ok(reverted) => println!("{} {}", "Reverted".green(), reverted.join(", ")),
+                    Err(e) => fail(e),
+                },
+            },
+        },
+        Commands::Serve(args) => {
+            if let Err(e) = server::serve(&args.bind) {
+                fail(e);
+            }
+        }
     }
 }
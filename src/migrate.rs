@@ -0,0 +1,143 @@
+use crate::backup;
+use crate::config::{self, ConfigError};
+use crate::db::{self, Backend};
+use cli_table::{print_stdout, Cell, Style, Table};
+use std::error::Error;
+use text_colorizer::*;
+
+/// Applies pending migrations before a command runs, so a database created by
+/// an older version of cli-invoice keeps working after an upgrade. A database
+/// that already has data is backed up first.
+pub fn on_startup() -> Result<(), Box<dyn Error>> {
+    let fresh = db::repository()?
+        .migration_status()?
+        .iter()
+        .all(|migration| !migration.applied);
+    let applied = up()?;
+
+    // Only upgrades are worth mentioning, not a new database being created
+    if !fresh && !applied.is_empty() {
+        println!(
+            "{} {}",
+            "Database upgraded:".green(),
+            applied.join(", ").bright_black()
+        );
+    }
+
+    Ok(())
+}
+
+/// Only SQLite files are backed up here; PostgreSQL has `pg_dump` for that.
+fn is_sqlite() -> Result<bool, ConfigError> {
+    Ok(Backend::of(&config::load_config()?.database_url) == Backend::Sqlite)
+}
+
+/// Applies pending migrations and returns their names.
+pub fn up() -> Result<Vec<String>, Box<dyn Error>> {
+    let repo = &mut *db::repository()?;
+    repo.check_schema()?;
+
+    let status = repo.migration_status()?;
+    if status.iter().all(|migration| migration.applied) {
+        return Ok(Vec::new());
+    }
+
+    // A brand new database has nothing worth saving
+    if status.iter().any(|migration| migration.applied) && is_sqlite()? {
+        let path = backup::backup(None)?;
+        println!(
+            "Database saved to {} before migrating",
+            path.display().to_string().bright_black()
+        );
+    }
+
+    Ok(repo.run_migrations()?)
+}
+
+/// Reverts the latest `steps` migrations after a backup, returning their names.
+/// Tables and columns they added are dropped along with their data.
+pub fn down(steps: usize, yes: bool) -> Result<Vec<String>, Box<dyn Error>> {
+    let repo = &mut *db::repository()?;
+    repo.check_schema()?;
+
+    let reverting = repo
+        .migration_status()?
+        .into_iter()
+        .rev()
+        .filter(|migration| migration.applied)
+        .take(steps)
+        .map(|migration| migration.name)
+        .collect::<Vec<_>>();
+
+    if reverting.is_empty() {
+        return Err("No migrations to revert".into());
+    }
+
+    println!("Reverting {}", reverting.join(", "));
+    if !yes && !backup::confirm("Data in the reverted tables and columns will be lost. Continue?") {
+        return Err("Revert cancelled".into());
+    }
+
+    if is_sqlite()? {
+        let path = backup::backup(None)?;
+        println!(
+            "Database saved to {}",
+            path.display().to_string().bright_black()
+        );
+    }
+
+    Ok(repo.revert_migrations(steps)?)
+}
+
+/// Prints every migration this version knows about and whether it is applied,
+/// along with any applied by a newer version.
+pub fn status() -> Result<(), Box<dyn Error>> {
+    let repo = &mut *db::repository()?;
+
+    let status = repo.migration_status()?;
+    let unknown = repo.unknown_migrations()?;
+
+    let mut rows = status
+        .iter()
+        .map(|migration| {
+            vec![
+                migration.version.clone().cell(),
+                migration.name.clone().cell(),
+                if migration.applied {
+                    "applied"
+                } else {
+                    "pending"
+                }
+                .cell(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    rows.extend(unknown.iter().map(|version| {
+        vec![
+            version.clone().cell(),
+            "".cell(),
+            "applied by a newer version".cell(),
+        ]
+    }));
+
+    let table = rows
+        .table()
+        .title(vec!["Version".cell(), "Name".cell(), "Status".cell()])
+        .bold(true);
+    print_stdout(table).expect("Error printing the table");
+
+    let pending = status.iter().filter(|migration| !migration.applied).count();
+    if !unknown.is_empty() {
+        println!(
+            "{}",
+            "The database is newer than this version of cli-invoice; upgrade cli-invoice first"
+                .red()
+        );
+    } else if pending > 0 {
+        println!("{} migration(s) pending", pending);
+    } else {
+        println!("{}", "Database is up to date".green());
+    }
+
+    Ok(())
+}
@@ -1,7 +1,11 @@
-use crate::schema::{clients, companies, company_clients, invoices, items};
+use crate::schema::{
+    clients, companies, company_clients, credit_note_items, credit_notes, invoices, items,
+    payments, quote_items, quotes, recurring_invoices, time_entries,
+};
 use diesel::prelude::*;
+use serde::Serialize;
 
-#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone)]
+#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone, Serialize)]
 #[diesel(table_name = companies)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct Company {
@@ -12,7 +16,7 @@ pub struct Company {
     pub phone: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Eq, Hash, Clone)]
+#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone, Serialize)]
 #[diesel(table_name = clients)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct Client {
@@ -21,8 +25,20 @@ pub struct Client {
     pub address: Option<String>,
     pub email: Option<String>,
     pub phone: Option<String>,
+    pub credit_limit: Option<f64>,
+}
+
+// Clients are used as map keys; the id identifies them, and the credit
+// limit, being a float, cannot take part in `Eq`/`Hash`.
+impl Eq for Client {}
+
+impl std::hash::Hash for Client {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
 }
 
+#[allow(dead_code)]
 #[derive(Queryable, Selectable, PartialEq, Debug)]
 #[diesel(table_name = company_clients)]
 #[diesel(belongs_to(Company))]
@@ -34,7 +50,7 @@ pub struct CompanyClient {
     pub client_id: i32,
 }
 
-#[derive(Queryable, Identifiable, Associations, Selectable, Debug, PartialEq)]
+#[derive(Queryable, Identifiable, Associations, Selectable, Debug, PartialEq, Clone, Serialize)]
 #[diesel(belongs_to(Client))]
 #[diesel(belongs_to(Company))]
 #[diesel(table_name = invoices)]
@@ -56,12 +72,15 @@ pub struct Invoice {
     pub tax: Option<f64>,
     pub notes: Option<String>,
     pub regenerated: Option<bool>,
+    pub quote_id: Option<i32>,
+    pub due_date: Option<String>,
+    /// The number the invoice had in the tool it was imported from
+    pub original_number: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone)]
+#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone, Serialize)]
 #[diesel(table_name = items)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
-
 pub struct Item {
     pub id: i32,
     pub invoice_id: i32,
@@ -69,6 +88,119 @@ pub struct Item {
     pub quantity: i32,
     pub unit_price: f64,
     pub total: f64,
+    pub tax_rate: Option<f64>,
+}
+
+#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone)]
+#[diesel(table_name = time_entries)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct TimeEntry {
+    pub id: i32,
+    pub source: String,
+    pub external_id: String,
+    pub client_id: Option<i32>,
+    pub client_name: String,
+    pub project: Option<String>,
+    pub description: String,
+    pub duration_minutes: i32,
+    pub date: String,
+    pub invoice_id: Option<i32>,
+}
+
+#[derive(Queryable, Identifiable, Associations, Selectable, Debug, PartialEq, Clone)]
+#[diesel(belongs_to(Invoice))]
+#[diesel(table_name = credit_notes)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct CreditNote {
+    pub id: i32,
+    pub invoice_id: i32,
+    pub date: String,
+    pub reason: Option<String>,
+    pub total_amount: f64,
+}
+
+#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone)]
+#[diesel(table_name = credit_note_items)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct CreditNoteItem {
+    pub id: i32,
+    pub credit_note_id: i32,
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: f64,
+    pub total: f64,
+    pub tax_rate: Option<f64>,
+}
+
+#[derive(Queryable, Identifiable, Associations, Selectable, Debug, PartialEq, Clone)]
+#[diesel(belongs_to(Invoice))]
+#[diesel(table_name = payments)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Payment {
+    pub id: i32,
+    pub invoice_id: i32,
+    pub amount: f64,
+    pub date: String,
+    pub method: Option<String>,
+    pub reference: Option<String>,
+    /// Set for imported payments whose date was not recorded; `date` is then
+    /// the invoice's due date
+    pub date_unknown: bool,
+}
+
+#[derive(Queryable, Identifiable, Associations, Selectable, Debug, PartialEq, Clone)]
+#[diesel(belongs_to(Client))]
+#[diesel(belongs_to(Company))]
+#[diesel(table_name = quotes)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Quote {
+    pub id: i32,
+    pub company_id: Option<i32>,
+    pub company_name: String,
+    pub company_address: Option<String>,
+    pub company_email: Option<String>,
+    pub company_phone: Option<String>,
+    pub client_id: Option<i32>,
+    pub client_name: String,
+    pub client_address: Option<String>,
+    pub client_email: Option<String>,
+    pub client_phone: Option<String>,
+    pub date: String,
+    pub valid_until: String,
+    pub total_amount: f64,
+    pub notes: Option<String>,
+    pub status: String,
+    pub invoice_id: Option<i32>,
+}
+
+#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone)]
+#[diesel(table_name = quote_items)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct QuoteItem {
+    pub id: i32,
+    pub quote_id: i32,
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: f64,
+    pub total: f64,
+    pub tax_rate: Option<f64>,
+}
+
+#[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone)]
+#[diesel(table_name = recurring_invoices)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct RecurringInvoice {
+    pub id: i32,
+    pub company_id: i32,
+    pub client_id: i32,
+    pub items: String,
+    pub notes: Option<String>,
+    pub interval: String,
+    pub day_of_month: i32,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    pub next_run_date: String,
+    pub active: bool,
 }
 
 #[derive(Insertable)]
@@ -114,6 +246,8 @@ pub struct NewInvoice {
     pub tax: Option<f64>,
     pub notes: Option<String>,
     pub regenerated: Option<bool>,
+    pub quote_id: Option<i32>,
+    pub due_date: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -124,4 +258,92 @@ pub struct NewItem {
     pub quantity: i32,
     pub unit_price: f64,
     pub total: f64,
+    pub tax_rate: Option<f64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = time_entries)]
+pub struct NewTimeEntry {
+    pub source: String,
+    pub external_id: String,
+    pub client_id: Option<i32>,
+    pub client_name: String,
+    pub project: Option<String>,
+    pub description: String,
+    pub duration_minutes: i32,
+    pub date: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = recurring_invoices)]
+pub struct NewRecurringInvoice {
+    pub company_id: i32,
+    pub client_id: i32,
+    pub items: String,
+    pub notes: Option<String>,
+    pub interval: String,
+    pub day_of_month: i32,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    pub next_run_date: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = quotes)]
+pub struct NewQuote {
+    pub company_id: Option<i32>,
+    pub company_name: String,
+    pub company_address: Option<String>,
+    pub company_email: Option<String>,
+    pub company_phone: Option<String>,
+    pub client_id: Option<i32>,
+    pub client_name: String,
+    pub client_address: Option<String>,
+    pub client_email: Option<String>,
+    pub client_phone: Option<String>,
+    pub date: String,
+    pub valid_until: String,
+    pub total_amount: f64,
+    pub notes: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = quote_items)]
+pub struct NewQuoteItem {
+    pub quote_id: i32,
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: f64,
+    pub total: f64,
+    pub tax_rate: Option<f64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = credit_notes)]
+pub struct NewCreditNote {
+    pub invoice_id: i32,
+    pub date: String,
+    pub reason: Option<String>,
+    pub total_amount: f64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = credit_note_items)]
+pub struct NewCreditNoteItem {
+    pub credit_note_id: i32,
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: f64,
+    pub total: f64,
+    pub tax_rate: Option<f64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = payments)]
+pub struct NewPayment {
+    pub invoice_id: i32,
+    pub amount: f64,
+    pub date: String,
+    pub method: Option<String>,
+    pub reference: Option<String>,
 }
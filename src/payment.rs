@@ -0,0 +1,141 @@
+use crate::db::{establish_connection, repository, DbError};
+use crate::models::{Invoice, NewPayment, Payment};
+use crate::schema::{invoices, payments};
+use chrono::NaiveDate;
+use cli_table::{print_stdout, Cell, Style, Table};
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+pub struct PaymentArgs {
+    pub invoice_id: i32,
+    pub amount: Option<f64>,
+    pub date: Option<NaiveDate>,
+    pub method: Option<String>,
+    pub reference: Option<String>,
+}
+
+/// Total paid against each invoice.
+pub fn paid_by_invoice() -> Result<HashMap<i32, f64>, DbError> {
+    repository()?.paid_by_invoice()
+}
+
+pub fn all() -> Result<Vec<Payment>, DbError> {
+    repository()?.payments()
+}
+
+/// Records a payment, defaulting to the outstanding balance and today's date.
+/// Returns the payment with the invoice it pays.
+pub fn record(args: PaymentArgs) -> Result<(Payment, Invoice), String> {
+    let conn = &mut *establish_connection()?;
+
+    let invoice = invoices::table
+        .find(args.invoice_id)
+        .first::<Invoice>(conn)
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Invoice with id {} not found", args.invoice_id))?;
+    crate::invoice::check_not_regenerated(&invoice)?;
+
+    let paid = paid_by_invoice()?
+        .get(&invoice.id)
+        .copied()
+        .unwrap_or_default();
+    let credited = crate::credit_note::credited_by_invoice()?
+        .get(&invoice.id)
+        .copied()
+        .unwrap_or_default();
+    let outstanding = invoice.total_amount - credited - paid;
+
+    // Anything under half a cent is float noise from the sums above
+    if outstanding < 0.005 {
+        return Err(format!(
+            "Invoice {} has no outstanding balance",
+            invoice.number()
+        ));
+    }
+
+    let amount = args.amount.unwrap_or(outstanding);
+    if amount <= 0.0 {
+        return Err("Payment amount must be positive".to_string());
+    }
+    if amount > outstanding + 0.005 {
+        return Err(format!(
+            "Payment of {:.2} is more than the {:.2} outstanding on invoice {}",
+            amount,
+            outstanding,
+            invoice.number()
+        ));
+    }
+
+    let date = args
+        .date
+        .unwrap_or_else(|| chrono::offset::Local::now().date_naive());
+
+    let payment = diesel::insert_into(payments::table)
+        .values(&NewPayment {
+            invoice_id: invoice.id,
+            amount,
+            date: date.format("%Y-%m-%d").to_string(),
+            method: args.method,
+            reference: args.reference,
+        })
+        .returning(Payment::as_returning())
+        .get_result(conn)
+        .map_err(|e| format!("Error saving payment: {}", e))?;
+
+    Ok((payment, invoice))
+}
+
+pub fn list(invoice_id: Option<i32>) -> Result<Vec<Payment>, DbError> {
+    let conn = &mut *establish_connection()?;
+
+    let mut query = payments::table.into_boxed();
+
+    if let Some(invoice_id) = invoice_id {
+        query = query.filter(payments::invoice_id.eq(invoice_id));
+    }
+
+    let list = query
+        .order((payments::date.asc(), payments::id.asc()))
+        .load::<Payment>(conn)?;
+
+    payment_table(&list, &crate::invoice::numbers()?);
+
+    Ok(list)
+}
+
+/// Prints payments with the number of the invoice each one pays, looked up
+/// in `invoice_numbers`.
+fn payment_table(payments: &[Payment], invoice_numbers: &HashMap<i32, String>) {
+    let payments = payments
+        .iter()
+        .map(|payment| {
+            vec![
+                payment.id.to_string().cell(),
+                invoice_numbers
+                    .get(&payment.invoice_id)
+                    .cloned()
+                    .unwrap_or_default()
+                    .cell(),
+                payment.date.clone().cell(),
+                format!("{:.2}", payment.amount).cell(),
+                payment.method.clone().unwrap_or_default().cell(),
+                payment.reference.clone().unwrap_or_default().cell(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let table = payments
+        .table()
+        .title(vec![
+            "ID".cell(),
+            "Invoice".cell(),
+            "Date".cell(),
+            "Amount".cell(),
+            "Method".cell(),
+            "Reference".cell(),
+        ])
+        .bold(true);
+
+    print_stdout(table).expect("Error printing the table");
+}
@@ -1,7 +1,9 @@
 use crate::config;
-use crate::invoice::{self, create_invoice_with_items};
-use crate::models::{Client, Company, NewInvoice};
+use crate::db::DbError;
+use crate::models::{Client, Company, Invoice, NewInvoice, NewItem};
+use crate::service::{self, GeneratedInvoice};
 use base64::{engine::general_purpose, Engine as _};
+use chrono::NaiveDate;
 use handlebars::Handlebars;
 use handlebars::{Context, Helper, HelperDef, HelperResult, Output, RenderContext};
 use headless_chrome::{types::PrintToPdfOptions, Browser, LaunchOptionsBuilder};
@@ -10,11 +12,10 @@ use serde_json::json;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::Duration;
 use text_colorizer::*;
-use urlencoding;
 
 struct AddressHelper;
 
@@ -42,10 +43,36 @@ pub struct Item {
     pub description: String,
     pub quantity: u32,
     pub price: f64,
+    /// Tax rate in percent; `None` marks the line as exempt from tax
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_rate: Option<f64>,
+}
+
+impl Item {
+    pub fn net(&self) -> f64 {
+        self.price * self.quantity as f64
+    }
+
+    pub fn tax(&self) -> f64 {
+        self.net() * self.tax_rate.unwrap_or_default() / 100.0
+    }
+
+    /// The stored line; the invoice id is filled in when the invoice is saved.
+    pub fn new_item(&self) -> NewItem {
+        NewItem {
+            invoice_id: 0,
+            description: self.description.clone(),
+            quantity: self.quantity as i32,
+            unit_price: self.price,
+            total: self.net(),
+            tax_rate: self.tax_rate,
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct CompanyPdf {
+    pub id: Option<i32>,
     pub name: Option<String>,
     pub address: Option<String>,
     pub email: Option<String>,
@@ -54,21 +81,26 @@ pub struct CompanyPdf {
 
 #[derive(Default)]
 pub struct ClientPdf {
+    pub id: Option<i32>,
     pub name: String,
     pub address: Option<String>,
     pub email: Option<String>,
     pub phone: Option<String>,
 }
 
-fn get_total_amount(items: &[Item]) -> f64 {
-    let total_amount = items
-        .iter()
-        .fold(0.0, |acc, item| acc + item.price * item.quantity as f64);
-    (total_amount * 100.0).round() / 100.0
+pub fn get_tax_amount(items: &[Item]) -> f64 {
+    let tax_amount = items.iter().fold(0.0, |acc, item| acc + item.tax());
+    (tax_amount * 100.0).round() / 100.0
+}
+
+/// Total of the items including tax.
+pub fn get_total_amount(items: &[Item]) -> f64 {
+    let total_amount = items.iter().fold(0.0, |acc, item| acc + item.net());
+    ((total_amount * 100.0).round() + (get_tax_amount(items) * 100.0).round()) / 100.0
 }
 
-fn get_image_data_url() -> Result<String, Box<dyn std::error::Error>> {
-    let config = config::load_config();
+pub fn get_image_data_url() -> Result<String, Box<dyn std::error::Error>> {
+    let config = config::load_config()?;
     // Read the image file into a byte vector
     let image_data = fs::read(config.logo_path)?;
     // Base64 encode the image data
@@ -88,15 +120,21 @@ fn process_template(
     Ok(rendered)
 }
 
-fn write_pdf(rendered: String, invoice_number: String) -> Result<(), Box<dyn std::error::Error>> {
+/// Where the PDF of an invoice is saved: `<invoice_path>/<year>/<month>/<number>.pdf`.
+pub fn invoice_pdf_path(invoice_path: &str, invoice_id: i32, date: NaiveDate) -> PathBuf {
+    Path::new(invoice_path)
+        .join(date.format("%Y").to_string())
+        .join(date.format("%b").to_string())
+        .join(format!("{:05}.pdf", invoice_id))
+}
+
+/// Prints rendered HTML to PDF with headless Chrome.
+fn print_to_pdf(rendered: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let options = LaunchOptionsBuilder::default().build()?;
     let browser = Browser::new(options)?;
     let tab = browser.new_tab()?;
 
-    tab.navigate_to(&format!(
-        "data:text/html,{}",
-        urlencoding::encode(&rendered)
-    ))?;
+    tab.navigate_to(&format!("data:text/html,{}", urlencoding::encode(rendered)))?;
     while tab.find_element("body").is_err() {
         thread::sleep(Duration::from_millis(200)); // check every 100 milliseconds
     }
@@ -106,43 +144,95 @@ fn write_pdf(rendered: String, invoice_number: String) -> Result<(), Box<dyn std
         ..Default::default()
     });
 
-    let pdf_data = tab.print_to_pdf(pdf_options)?;
-
-    let today = chrono::offset::Local::now();
+    Ok(tab.print_to_pdf(pdf_options)?)
+}
 
-    let year = today.format("%Y");
-    let month = today.format("%b");
+/// Saves a PDF as `<invoice_path>/<year>/<month>/<file_name>.pdf`.
+pub fn save_pdf(
+    pdf_data: &[u8],
+    file_name: &str,
+    date: NaiveDate,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let year = date.format("%Y");
+    let month = date.format("%b");
 
-    let config = config::load_config();
+    let config = config::load_config()?;
 
     // Construct path
-    let path_str = format!(
+    let path = PathBuf::from(format!(
         "{}/{}/{}/{}.pdf",
-        config.invoice_path, year, month, invoice_number
-    );
-
-    let path = Path::new(&path_str);
+        config.invoice_path, year, month, file_name
+    ));
 
-    // Create directories if they don't exist
-    if let Err(e) = fs::create_dir_all(path.parent().unwrap()) {
-        println!("Failed to create directories: {}", e);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
     }
 
-    let mut file = File::create(&path_str)?;
-    file.write_all(&pdf_data)?;
+    let mut file = File::create(&path)?;
+    file.write_all(pdf_data)?;
+
+    Ok(path)
+}
 
-    println!("Invoice PDF saved to: {}", &path_str.bright_black());
+fn write_pdf(
+    rendered: String,
+    invoice_number: String,
+    date: NaiveDate,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pdf_data = print_to_pdf(&rendered)?;
+    let path = save_pdf(&pdf_data, &invoice_number, date)?;
+
+    println!(
+        "PDF saved to: {}",
+        path.display().to_string().bright_black()
+    );
 
     Ok(())
 }
 
-fn get_company(company_name: Option<String>) -> Result<Company, Box<dyn std::error::Error>> {
+/// Renders a saved invoice to PDF bytes, with the details stored on it.
+pub fn render_invoice(
+    invoice: &Invoice,
+    items: &[Item],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let due_date = invoice
+        .due_date
+        .as_deref()
+        .and_then(crate::utils::parse_date)
+        .map(|date| date.format("%d %B %Y").to_string())
+        .unwrap_or_default();
+
+    let data = json!({
+        "invoice_number": invoice.number(),
+        "created_date": invoice.date,
+        "due_date": due_date,
+        "client_name": invoice.client_name,
+        "client_address": invoice.client_address.clone().unwrap_or_default(),
+        "client_email": invoice.client_email.clone().unwrap_or_default(),
+        "client_phone": invoice.client_phone.clone().unwrap_or_default(),
+        "company_name": invoice.company_name,
+        "company_address": invoice.company_address.clone().unwrap_or_default(),
+        "company_email": invoice.company_email.clone().unwrap_or_default(),
+        "company_phone": invoice.company_phone.clone().unwrap_or_default(),
+        "items": items,
+        "total": invoice.total_amount,
+        "logo_url": get_image_data_url()?,
+        "tax": format!("{:.2}", invoice.tax.unwrap_or_else(|| get_tax_amount(items))),
+        "notes": invoice.notes.clone().unwrap_or_default(),
+    });
+
+    let rendered = process_template(invoice_template()?, data)?;
+
+    print_to_pdf(&rendered)
+}
+
+pub fn get_company(company_name: Option<String>) -> Result<Company, Box<dyn std::error::Error>> {
     let company = match company_name {
         Some(company_name) => crate::models::Company::search_by(
             crate::company::CompanySearchField::Name,
             &company_name,
-        ),
-        None => vec![crate::models::Company::default_company()],
+        )?,
+        None => vec![crate::models::Company::default_company()?],
     };
 
     if company.is_empty() {
@@ -161,8 +251,8 @@ fn get_company(company_name: Option<String>) -> Result<Company, Box<dyn std::err
     }
 }
 
-fn get_client(client_name: String) -> Result<Client, Box<dyn std::error::Error>> {
-    let clients = crate::client::search_by_name(&client_name, Some(false));
+pub fn get_client(client_name: String) -> Result<Client, Box<dyn std::error::Error>> {
+    let clients = crate::client::search_by_name(&client_name, Some(false))?;
 
     if clients.is_empty() {
         return Err(format!("{}", "Client not found".red()).into());
@@ -180,124 +270,89 @@ fn get_client(client_name: String) -> Result<Client, Box<dyn std::error::Error>>
     }
 }
 
-pub fn generate_pdf(
+/// The profile's custom invoice template, or the built-in one.
+fn invoice_template() -> Result<String, Box<dyn std::error::Error>> {
+    let template_path = config::load_config()?.template_path;
+    if template_path.is_empty() {
+        return Ok(include_str!("template.html").to_string());
+    }
+
+    fs::read_to_string(&template_path)
+        .map_err(|e| format!("Unable to read template {}: {}", template_path, e).into())
+}
+
+/// Renders a document other than an invoice (quote, credit note) with the shared
+/// template and saves it alongside the invoices as `<file_name>.pdf`.
+pub fn render_document(
+    data: serde_json::Value,
+    file_name: String,
+    date: NaiveDate,
+) -> Result<(), Box<dyn std::error::Error>> {
+    render_with_template(&invoice_template()?, data, file_name, date)
+}
+
+/// Renders `data` with a dedicated template, such as the client statement.
+pub fn render_with_template(
+    template: &str,
+    data: serde_json::Value,
+    file_name: String,
+    date: NaiveDate,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rendered = process_template(template.to_string(), data)?;
+
+    write_pdf(rendered, file_name, date)
+}
+
+/// The invoice record for the given company and client details, dated `date`
+/// or today. A company without a name is the default company.
+pub fn new_invoice(
     company: CompanyPdf,
     client: ClientPdf,
-    items: Vec<Item>,
+    items: &[Item],
     notes: Option<String>,
-    custom: bool,
     regen: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // let mut file = File::open("template.html")?;
-    // let mut template_string = String::new();
-    // file.read_to_string(&mut template_string)?;
-
-    let template_string = include_str!("template.html").to_string();
-
-    let total_amount = get_total_amount(&items);
-    let image_data_url = get_image_data_url()?;
-
-    //todays day Month Year
-    let today = chrono::offset::Local::now();
-
-    let invoice_number = invoice::latest_id() + 1;
-    let formatted_invoice_number = format!("{:05}", invoice_number);
-
-    let data = if custom {
-        let company_name = company
-            .name
-            .unwrap_or(crate::models::Company::default_company().name);
-
-        json!({
-            "invoice_number": formatted_invoice_number,
-            "created_date": today.format("%d %B %Y").to_string(),
-            "client_name": client.name,
-            "client_address": client.address.unwrap_or_default(),
-            "client_email": client.email.unwrap_or_default(),
-            "client_phone": client.phone.unwrap_or_default(),
-            "company_name": company_name,
-            "company_address": company.address.unwrap_or_default(),
-            "company_email": company.email.unwrap_or_default(),
-            "company_phone": company.phone.unwrap_or_default(),
-            "items": items,
-            "total": total_amount,
-            "logo_url": image_data_url,
-            "tax": "0.00",
-            "notes": notes.unwrap_or_default(),
-        })
-    } else {
-        let company = get_company(company.name)?;
-        let client = get_client(client.name)?;
-
-        let company_name = company.name;
-        let company_address = company.address.unwrap_or_default();
-
-        let client_name = client.name;
-        let client_address = client.address.unwrap_or_default();
-
-        json!({
-            "invoice_number": formatted_invoice_number,
-            "created_date": today.format("%d %B %Y").to_string(),
-            "client_name": client_name,
-            "client_address": client_address,
-            "company_name": company_name,
-            "company_address": company_address,
-            "items": items,
-            "total": total_amount,
-            "logo_url": image_data_url,
-            "tax": "0.00",
-            "notes": notes.unwrap_or_default(),
-        })
+    date: Option<NaiveDate>,
+) -> Result<NewInvoice, DbError> {
+    let date = date.unwrap_or_else(|| chrono::offset::Local::now().date_naive());
+    let due_date = date + chrono::Duration::days(config::load_config()?.payment_terms_days);
+
+    let company_name = match company.name {
+        Some(name) => name,
+        None => Company::default_company()?.name,
     };
 
-    print!("{}", "Generating invoice... \n".yellow());
-
-    let rendered = process_template(template_string, data.clone())?;
-
-    let pdf = write_pdf(rendered, formatted_invoice_number);
-
-    match pdf {
-        Ok(_) => {
-            create_invoice_with_items(
-                &NewInvoice {
-                    company_id: Some(1), // This should be the actual company_id
-                    company_name: data["company_name"]
-                        .as_str()
-                        .map_or(String::new(), ToString::to_string),
-                    company_address: data["company_address"].as_str().map(|s| s.to_string()),
-                    company_email: data["company_email"].as_str().map(|s| s.to_string()),
-                    company_phone: data["company_phone"].as_str().map(|s| s.to_string()),
-                    client_id: Some(1), // This should be the actual client_id
-                    client_name: data["client_name"]
-                        .as_str()
-                        .map_or(String::new(), ToString::to_string),
-                    client_address: data["client_address"].as_str().map(|s| s.to_string()),
-                    client_email: data["client_email"].as_str().map(|s| s.to_string()),
-                    client_phone: data["client_phone"].as_str().map(|s| s.to_string()),
-                    date: data["created_date"]
-                        .as_str()
-                        .map_or(String::new(), ToString::to_string),
-                    total_amount: data["total"].as_f64().map_or(0.0, |v| v),
-                    tax: data["tax"].as_f64(),
-                    notes: data["notes"].as_str().map(|s| s.to_string()),
-                    regenerated: Some(regen),
-                },
-                items
-                    .into_iter()
-                    .map(|item| crate::models::NewItem {
-                        invoice_id: 0,
-                        description: item.description,
-                        quantity: item.quantity as i32,
-                        unit_price: item.price,
-                        total: (item.price * item.quantity as f64),
-                    })
-                    .collect::<Vec<crate::models::NewItem>>(),
-            );
-        }
-        Err(e) => {
-            print!("{}", format!("Error generating invoice: {}", e).red());
-        }
-    }
+    Ok(NewInvoice {
+        company_id: company.id,
+        company_name,
+        company_address: company.address,
+        company_email: company.email,
+        company_phone: company.phone,
+        client_id: client.id,
+        client_name: client.name,
+        client_address: client.address,
+        client_email: client.email,
+        client_phone: client.phone,
+        date: date.format("%d %B %Y").to_string(),
+        total_amount: get_total_amount(items),
+        tax: Some(get_tax_amount(items)),
+        notes: notes.filter(|notes| !notes.trim().is_empty()),
+        regenerated: Some(regen),
+        quote_id: None,
+        due_date: Some(due_date.format("%Y-%m-%d").to_string()),
+    })
+}
 
-    Ok(())
+/// Issues an invoice for the given details through
+/// [`service::issue_invoice`], like every other invoice.
+pub fn generate_pdf(
+    company: CompanyPdf,
+    client: ClientPdf,
+    items: Vec<Item>,
+    notes: Option<String>,
+    regen: bool,
+    date: Option<NaiveDate>,
+) -> Result<GeneratedInvoice, service::Error> {
+    let new_invoice = new_invoice(company, client, &items, notes, regen, date)?;
+
+    service::issue_invoice(&new_invoice, &items)
 }
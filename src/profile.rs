@@ -0,0 +1,157 @@
+use crate::company::create_company_prompts;
+use crate::config::{self, Profile, DEFAULT_PROFILE};
+use crate::db::run_migration;
+use cli_table::{print_stdout, Cell, Style, Table};
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn list() -> Result<(), String> {
+    let stored = config::read_config()?;
+    let active = config::profile_name()?;
+
+    let default = (DEFAULT_PROFILE.to_string(), stored.profile());
+    let rows = std::iter::once(default)
+        .chain(stored.profiles.clone())
+        .map(|(name, profile)| {
+            let marker = if name == active { "*" } else { "" };
+            vec![
+                marker.cell(),
+                name.cell(),
+                profile.database_url.cell(),
+                profile.invoice_path.cell(),
+                profile.default_company.cell(),
+                if profile.template_path.is_empty() {
+                    "built-in".to_string()
+                } else {
+                    profile.template_path
+                }
+                .cell(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let table = rows
+        .table()
+        .title(vec![
+            "Active".cell(),
+            "Name".cell(),
+            "Database".cell(),
+            "Invoice Path".cell(),
+            "Default Company".cell(),
+            "Template".cell(),
+        ])
+        .bold(true);
+
+    print_stdout(table).map_err(|e| e.to_string())
+}
+
+pub struct CreateArgs {
+    pub name: String,
+    pub database_url: Option<PathBuf>,
+    pub invoice_path: Option<PathBuf>,
+    pub logo_path: Option<String>,
+    pub template_path: Option<String>,
+    pub company_name: Option<String>,
+}
+
+/// Creates a profile with its own database, then prompts for its company.
+/// Paths default to `~/clinvoice/<name>/`, and the logo to the current one.
+pub fn create(args: CreateArgs) -> Result<(), String> {
+    let mut stored = config::read_config()?;
+
+    if args.name == DEFAULT_PROFILE || stored.profiles.contains_key(&args.name) {
+        return Err(format!("Profile {} already exists", args.name));
+    }
+    if args.name.is_empty() || args.name.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid profile name {}", args.name));
+    }
+
+    let profile_dir = dirs::home_dir()
+        .ok_or("Home directory not found")?
+        .join("clinvoice")
+        .join(&args.name);
+
+    let database_url = args
+        .database_url
+        .unwrap_or_else(|| profile_dir.join("cli_invoice.sqlite3"));
+    let shared = std::iter::once(stored.profile())
+        .chain(stored.profiles.values().cloned())
+        .any(|profile| Path::new(&profile.database_url) == database_url);
+    if shared {
+        return Err(format!(
+            "{} is used by another profile; profiles need their own database",
+            database_url.display()
+        ));
+    }
+    let invoice_path = args.invoice_path.unwrap_or_else(|| profile_dir.clone());
+
+    let logo_path = match args.logo_path {
+        Some(logo_path) => logo_path,
+        None => config::load_config()?.logo_path,
+    };
+    if !config::is_valid_logo_path(&logo_path) {
+        return Err(format!("{} is not a png, jpeg or svg file", logo_path));
+    }
+    let template_path = args.template_path.unwrap_or_default();
+    if !template_path.is_empty() && !Path::new(&template_path).is_file() {
+        return Err(format!("Template {} does not exist", template_path));
+    }
+
+    for dir in [database_url.parent(), Some(invoice_path.as_path())]
+        .into_iter()
+        .flatten()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Unable to create {}: {}", dir.display(), e))?;
+    }
+
+    let database_url = database_url.display().to_string();
+    let mut conn = SqliteConnection::establish(&database_url)
+        .map_err(|e| format!("Error creating {}: {}", database_url, e))?;
+    run_migration(&mut conn)?;
+
+    stored.profiles.insert(
+        args.name.clone(),
+        Profile {
+            database_url,
+            default_company: -1,
+            logo_path,
+            invoice_path: invoice_path.display().to_string(),
+            template_path,
+        },
+    );
+    config::write_config(&stored)?;
+
+    // The company is created in the new profile's database
+    config::use_profile(Some(args.name.clone()));
+    let company = create_company_prompts(args.company_name, None, None, None)?;
+
+    let mut config = config::load_config()?;
+    config.default_company = company.id;
+    config::save_config(&config)?;
+
+    Ok(())
+}
+
+pub fn switch(name: &str) -> Result<(), String> {
+    let mut stored = config::read_config()?;
+
+    stored.active_profile = if name == DEFAULT_PROFILE {
+        None
+    } else if stored.profiles.contains_key(name) {
+        Some(name.to_string())
+    } else {
+        return Err(format!("Profile {} not found", name));
+    };
+    config::write_config(&stored)?;
+
+    Ok(())
+}
+
+/// Checks a `--profile` before anything is loaded with it.
+pub fn exists(name: &str) -> Result<bool, String> {
+    Ok(name == DEFAULT_PROFILE || config::read_config()?.profiles.contains_key(name))
+}
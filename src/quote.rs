@@ -0,0 +1,415 @@
+use crate::db::{establish_connection, DbError};
+use crate::invoice::collect_items;
+use crate::models::{NewInvoice, NewQuote, NewQuoteItem, Quote, QuoteItem};
+use crate::pdf::{self, ClientPdf, CompanyPdf};
+use crate::repository::Repository;
+use crate::schema::{quote_items, quotes};
+use crate::service;
+use crate::utils::{self, get_input};
+use chrono::{Duration, NaiveDate};
+use cli_table::{print_stdout, Cell, Style, Table};
+use diesel::prelude::*;
+use serde_json::json;
+use text_colorizer::*;
+
+pub const STATUS_ACCEPTED: &str = "accepted";
+pub const STATUS_DECLINED: &str = "declined";
+
+// Both the quote date and its validity date are stored the way invoice dates are
+const DATE_FORMAT: &str = "%d %B %Y";
+
+pub struct QuoteArgs {
+    pub company_name: Option<String>,
+    pub client_name: Option<String>,
+    pub items: Option<Vec<String>>,
+    pub notes: Option<String>,
+    pub valid_days: i64,
+}
+
+pub fn format_quote_number(id: i32) -> String {
+    format!("Q-{:05}", id)
+}
+
+pub fn create(args: QuoteArgs) -> Result<i32, Box<dyn std::error::Error>> {
+    let client_name = match args.client_name {
+        Some(client_name) => client_name,
+        None => loop {
+            let client_name = get_input("Enter the client name: ");
+            if !client_name.trim().is_empty() {
+                break client_name;
+            }
+            println!("{}", "Client name cannot be empty".red());
+        },
+    };
+
+    let company = pdf::get_company(args.company_name)?;
+    let client = pdf::get_client(client_name)?;
+
+    let items = args.items.unwrap_or_else(|| collect_items(false));
+    let items = items
+        .iter()
+        .map(|item| {
+            serde_json::from_str::<pdf::Item>(item)
+                .map_err(|e| format!("Error parsing item {}: {}", item, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if items.is_empty() {
+        return Err("A quote needs at least one item".into());
+    }
+
+    let date = chrono::offset::Local::now().date_naive();
+    let valid_until = date + Duration::days(args.valid_days);
+
+    let conn = &mut *establish_connection()?;
+
+    // The quote and its items are saved together, then rendered under the
+    // number of the saved record
+    let quote = conn.transaction(|conn| {
+        let quote = diesel::insert_into(quotes::table)
+            .values(&NewQuote {
+                company_id: Some(company.id),
+                company_name: company.name,
+                company_address: company.address,
+                company_email: company.email,
+                company_phone: company.phone,
+                client_id: Some(client.id),
+                client_name: client.name,
+                client_address: client.address,
+                client_email: client.email,
+                client_phone: client.phone,
+                date: date.format(DATE_FORMAT).to_string(),
+                valid_until: valid_until.format(DATE_FORMAT).to_string(),
+                total_amount: pdf::get_total_amount(&items),
+                notes: args.notes,
+            })
+            .returning(Quote::as_returning())
+            .get_result(conn)?;
+
+        for item in &items {
+            diesel::insert_into(quote_items::table)
+                .values(&NewQuoteItem {
+                    quote_id: quote.id,
+                    description: item.description.clone(),
+                    quantity: item.quantity as i32,
+                    unit_price: item.price,
+                    total: item.net(),
+                    tax_rate: item.tax_rate,
+                })
+                .execute(conn)?;
+        }
+
+        Ok::<_, DbError>(quote)
+    })?;
+
+    print!("{}", "Generating quote... \n".yellow());
+
+    if let Err(e) = render(&quote, &items, date) {
+        conn.transaction(|conn| {
+            diesel::delete(quote_items::table.filter(quote_items::quote_id.eq(quote.id)))
+                .execute(conn)?;
+            diesel::delete(quotes::table.find(quote.id)).execute(conn)
+        })
+        .map_err(DbError::from)?;
+        return Err(e);
+    }
+
+    Ok(quote.id)
+}
+
+/// Renders a saved quote under its own number.
+fn render(
+    quote: &Quote,
+    items: &[pdf::Item],
+    date: NaiveDate,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = json!({
+        "document_title": "QUOTE",
+        "number_label": "Quote #",
+        "invoice_number": format_quote_number(quote.id),
+        "created_date": quote.date,
+        "valid_until": quote.valid_until,
+        "client_name": quote.client_name,
+        "client_address": quote.client_address.clone().unwrap_or_default(),
+        "client_email": quote.client_email.clone().unwrap_or_default(),
+        "client_phone": quote.client_phone.clone().unwrap_or_default(),
+        "company_name": quote.company_name,
+        "company_address": quote.company_address.clone().unwrap_or_default(),
+        "company_email": quote.company_email.clone().unwrap_or_default(),
+        "company_phone": quote.company_phone.clone().unwrap_or_default(),
+        "items": items,
+        "total": quote.total_amount,
+        "logo_url": pdf::get_image_data_url()?,
+        "tax": format!("{:.2}", pdf::get_tax_amount(items)),
+        "notes": quote.notes.clone().unwrap_or_default(),
+    });
+
+    pdf::render_document(data, format_quote_number(quote.id), date)
+}
+
+pub fn find_by_id(id: i32) -> Result<Option<(Quote, Vec<QuoteItem>)>, DbError> {
+    let conn = &mut *establish_connection()?;
+
+    let Some(quote) = quotes::table.find(id).first::<Quote>(conn).optional()? else {
+        return Ok(None);
+    };
+
+    let items = quote_items::table
+        .filter(quote_items::quote_id.eq(id))
+        .load::<QuoteItem>(conn)?;
+
+    Ok(Some((quote, items)))
+}
+
+pub fn list(status: Option<String>) -> Result<Vec<Quote>, DbError> {
+    let conn = &mut *establish_connection()?;
+
+    let mut query = quotes::table.into_boxed();
+
+    if let Some(status) = status {
+        query = query.filter(quotes::status.eq(status));
+    }
+
+    let list = query.order(quotes::id.asc()).load::<Quote>(conn)?;
+
+    quote_table(&list);
+
+    Ok(list)
+}
+
+pub fn show(id: i32) -> Result<(), DbError> {
+    match find_by_id(id)? {
+        Some((quote, items)) => {
+            quote_table(std::slice::from_ref(&quote));
+            crate::invoice::items_table(
+                &items
+                    .into_iter()
+                    .map(|item| crate::models::Item {
+                        id: item.id,
+                        invoice_id: item.quote_id,
+                        description: item.description,
+                        quantity: item.quantity,
+                        unit_price: item.unit_price,
+                        total: item.total,
+                        tax_rate: item.tax_rate,
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+        None => println!("{} {}", "No quote found using id:".red(), id),
+    }
+
+    Ok(())
+}
+
+pub fn set_status(id: i32, status: &str) -> Result<(), String> {
+    let (quote, _) = find_by_id(id)?.ok_or_else(|| format!("Quote with id {} not found", id))?;
+
+    if quote.invoice_id.is_some() {
+        return Err(format!(
+            "Quote {} has already been converted into an invoice",
+            format_quote_number(quote.id)
+        ));
+    }
+
+    let conn = &mut *establish_connection()?;
+
+    diesel::update(quotes::table.find(id))
+        .set(quotes::status.eq(status))
+        .execute(conn)
+        .map_err(|e| format!("Error updating quote: {}", e))?;
+
+    Ok(())
+}
+
+/// Refuses quotes that were converted already or declined.
+fn check_convertible(quote: &Quote) -> Result<(), String> {
+    if let Some(invoice_id) = quote.invoice_id {
+        return Err(format!(
+            "Quote {} was already converted into invoice {:05}",
+            format_quote_number(quote.id),
+            invoice_id
+        ));
+    }
+
+    if quote.status == STATUS_DECLINED {
+        return Err(format!(
+            "Quote {} was declined and cannot be converted",
+            format_quote_number(quote.id)
+        ));
+    }
+
+    Ok(())
+}
+
+/// Issues an invoice carrying over every item of the quote and links the two.
+pub fn convert(id: i32) -> Result<i32, Box<dyn std::error::Error>> {
+    let (quote, items) =
+        find_by_id(id)?.ok_or_else(|| format!("Quote with id {} not found", id))?;
+
+    check_convertible(&quote)?;
+
+    let today = chrono::offset::Local::now().date_naive();
+    if utils::parse_date(&quote.valid_until).is_some_and(|d| d < today) {
+        println!(
+            "{}",
+            format!(
+                "Warning: quote {} expired on {}",
+                format_quote_number(quote.id),
+                quote.valid_until
+            )
+            .yellow()
+        );
+    }
+
+    let company = CompanyPdf {
+        id: quote.company_id,
+        name: Some(quote.company_name),
+        address: quote.company_address,
+        email: quote.company_email,
+        phone: quote.company_phone,
+    };
+    let client = ClientPdf {
+        id: quote.client_id,
+        name: quote.client_name,
+        address: quote.client_address,
+        email: quote.client_email,
+        phone: quote.client_phone,
+    };
+    let items = items
+        .into_iter()
+        .map(|item| pdf::Item {
+            description: item.description,
+            quantity: item.quantity as u32,
+            price: item.unit_price,
+            tax_rate: item.tax_rate,
+        })
+        .collect::<Vec<_>>();
+
+    let new_invoice = NewInvoice {
+        quote_id: Some(quote.id),
+        ..pdf::new_invoice(company, client, &items, quote.notes, false, None)?
+    };
+
+    // The invoice and the link back from the quote are saved together, so a
+    // failed link can't leave the quote open to being converted twice
+    let conn = &mut *establish_connection()?;
+    let invoice = conn.transaction(|conn| {
+        let id = conn.create_invoice(
+            &new_invoice,
+            items.iter().map(pdf::Item::new_item).collect(),
+        )?;
+        let linked = diesel::update(quotes::table.find(quote.id))
+            .filter(quotes::invoice_id.is_null())
+            .set((
+                quotes::invoice_id.eq(id),
+                quotes::status.eq(STATUS_ACCEPTED),
+            ))
+            .execute(conn)?;
+        if linked == 0 {
+            return Err(DbError::InvalidData(format!(
+                "Quote {} was converted by another run",
+                format_quote_number(quote.id)
+            )));
+        }
+        conn.find_invoice(id)?
+            .ok_or_else(|| DbError::NotFound(format!("Invoice with id {}", id)))
+    })?;
+
+    if let Err(e) = service::save_invoice_pdf(&invoice, &items) {
+        conn.transaction(|conn| {
+            diesel::update(quotes::table.find(quote.id))
+                .set((
+                    quotes::invoice_id.eq(None::<i32>),
+                    quotes::status.eq(&quote.status),
+                ))
+                .execute(conn)?;
+            conn.delete_invoice(invoice.id)
+        })?;
+        return Err(e.into());
+    }
+
+    Ok(invoice.id)
+}
+
+fn quote_table(quotes: &[Quote]) {
+    let quotes = quotes
+        .iter()
+        .map(|quote| {
+            vec![
+                quote.id.to_string().cell(),
+                format_quote_number(quote.id).cell(),
+                quote.client_name.clone().cell(),
+                quote.company_name.clone().cell(),
+                quote.date.clone().cell(),
+                quote.valid_until.clone().cell(),
+                format!("{:.2}", quote.total_amount).cell(),
+                quote.status.clone().cell(),
+                quote
+                    .invoice_id
+                    .map(|id| format!("{:05}", id))
+                    .unwrap_or_default()
+                    .cell(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let table = quotes
+        .table()
+        .title(vec![
+            "ID".cell(),
+            "Number".cell(),
+            "Client Name".cell(),
+            "Company Name".cell(),
+            "Date".cell(),
+            "Valid Until".cell(),
+            "Total Amount".cell(),
+            "Status".cell(),
+            "Invoice".cell(),
+        ])
+        .bold(true);
+
+    print_stdout(table).expect("Error printing the table");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(status: &str, invoice_id: Option<i32>) -> Quote {
+        Quote {
+            id: 7,
+            company_id: Some(1),
+            company_name: "Acme".to_string(),
+            company_address: None,
+            company_email: None,
+            company_phone: None,
+            client_id: Some(3),
+            client_name: "Carol".to_string(),
+            client_address: None,
+            client_email: None,
+            client_phone: None,
+            date: "01 October 2026".to_string(),
+            valid_until: "31 October 2026".to_string(),
+            total_amount: 120.0,
+            notes: None,
+            status: status.to_string(),
+            invoice_id,
+        }
+    }
+
+    #[test]
+    fn converts_pending_and_accepted_quotes() {
+        assert!(check_convertible(&quote("pending", None)).is_ok());
+        assert!(check_convertible(&quote(STATUS_ACCEPTED, None)).is_ok());
+    }
+
+    #[test]
+    fn refuses_declined_or_converted_quotes() {
+        let declined = check_convertible(&quote(STATUS_DECLINED, None)).unwrap_err();
+        assert!(declined.contains("Q-00007 was declined"));
+
+        let converted = check_convertible(&quote(STATUS_ACCEPTED, Some(12))).unwrap_err();
+        assert!(converted.contains("already converted into invoice 00012"));
+    }
+}
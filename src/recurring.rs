@@ -0,0 +1,424 @@
+use crate::db::{establish_connection, DbError};
+use crate::invoice::collect_items;
+use crate::models::{Client, Company, NewRecurringInvoice, RecurringInvoice};
+use crate::pdf::{self, ClientPdf, CompanyPdf};
+use crate::repository::Repository;
+use crate::schema::recurring_invoices;
+use crate::service;
+use chrono::{Datelike, Months, NaiveDate};
+use cli_table::{print_stdout, Cell, Style, Table};
+use diesel::prelude::*;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum RecurringInterval {
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl RecurringInterval {
+    fn months(&self) -> u32 {
+        match self {
+            RecurringInterval::Monthly => 1,
+            RecurringInterval::Quarterly => 3,
+            RecurringInterval::Yearly => 12,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            RecurringInterval::Monthly => "monthly",
+            RecurringInterval::Quarterly => "quarterly",
+            RecurringInterval::Yearly => "yearly",
+        }
+    }
+
+    fn parse(value: &str) -> Option<RecurringInterval> {
+        match value {
+            "monthly" => Some(RecurringInterval::Monthly),
+            "quarterly" => Some(RecurringInterval::Quarterly),
+            "yearly" => Some(RecurringInterval::Yearly),
+            _ => None,
+        }
+    }
+}
+
+pub struct RecurringArgs {
+    pub company_id: Option<i32>,
+    pub client_id: i32,
+    pub items: Option<Vec<String>>,
+    pub notes: Option<String>,
+    pub interval: RecurringInterval,
+    pub day_of_month: u32,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+}
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, DATE_FORMAT)
+        .map_err(|e| format!("Invalid date {}: {}", value, e))
+}
+
+/// Returns `day` in the month of `date`, clamped to the last day of that month.
+fn on_day(date: NaiveDate, day: u32) -> NaiveDate {
+    let first = date.with_day(1).expect("first day of month is valid");
+    let last = (first + Months::new(1)).pred_opt().expect("date in range");
+    first
+        .with_day(day.min(last.day()))
+        .expect("clamped day is valid")
+}
+
+fn next_run(date: NaiveDate, interval: RecurringInterval, day: u32) -> NaiveDate {
+    let date =
+        date.with_day(1).expect("first day of month is valid") + Months::new(interval.months());
+    on_day(date, day)
+}
+
+fn first_run(start: NaiveDate, interval: RecurringInterval, day: u32) -> NaiveDate {
+    let run = on_day(start, day);
+    if run < start {
+        next_run(run, interval, day)
+    } else {
+        run
+    }
+}
+
+pub fn create(args: RecurringArgs) -> Result<RecurringInvoice, String> {
+    if !(1..=31).contains(&args.day_of_month) {
+        return Err("Day of month must be between 1 and 31".to_string());
+    }
+
+    let company_id = match args.company_id {
+        Some(company_id) => company_id,
+        None => crate::config::load_config()?.default_company,
+    };
+
+    if Company::find_by_id(company_id)?.is_none() {
+        return Err(format!("Company with id {} not found", company_id));
+    }
+    if Client::find_by_id(args.client_id)?.is_none() {
+        return Err(format!("Client with id {} not found", args.client_id));
+    }
+
+    let items = args.items.unwrap_or_else(|| collect_items(false));
+    let items = items
+        .iter()
+        .map(|item| {
+            serde_json::from_str::<pdf::Item>(item)
+                .map_err(|e| format!("Error parsing item {}: {}", item, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if items.is_empty() {
+        return Err("A recurring invoice needs at least one item".to_string());
+    }
+
+    let start_date = args
+        .start_date
+        .unwrap_or_else(|| chrono::offset::Local::now().date_naive());
+
+    if let Some(end_date) = args.end_date {
+        if end_date < start_date {
+            return Err("End date must be after the start date".to_string());
+        }
+    }
+
+    let next_run_date = first_run(start_date, args.interval, args.day_of_month);
+
+    let new_recurring = NewRecurringInvoice {
+        company_id,
+        client_id: args.client_id,
+        items: serde_json::to_string(&items).map_err(|e| e.to_string())?,
+        notes: args.notes,
+        interval: args.interval.as_str().to_string(),
+        day_of_month: args.day_of_month as i32,
+        start_date: start_date.format(DATE_FORMAT).to_string(),
+        end_date: args.end_date.map(|d| d.format(DATE_FORMAT).to_string()),
+        next_run_date: next_run_date.format(DATE_FORMAT).to_string(),
+    };
+
+    let conn = &mut *establish_connection()?;
+
+    diesel::insert_into(recurring_invoices::table)
+        .values(&new_recurring)
+        .returning(RecurringInvoice::as_returning())
+        .get_result(conn)
+        .map_err(|e| format!("Error saving recurring invoice: {}", e))
+}
+
+pub fn list() -> Result<Vec<RecurringInvoice>, DbError> {
+    let conn = &mut *establish_connection()?;
+
+    let list = recurring_invoices::table
+        .order(recurring_invoices::next_run_date.asc())
+        .load::<RecurringInvoice>(conn)?;
+
+    recurring_table(&list)?;
+
+    Ok(list)
+}
+
+pub fn delete(id: i32) -> Result<(), String> {
+    let conn = &mut *establish_connection()?;
+
+    let deleted = diesel::delete(recurring_invoices::table.find(id))
+        .execute(conn)
+        .map_err(|e| format!("Error deleting recurring invoice: {}", e))?;
+
+    if deleted == 0 {
+        return Err(format!("Recurring invoice with id {} not found", id));
+    }
+
+    Ok(())
+}
+
+fn deactivate(conn: &mut SqliteConnection, id: i32) -> Result<(), DbError> {
+    diesel::update(recurring_invoices::table.find(id))
+        .set(recurring_invoices::active.eq(false))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Generates every invoice that is due up to `today`.
+///
+/// Each invoice is saved in the same transaction that moves the next run date
+/// on, then rendered, so running this again (or after a partial failure)
+/// never issues the same period twice.
+pub fn run(today: NaiveDate, dry_run: bool) -> Result<usize, String> {
+    let conn = &mut *establish_connection()?;
+
+    let schedules = recurring_invoices::table
+        .filter(recurring_invoices::active.eq(true))
+        .order(recurring_invoices::id.asc())
+        .load::<RecurringInvoice>(conn)
+        .map_err(DbError::from)?;
+
+    let mut generated = 0;
+    let mut failures = Vec::new();
+
+    for schedule in schedules {
+        let interval = RecurringInterval::parse(&schedule.interval)
+            .ok_or_else(|| format!("Unknown interval {}", schedule.interval))?;
+        let end_date = schedule.end_date.as_deref().map(parse_date).transpose()?;
+        let mut next = parse_date(&schedule.next_run_date)?;
+
+        let (Some(company), Some(client)) = (
+            Company::find_by_id(schedule.company_id)?,
+            Client::find_by_id(schedule.client_id)?,
+        ) else {
+            failures.push(format!(
+                "Recurring invoice {} references a missing company or client",
+                schedule.id
+            ));
+            continue;
+        };
+
+        while next <= today {
+            if end_date.is_some_and(|end| next > end) {
+                break;
+            }
+
+            if dry_run {
+                println!(
+                    "Would generate invoice for {} dated {}",
+                    client.name,
+                    next.format(DATE_FORMAT)
+                );
+                generated += 1;
+                next = next_run(next, interval, schedule.day_of_month as u32);
+                continue;
+            }
+
+            let items: Vec<pdf::Item> =
+                serde_json::from_str(&schedule.items).map_err(|e| e.to_string())?;
+
+            let company_pdf = CompanyPdf {
+                id: Some(company.id),
+                name: Some(company.name.clone()),
+                address: company.address.clone(),
+                email: company.email.clone(),
+                phone: company.phone.clone(),
+            };
+            let client_pdf = ClientPdf {
+                id: Some(client.id),
+                name: client.name.clone(),
+                address: client.address.clone(),
+                email: client.email.clone(),
+                phone: client.phone.clone(),
+            };
+
+            let after = next_run(next, interval, schedule.day_of_month as u32);
+            let issued = pdf::new_invoice(
+                company_pdf,
+                client_pdf,
+                &items,
+                schedule.notes.clone(),
+                false,
+                Some(next),
+            )
+            .and_then(|new_invoice| {
+                // The invoice and the schedule move on together, so a crash
+                // between the two can't issue the same period twice
+                conn.transaction(|conn| {
+                    let id = conn.create_invoice(
+                        &new_invoice,
+                        items.iter().map(pdf::Item::new_item).collect(),
+                    )?;
+                    diesel::update(recurring_invoices::table.find(schedule.id))
+                        .set(
+                            recurring_invoices::next_run_date
+                                .eq(after.format(DATE_FORMAT).to_string()),
+                        )
+                        .execute(conn)?;
+                    conn.find_invoice(id)?
+                        .ok_or_else(|| DbError::NotFound(format!("Invoice with id {}", id)))
+                })
+            });
+            let invoice = match issued {
+                Ok(invoice) => invoice,
+                Err(e) => {
+                    failures.push(format!("Recurring invoice {}: {}", schedule.id, e));
+                    break;
+                }
+            };
+
+            generated += 1;
+            next = after;
+
+            // The PDF is rendered once the invoice is saved; without Chrome
+            // the invoice stays and only the file is missing
+            if let Err(e) = service::save_invoice_pdf(&invoice, &items) {
+                failures.push(format!(
+                    "Recurring invoice {}: invoice {:05} was saved but not rendered: {}",
+                    schedule.id, invoice.id, e
+                ));
+                break;
+            }
+        }
+
+        if !dry_run && end_date.is_some_and(|end| next > end) {
+            deactivate(conn, schedule.id)?;
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(failures.join("\n"));
+    }
+
+    Ok(generated)
+}
+
+fn recurring_table(list: &[RecurringInvoice]) -> Result<(), DbError> {
+    let mut rows = Vec::new();
+    for recurring in list {
+        let client_name = Client::find_by_id(recurring.client_id)?
+            .map(|client| client.name)
+            .unwrap_or_default();
+        let total = serde_json::from_str::<Vec<pdf::Item>>(&recurring.items)
+            .map(|items| {
+                items
+                    .iter()
+                    .fold(0.0, |acc, item| acc + item.price * item.quantity as f64)
+            })
+            .unwrap_or_default();
+
+        rows.push(vec![
+            recurring.id.to_string().cell(),
+            client_name.cell(),
+            recurring.interval.clone().cell(),
+            recurring.day_of_month.to_string().cell(),
+            format!("{:.2}", total).cell(),
+            recurring.next_run_date.clone().cell(),
+            recurring.end_date.clone().unwrap_or_default().cell(),
+            if recurring.active { "yes" } else { "no" }.cell(),
+        ]);
+    }
+
+    let table = rows
+        .table()
+        .title(vec![
+            "ID".cell(),
+            "Client".cell(),
+            "Interval".cell(),
+            "Day".cell(),
+            "Total".cell(),
+            "Next Run".cell(),
+            "End Date".cell(),
+            "Active".cell(),
+        ])
+        .bold(true);
+
+    print_stdout(table).expect("Error printing the table");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        parse_date(value).unwrap()
+    }
+
+    #[test]
+    fn clamps_day_31_to_the_end_of_short_months() {
+        let monthly = RecurringInterval::Monthly;
+        assert_eq!(
+            next_run(date("2026-01-31"), monthly, 31),
+            date("2026-02-28")
+        );
+        assert_eq!(
+            next_run(date("2026-02-28"), monthly, 31),
+            date("2026-03-31")
+        );
+        assert_eq!(
+            next_run(date("2027-12-31"), monthly, 29),
+            date("2028-01-29")
+        );
+        assert_eq!(
+            next_run(date("2028-01-29"), monthly, 29),
+            date("2028-02-29")
+        );
+        assert_eq!(
+            next_run(date("2026-11-30"), RecurringInterval::Quarterly, 31),
+            date("2027-02-28")
+        );
+    }
+
+    #[test]
+    fn yearly_runs_keep_the_month_and_clamp_leap_days() {
+        let yearly = RecurringInterval::Yearly;
+        assert_eq!(next_run(date("2028-02-29"), yearly, 29), date("2029-02-28"));
+        assert_eq!(next_run(date("2029-02-28"), yearly, 29), date("2030-02-28"));
+        assert_eq!(next_run(date("2026-07-15"), yearly, 15), date("2027-07-15"));
+    }
+
+    #[test]
+    fn first_run_is_on_or_after_the_start_date() {
+        let monthly = RecurringInterval::Monthly;
+        assert_eq!(
+            first_run(date("2026-10-19"), monthly, 19),
+            date("2026-10-19")
+        );
+        assert_eq!(
+            first_run(date("2026-10-19"), monthly, 31),
+            date("2026-10-31")
+        );
+        assert_eq!(
+            first_run(date("2026-10-19"), monthly, 1),
+            date("2026-11-01")
+        );
+        assert_eq!(
+            first_run(date("2026-02-10"), monthly, 31),
+            date("2026-02-28")
+        );
+        assert_eq!(
+            first_run(date("2026-10-19"), RecurringInterval::Yearly, 1),
+            date("2027-10-01")
+        );
+    }
+}
@@ -0,0 +1,874 @@
+use crate::config::TaxBasis;
+use crate::db::{repository, DbError};
+use crate::models::{CreditNote, CreditNoteItem, Invoice, Item, Payment};
+use crate::repository::InvoiceFilter;
+use crate::utils::parse_date;
+use chrono::{Datelike, NaiveDate};
+use cli_table::{print_stdout, Cell, Style, Table};
+use indexmap::IndexMap;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ReportFormat {
+    #[default]
+    Table,
+    Csv,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ReportPeriod {
+    Month,
+    Quarter,
+    Year,
+}
+
+impl ReportPeriod {
+    fn key(&self, date: NaiveDate) -> String {
+        match self {
+            ReportPeriod::Month => date.format("%Y-%m").to_string(),
+            ReportPeriod::Quarter => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
+            ReportPeriod::Year => date.year().to_string(),
+        }
+    }
+}
+
+/// Optional inclusive date range applied to every report.
+#[derive(Clone, Copy, Default)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+/// A rendered report: a title, column headers and rows of JSON values, so the
+/// same data can be printed as a table, CSV or JSON.
+pub struct Report {
+    pub title: String,
+    pub headers: Vec<&'static str>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+pub fn money(amount: f64) -> Value {
+    json!((amount * 100.0).round() / 100.0)
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) if n.is_f64() => format!("{:.2}", n.as_f64().unwrap_or_default()),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// `"Paid Invoices"` -> `"paid_invoices"`, `"Share %"` -> `"share"`.
+fn json_key(header: &str) -> String {
+    header
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+impl Report {
+    pub fn print(&self, format: ReportFormat) -> Result<(), Box<dyn std::error::Error>> {
+        match format {
+            ReportFormat::Table => {
+                println!("{}\n", self.title);
+                let rows = self
+                    .rows
+                    .iter()
+                    .map(|row| row.iter().map(|v| display(v).cell()).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                let table = rows
+                    .table()
+                    .title(self.headers.iter().map(|h| h.cell()).collect::<Vec<_>>())
+                    .bold(true);
+                print_stdout(table)?;
+            }
+            ReportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(std::io::stdout());
+                writer.write_record(&self.headers)?;
+                for row in &self.rows {
+                    writer.write_record(row.iter().map(display))?;
+                }
+                writer.flush()?;
+            }
+            ReportFormat::Json => {
+                let rows = self
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        self.headers
+                            .iter()
+                            .map(|h| json_key(h))
+                            .zip(row.iter().cloned())
+                            .collect::<serde_json::Map<_, _>>()
+                    })
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::to_string_pretty(&rows)?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Invoices, credit notes and payments loaded once for a report run.
+pub struct Ledger {
+    pub invoices: Vec<(Invoice, NaiveDate)>,
+    /// Regenerated copies, kept apart so they never count towards a total
+    pub regenerated: Vec<(Invoice, NaiveDate)>,
+    pub credit_notes: Vec<(CreditNote, NaiveDate)>,
+    pub payments: Vec<(Payment, NaiveDate)>,
+    /// Invoice items keyed by invoice id
+    pub items: HashMap<i32, Vec<Item>>,
+    /// Credit note items keyed by credit note id
+    pub credit_note_items: HashMap<i32, Vec<CreditNoteItem>>,
+}
+
+impl Ledger {
+    pub fn load() -> Result<Ledger, DbError> {
+        let repo = &mut *repository()?;
+
+        Ledger::from_records(
+            repo.list_invoices(&InvoiceFilter::default())?,
+            repo.credit_notes()?,
+            repo.payments()?,
+            repo.invoice_items(None)?,
+            repo.credit_note_items()?,
+        )
+    }
+
+    /// Builds a ledger from loaded records. Regenerated invoices are copies of
+    /// an invoice that is already in the list, so they are set aside in
+    /// `regenerated` rather than counted twice.
+    pub fn from_records(
+        invoices: Vec<Invoice>,
+        credit_notes: Vec<CreditNote>,
+        payments: Vec<Payment>,
+        invoice_items: Vec<Item>,
+        note_items: Vec<CreditNoteItem>,
+    ) -> Result<Ledger, DbError> {
+        let (regenerated, invoices): (Vec<_>, Vec<_>) = invoices
+            .into_iter()
+            .map(|invoice| {
+                let date = record_date("Invoice", invoice.id, &invoice.date)?;
+                Ok((invoice, date))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?
+            .into_iter()
+            .partition(|(invoice, _)| invoice.regenerated == Some(true));
+
+        let credit_notes = credit_notes
+            .into_iter()
+            .map(|note| {
+                let date = record_date("Credit note", note.id, &note.date)?;
+                Ok((note, date))
+            })
+            .collect::<Result<_, DbError>>()?;
+
+        let payments = payments
+            .into_iter()
+            .map(|payment| {
+                let date = record_date("Payment", payment.id, &payment.date)?;
+                Ok((payment, date))
+            })
+            .collect::<Result<_, DbError>>()?;
+
+        let mut items: HashMap<i32, Vec<Item>> = HashMap::new();
+        for item in invoice_items {
+            items.entry(item.invoice_id).or_default().push(item);
+        }
+
+        let mut credit_note_items: HashMap<i32, Vec<CreditNoteItem>> = HashMap::new();
+        for item in note_items {
+            credit_note_items
+                .entry(item.credit_note_id)
+                .or_default()
+                .push(item);
+        }
+
+        Ok(Ledger {
+            invoices,
+            regenerated,
+            credit_notes,
+            payments,
+            items,
+            credit_note_items,
+        })
+    }
+
+    fn invoice(&self, id: i32) -> Option<&Invoice> {
+        self.invoices
+            .iter()
+            .map(|(invoice, _)| invoice)
+            .find(|invoice| invoice.id == id)
+    }
+
+    /// Positive amount credited against each invoice, optionally up to a date.
+    pub fn credited(&self, as_of: Option<NaiveDate>) -> HashMap<i32, f64> {
+        let mut credited = HashMap::new();
+        for (note, date) in &self.credit_notes {
+            if as_of.is_none_or(|as_of| *date <= as_of) {
+                *credited.entry(note.invoice_id).or_insert(0.0) -= note.total_amount;
+            }
+        }
+        credited
+    }
+
+    /// Amount paid against each invoice, optionally up to a date.
+    pub fn paid(&self, as_of: Option<NaiveDate>) -> HashMap<i32, f64> {
+        let mut paid = HashMap::new();
+        for (payment, date) in &self.payments {
+            if as_of.is_none_or(|as_of| *date <= as_of) {
+                *paid.entry(payment.invoice_id).or_insert(0.0) += payment.amount;
+            }
+        }
+        paid
+    }
+
+    /// Outstanding balance of every invoice issued up to `as_of`, with its due date.
+    pub fn outstanding(&self, as_of: NaiveDate, terms: i64) -> Vec<(&Invoice, NaiveDate, f64)> {
+        let credited = self.credited(Some(as_of));
+        let paid = self.paid(Some(as_of));
+
+        self.invoices
+            .iter()
+            .filter(|(_, date)| *date <= as_of)
+            .filter_map(|(invoice, date)| {
+                let balance = invoice.total_amount
+                    - credited.get(&invoice.id).copied().unwrap_or_default()
+                    - paid.get(&invoice.id).copied().unwrap_or_default();
+                (balance > 0.005).then(|| (invoice, due_date(invoice, *date, terms), balance))
+            })
+            .collect()
+    }
+}
+
+fn record_date(record: &str, id: i32, date: &str) -> Result<NaiveDate, DbError> {
+    parse_date(date).ok_or_else(|| {
+        DbError::InvalidData(format!(
+            "{} {} has an unreadable date: {}",
+            record, id, date
+        ))
+    })
+}
+
+/// Due date of an invoice, falling back to the payment terms for invoices
+/// issued before due dates were recorded.
+pub fn due_date(invoice: &Invoice, invoice_date: NaiveDate, terms: i64) -> NaiveDate {
+    invoice
+        .due_date
+        .as_deref()
+        .and_then(parse_date)
+        .unwrap_or(invoice_date + chrono::Duration::days(terms))
+}
+
+#[derive(Default)]
+struct Totals {
+    invoices: usize,
+    invoiced: f64,
+    credited: f64,
+    paid: f64,
+}
+
+impl Totals {
+    fn net(&self) -> f64 {
+        self.invoiced - self.credited
+    }
+}
+
+pub fn revenue_by_period(ledger: &Ledger, period: ReportPeriod, range: DateRange) -> Report {
+    let mut totals: IndexMap<String, Totals> = IndexMap::new();
+
+    for (invoice, date) in ledger.invoices.iter().filter(|(_, d)| range.contains(*d)) {
+        let entry = totals.entry(period.key(*date)).or_default();
+        entry.invoices += 1;
+        entry.invoiced += invoice.total_amount;
+    }
+    for (note, date) in ledger
+        .credit_notes
+        .iter()
+        .filter(|(_, d)| range.contains(*d))
+    {
+        totals.entry(period.key(*date)).or_default().credited -= note.total_amount;
+    }
+    for (payment, date) in ledger.payments.iter().filter(|(_, d)| range.contains(*d)) {
+        totals.entry(period.key(*date)).or_default().paid += payment.amount;
+    }
+
+    totals.sort_keys();
+
+    Report {
+        title: "Revenue by period".to_string(),
+        headers: vec![
+            "Period",
+            "Invoices",
+            "Invoiced",
+            "Credited",
+            "Net",
+            "Collected",
+        ],
+        rows: totals
+            .iter()
+            .map(|(key, t)| {
+                vec![
+                    json!(key),
+                    json!(t.invoices),
+                    money(t.invoiced),
+                    money(t.credited),
+                    money(t.net()),
+                    money(t.paid),
+                ]
+            })
+            .collect(),
+    }
+}
+
+fn totals_by<F>(ledger: &Ledger, range: DateRange, key: F) -> IndexMap<String, Totals>
+where
+    F: Fn(&Invoice) -> String,
+{
+    let mut totals: IndexMap<String, Totals> = IndexMap::new();
+
+    for (invoice, _) in ledger.invoices.iter().filter(|(_, d)| range.contains(*d)) {
+        let entry = totals.entry(key(invoice)).or_default();
+        entry.invoices += 1;
+        entry.invoiced += invoice.total_amount;
+    }
+    for (note, _) in ledger
+        .credit_notes
+        .iter()
+        .filter(|(_, d)| range.contains(*d))
+    {
+        if let Some(invoice) = ledger.invoice(note.invoice_id) {
+            totals.entry(key(invoice)).or_default().credited -= note.total_amount;
+        }
+    }
+    for (payment, _) in ledger.payments.iter().filter(|(_, d)| range.contains(*d)) {
+        if let Some(invoice) = ledger.invoice(payment.invoice_id) {
+            totals.entry(key(invoice)).or_default().paid += payment.amount;
+        }
+    }
+
+    totals
+}
+
+fn party_report(title: &str, header: &'static str, totals: IndexMap<String, Totals>) -> Report {
+    let mut totals = totals.into_iter().collect::<Vec<_>>();
+    totals.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Report {
+        title: title.to_string(),
+        headers: vec![
+            header,
+            "Invoices",
+            "Invoiced",
+            "Credited",
+            "Net",
+            "Collected",
+        ],
+        rows: totals
+            .iter()
+            .map(|(key, t)| {
+                vec![
+                    json!(key),
+                    json!(t.invoices),
+                    money(t.invoiced),
+                    money(t.credited),
+                    money(t.net()),
+                    money(t.paid),
+                ]
+            })
+            .collect(),
+    }
+}
+
+pub fn revenue_by_client(ledger: &Ledger, range: DateRange) -> Report {
+    let totals = totals_by(ledger, range, |invoice| invoice.client_name.clone());
+    party_report("Revenue by client", "Client", totals)
+}
+
+pub fn revenue_by_company(ledger: &Ledger, range: DateRange) -> Report {
+    let totals = totals_by(ledger, range, |invoice| invoice.company_name.clone());
+    party_report("Revenue by company", "Company", totals)
+}
+
+pub fn top_clients(ledger: &Ledger, range: DateRange, limit: usize) -> Report {
+    let totals = totals_by(ledger, range, |invoice| invoice.client_name.clone());
+    let grand_total: f64 = totals.values().map(Totals::net).sum();
+
+    let mut ranked = totals.into_iter().collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.1.net().total_cmp(&a.1.net()));
+
+    Report {
+        title: format!("Top {} clients", limit),
+        headers: vec!["Rank", "Client", "Invoices", "Net", "Share %"],
+        rows: ranked
+            .iter()
+            .take(limit)
+            .enumerate()
+            .map(|(i, (client, t))| {
+                let share = if grand_total > 0.0 {
+                    t.net() / grand_total * 100.0
+                } else {
+                    0.0
+                };
+                vec![
+                    json!(i + 1),
+                    json!(client),
+                    json!(t.invoices),
+                    money(t.net()),
+                    money(share),
+                ]
+            })
+            .collect(),
+    }
+}
+
+/// Days between each fully settled invoice and the payment that settled it.
+pub fn days_to_pay(ledger: &Ledger, range: DateRange) -> Report {
+    let credited = ledger.credited(None);
+    let mut per_client: IndexMap<String, Vec<i64>> = IndexMap::new();
+
+    for (invoice, invoice_date) in ledger.invoices.iter().filter(|(_, d)| range.contains(*d)) {
+        let due = invoice.total_amount - credited.get(&invoice.id).copied().unwrap_or_default();
+        if due <= 0.0 {
+            continue;
+        }
+
+        let mut paid = 0.0;
+        let settled = ledger
+            .payments
+            .iter()
+            .filter(|(payment, _)| payment.invoice_id == invoice.id)
+            .find(|(payment, _)| {
+                paid += payment.amount;
+                paid >= due - 0.005
+            });
+
+        // Imported payments without a recorded date say nothing about how
+        // long the client took
+        if let Some((_, paid_date)) = settled.filter(|(payment, _)| !payment.date_unknown) {
+            per_client
+                .entry(invoice.client_name.clone())
+                .or_default()
+                .push((*paid_date - *invoice_date).num_days());
+        }
+    }
+
+    per_client.sort_keys();
+
+    let average = |days: &[i64]| days.iter().sum::<i64>() as f64 / days.len() as f64;
+    let all_days = per_client.values().flatten().copied().collect::<Vec<_>>();
+
+    let mut rows = per_client
+        .iter()
+        .map(|(client, days)| vec![json!(client), json!(days.len()), money(average(days))])
+        .collect::<Vec<_>>();
+
+    if !all_days.is_empty() {
+        rows.push(vec![
+            json!("All clients"),
+            json!(all_days.len()),
+            money(average(&all_days)),
+        ]);
+    }
+
+    Report {
+        title: "Average days to pay".to_string(),
+        headers: vec!["Client", "Paid Invoices", "Average Days"],
+        rows,
+    }
+}
+
+const AGING_BUCKETS: usize = 5;
+
+fn aging_bucket(days_overdue: i64) -> usize {
+    match days_overdue {
+        i64::MIN..=0 => 0,
+        1..=30 => 1,
+        31..=60 => 2,
+        61..=90 => 3,
+        _ => 4,
+    }
+}
+
+fn aging_row(company: &str, client: &str, buckets: &[f64; AGING_BUCKETS]) -> Vec<Value> {
+    let mut row = vec![json!(company), json!(client)];
+    row.extend(buckets.iter().map(|amount| money(*amount)));
+    row.push(money(buckets.iter().sum()));
+    row
+}
+
+/// Outstanding balances bucketed by days past due, per client with company totals.
+pub fn aging(ledger: &Ledger, as_of: NaiveDate, terms: i64) -> Report {
+    let mut balances: IndexMap<String, IndexMap<String, [f64; AGING_BUCKETS]>> = IndexMap::new();
+
+    for (invoice, due, balance) in ledger.outstanding(as_of, terms) {
+        let bucket = aging_bucket((as_of - due).num_days());
+        balances
+            .entry(invoice.company_name.clone())
+            .or_default()
+            .entry(invoice.client_name.clone())
+            .or_insert([0.0; AGING_BUCKETS])[bucket] += balance;
+    }
+
+    balances.sort_keys();
+
+    let mut rows = Vec::new();
+    let mut grand_total = [0.0; AGING_BUCKETS];
+
+    for (company, mut clients) in balances {
+        clients.sort_keys();
+        let mut company_total = [0.0; AGING_BUCKETS];
+
+        for (client, buckets) in &clients {
+            rows.push(aging_row(&company, client, buckets));
+            for (i, amount) in buckets.iter().enumerate() {
+                company_total[i] += amount;
+                grand_total[i] += amount;
+            }
+        }
+
+        rows.push(aging_row(&company, "Company total", &company_total));
+    }
+
+    rows.push(aging_row("", "Total", &grand_total));
+
+    Report {
+        title: format!(
+            "Accounts receivable aging as of {}",
+            as_of.format("%Y-%m-%d")
+        ),
+        headers: vec![
+            "Company", "Client", "Current", "1-30", "31-60", "61-90", "90+", "Total",
+        ],
+        rows,
+    }
+}
+
+/// Parses a tax period (`2026-Q3`, `2026-07` or `2026`) into its date range.
+pub fn parse_tax_period(period: &str) -> Result<DateRange, String> {
+    let invalid = || {
+        format!(
+            "Invalid period {}, expected YYYY-Qn, YYYY-MM or YYYY",
+            period
+        )
+    };
+
+    let (year, first_month, months) = match period.split_once('-') {
+        Some((year, quarter)) if quarter.starts_with(['Q', 'q']) => {
+            let quarter = quarter[1..].parse::<u32>().map_err(|_| invalid())?;
+            if !(1..=4).contains(&quarter) {
+                return Err(invalid());
+            }
+            (year, (quarter - 1) * 3 + 1, 3)
+        }
+        Some((year, month)) => (year, month.parse::<u32>().map_err(|_| invalid())?, 1),
+        None => (period, 1, 12),
+    };
+
+    let year = year.parse::<i32>().map_err(|_| invalid())?;
+    let from = NaiveDate::from_ymd_opt(year, first_month, 1).ok_or_else(invalid)?;
+    let to = from
+        .checked_add_months(chrono::Months::new(months))
+        .and_then(|next| next.pred_opt())
+        .ok_or_else(invalid)?;
+
+    Ok(DateRange {
+        from: Some(from),
+        to: Some(to),
+    })
+}
+
+#[derive(Default)]
+struct TaxAmounts {
+    net: f64,
+    tax: f64,
+}
+
+/// Sales for a tax period split by how they are taxed.
+#[derive(Default)]
+struct TaxSummary {
+    /// Taxable sales keyed by rate
+    rated: IndexMap<String, TaxAmounts>,
+    zero_rated: f64,
+    exempt: f64,
+    credited: TaxAmounts,
+}
+
+impl TaxSummary {
+    /// Adds `share` of a line to the box matching its tax rate.
+    fn add(&mut self, net: f64, tax_rate: Option<f64>, share: f64) {
+        let net = net * share;
+        match tax_rate {
+            None => self.exempt += net,
+            Some(0.0) => self.zero_rated += net,
+            Some(rate) => {
+                let amounts = self.rated.entry(rate.to_string()).or_default();
+                amounts.net += net;
+                amounts.tax += net * rate / 100.0;
+            }
+        }
+    }
+
+    fn total_net(&self) -> f64 {
+        self.rated.values().map(|a| a.net).sum::<f64>()
+            + self.zero_rated
+            + self.exempt
+            + self.credited.net
+    }
+
+    fn total_tax(&self) -> f64 {
+        self.rated.values().map(|a| a.tax).sum::<f64>() + self.credited.tax
+    }
+}
+
+fn line_gross(net: f64, tax_rate: Option<f64>) -> f64 {
+    net * (1.0 + tax_rate.unwrap_or_default() / 100.0)
+}
+
+/// Tax return for a period. On the accrual basis sales count when invoiced and
+/// credit notes when issued; on the cash basis each payment is split across
+/// the lines of its invoice, after credit notes, in proportion to their value.
+pub fn tax_return(ledger: &Ledger, period: DateRange, basis: TaxBasis) -> Report {
+    let mut summary = TaxSummary::default();
+
+    match basis {
+        TaxBasis::Accrual => {
+            for (invoice, _) in ledger.invoices.iter().filter(|(_, d)| period.contains(*d)) {
+                for item in ledger.items.get(&invoice.id).into_iter().flatten() {
+                    summary.add(item.total, item.tax_rate, 1.0);
+                }
+            }
+            for (note, _) in ledger
+                .credit_notes
+                .iter()
+                .filter(|(_, d)| period.contains(*d))
+            {
+                for item in ledger.credit_note_items.get(&note.id).into_iter().flatten() {
+                    summary.credited.net += item.total;
+                    summary.credited.tax += item.total * item.tax_rate.unwrap_or_default() / 100.0;
+                }
+            }
+        }
+        TaxBasis::Cash => {
+            for (payment, _) in ledger.payments.iter().filter(|(_, d)| period.contains(*d)) {
+                let invoice_lines = ledger
+                    .items
+                    .get(&payment.invoice_id)
+                    .into_iter()
+                    .flatten()
+                    .map(|item| (item.total, item.tax_rate));
+                let credit_lines = ledger
+                    .credit_notes
+                    .iter()
+                    .filter(|(note, _)| note.invoice_id == payment.invoice_id)
+                    .flat_map(|(note, _)| ledger.credit_note_items.get(&note.id))
+                    .flatten()
+                    .map(|item| (item.total, item.tax_rate));
+                let lines = invoice_lines.chain(credit_lines).collect::<Vec<_>>();
+
+                let gross: f64 = lines
+                    .iter()
+                    .map(|(net, rate)| line_gross(*net, *rate))
+                    .sum();
+                if gross <= 0.0 {
+                    continue;
+                }
+
+                for (net, rate) in lines {
+                    summary.add(net, rate, payment.amount / gross);
+                }
+            }
+        }
+    }
+
+    let mut rates = summary.rated.iter().collect::<Vec<_>>();
+    rates.sort_by(|a, b| {
+        let rate = |key: &str| key.parse::<f64>().unwrap_or_default();
+        rate(b.0).total_cmp(&rate(a.0))
+    });
+
+    let mut rows = rates
+        .iter()
+        .map(|(rate, amounts)| {
+            vec![
+                json!("1"),
+                json!(format!("Taxable sales at {}%", rate)),
+                money(amounts.net),
+                money(amounts.tax),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let credit_notes = match basis {
+        TaxBasis::Accrual => "Credit notes issued",
+        TaxBasis::Cash => "Credit notes (netted against payments received)",
+    };
+
+    rows.extend([
+        vec![
+            json!("2"),
+            json!("Zero-rated sales"),
+            money(summary.zero_rated),
+            money(0.0),
+        ],
+        vec![
+            json!("3"),
+            json!("Exempt sales"),
+            money(summary.exempt),
+            money(0.0),
+        ],
+        vec![
+            json!("4"),
+            json!(credit_notes),
+            money(summary.credited.net),
+            money(summary.credited.tax),
+        ],
+        vec![
+            json!("5"),
+            json!("Total tax due"),
+            Value::Null,
+            money(summary.total_tax()),
+        ],
+        vec![
+            json!("6"),
+            json!("Total sales excluding tax"),
+            money(summary.total_net()),
+            Value::Null,
+        ],
+    ]);
+
+    let basis = match basis {
+        TaxBasis::Accrual => "accrual",
+        TaxBasis::Cash => "cash",
+    };
+
+    Report {
+        title: format!(
+            "Tax return {} to {} ({} basis)",
+            period.from.map(|d| d.to_string()).unwrap_or_default(),
+            period.to.map(|d| d.to_string()).unwrap_or_default(),
+            basis
+        ),
+        headers: vec!["Box", "Description", "Net", "Tax"],
+        rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(id: i32, date: &str, regenerated: bool) -> Invoice {
+        Invoice {
+            id,
+            company_id: Some(1),
+            company_name: "Acme".to_string(),
+            company_address: None,
+            company_email: None,
+            company_phone: None,
+            client_id: Some(1),
+            client_name: "Carol".to_string(),
+            client_address: None,
+            client_email: None,
+            client_phone: None,
+            date: date.to_string(),
+            total_amount: 100.0,
+            logo_url: None,
+            tax: None,
+            notes: None,
+            regenerated: Some(regenerated),
+            quote_id: None,
+            due_date: Some("2026-08-31".to_string()),
+            original_number: None,
+        }
+    }
+
+    #[test]
+    fn aging_counts_a_regenerated_invoice_once() {
+        let payment = Payment {
+            id: 1,
+            invoice_id: 1,
+            amount: 30.0,
+            date: "2026-09-10".to_string(),
+            method: None,
+            reference: None,
+            date_unknown: false,
+        };
+        let ledger = Ledger::from_records(
+            vec![
+                invoice(1, "01 August 2026", false),
+                invoice(2, "01 September 2026", true),
+            ],
+            Vec::new(),
+            vec![payment],
+            Vec::new(),
+            Vec::new(),
+        )
+        .unwrap();
+
+        let as_of = NaiveDate::from_ymd_opt(2026, 10, 15).unwrap();
+        let report = aging(&ledger, as_of, 30);
+
+        // Carol, Acme's company total and the grand total
+        assert_eq!(report.rows.len(), 3);
+        let total = report.rows.last().unwrap();
+        assert_eq!(total[4], money(70.0), "45 days overdue lands in 31-60");
+        assert_eq!(total[7], money(70.0));
+    }
+
+    #[test]
+    fn unreadable_dates_are_an_error() {
+        let result = Ledger::from_records(
+            vec![invoice(1, "sometime in August", false)],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        );
+
+        assert!(matches!(result, Err(DbError::InvalidData(_))));
+    }
+
+    #[test]
+    fn days_to_pay_skips_payments_without_a_known_date() {
+        let payment = |id: i32, invoice_id: i32, date_unknown: bool| Payment {
+            id,
+            invoice_id,
+            amount: 100.0,
+            date: "2026-08-11".to_string(),
+            method: None,
+            reference: None,
+            date_unknown,
+        };
+        let ledger = Ledger::from_records(
+            vec![
+                invoice(1, "01 August 2026", false),
+                invoice(2, "01 August 2026", false),
+            ],
+            Vec::new(),
+            vec![payment(1, 1, false), payment(2, 2, true)],
+            Vec::new(),
+            Vec::new(),
+        )
+        .unwrap();
+
+        let report = days_to_pay(&ledger, DateRange::default());
+
+        let total = report.rows.last().unwrap();
+        assert_eq!(total[1], json!(1));
+        assert_eq!(total[2], money(10.0));
+    }
+}
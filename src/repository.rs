@@ -0,0 +1,430 @@
+//! Storage for companies, clients and invoices, with one implementation per
+//! database backend. [`crate::db::repository`] picks the backend from the
+//! database URL.
+
+use crate::client::ClientSearchField;
+use crate::company::CompanySearchField;
+use crate::db::{DbError, MigrationState, MIGRATIONS, PG_MIGRATIONS};
+use crate::models::{
+    Client, Company, CreditNote, CreditNoteItem, Invoice, Item, NewClient, NewCompany,
+    NewCompanyClient, NewInvoice, NewItem, Payment,
+};
+use crate::schema::{
+    clients, companies, company_clients, credit_note_items, credit_notes, invoices, items, payments,
+};
+use diesel::migration::{Migration, MigrationSource};
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use diesel::sqlite::{Sqlite, SqliteConnection};
+use diesel_migrations::MigrationHarness;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct InvoiceFilter {
+    pub id: Option<i32>,
+    pub client_id: Option<i32>,
+    /// Exact client name, as it was on the invoice
+    pub client_name: Option<String>,
+}
+
+pub trait Repository {
+    fn create_company(&mut self, company: &NewCompany) -> Result<Company, DbError>;
+    fn find_company(&mut self, id: i32) -> Result<Option<Company>, DbError>;
+    fn search_companies(
+        &mut self,
+        field: &CompanySearchField,
+        value: &str,
+    ) -> Result<Vec<Company>, DbError>;
+    fn list_companies(&mut self) -> Result<Vec<Company>, DbError>;
+
+    /// Saves a client and links it to `company_ids`, all or nothing.
+    fn create_client(
+        &mut self,
+        client: &NewClient,
+        company_ids: &[i32],
+        credit_limit: Option<f64>,
+    ) -> Result<Client, DbError>;
+    fn find_client(&mut self, id: i32) -> Result<Option<Client>, DbError>;
+    fn list_clients(&mut self) -> Result<Vec<Client>, DbError>;
+    /// Every client with each of its companies, optionally filtered by a
+    /// case-insensitive substring.
+    fn clients_with_companies(
+        &mut self,
+        search: Option<(&ClientSearchField, &str)>,
+    ) -> Result<Vec<(Client, Company)>, DbError>;
+    fn set_credit_limit(
+        &mut self,
+        client_id: i32,
+        credit_limit: Option<f64>,
+    ) -> Result<Option<Client>, DbError>;
+    fn link_company_client(&mut self, company_id: i32, client_id: i32) -> Result<usize, DbError>;
+
+    /// Saves an invoice and its items together, so a failure leaves neither.
+    fn create_invoice(&mut self, invoice: &NewInvoice, items: Vec<NewItem>)
+        -> Result<i32, DbError>;
+    /// Removes an invoice and its items, for one that was never issued.
+    fn delete_invoice(&mut self, id: i32) -> Result<(), DbError>;
+    fn find_invoice(&mut self, id: i32) -> Result<Option<Invoice>, DbError>;
+    fn list_invoices(&mut self, filter: &InvoiceFilter) -> Result<Vec<Invoice>, DbError>;
+    fn latest_invoice_id(&mut self) -> Result<i32, DbError>;
+    /// Items of one invoice, or of all invoices with `None`.
+    fn invoice_items(&mut self, invoice_id: Option<i32>) -> Result<Vec<Item>, DbError>;
+
+    /// Payments received per invoice id.
+    fn paid_by_invoice(&mut self) -> Result<HashMap<i32, f64>, DbError>;
+    /// Credit notes per invoice id, as positive amounts.
+    fn credited_by_invoice(&mut self) -> Result<HashMap<i32, f64>, DbError>;
+    fn payments(&mut self) -> Result<Vec<Payment>, DbError>;
+    fn credit_notes(&mut self) -> Result<Vec<CreditNote>, DbError>;
+    fn credit_note_items(&mut self) -> Result<Vec<CreditNoteItem>, DbError>;
+
+    /// Every migration for this backend, oldest first.
+    fn migration_status(&mut self) -> Result<Vec<MigrationState>, DbError>;
+    /// Versions applied to the database that this binary has no migration
+    /// for, meaning it was last opened by a newer version of cli-invoice.
+    fn unknown_migrations(&mut self) -> Result<Vec<String>, DbError>;
+    /// Applies pending migrations and returns the names of those applied.
+    fn run_migrations(&mut self) -> Result<Vec<String>, DbError>;
+    /// Reverts the latest `steps` migrations and returns the names of those
+    /// reverted, newest first.
+    fn revert_migrations(&mut self, steps: usize) -> Result<Vec<String>, DbError>;
+
+    /// Refuses a database that was migrated by a newer binary.
+    fn check_schema(&mut self) -> Result<(), DbError> {
+        match self.unknown_migrations()?.into_iter().max() {
+            Some(version) => Err(DbError::SchemaTooNew(version)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn migration_error(e: impl std::fmt::Display) -> DbError {
+    DbError::Migration(e.to_string())
+}
+
+/// The queries are the same for every backend; only the connection type, the
+/// migrations and the case-insensitive `LIKE` differ.
+macro_rules! impl_repository {
+    ($conn:ty, $backend:ty, $migrations:expr, $like:ident) => {
+        impl Repository for $conn {
+            fn create_company(&mut self, company: &NewCompany) -> Result<Company, DbError> {
+                Ok(diesel::insert_into(companies::table)
+                    .values(company)
+                    .returning(Company::as_returning())
+                    .get_result(self)?)
+            }
+
+            fn find_company(&mut self, id: i32) -> Result<Option<Company>, DbError> {
+                Ok(companies::table
+                    .find(id)
+                    .first::<Company>(self)
+                    .optional()?)
+            }
+
+            fn search_companies(
+                &mut self,
+                field: &CompanySearchField,
+                value: &str,
+            ) -> Result<Vec<Company>, DbError> {
+                let pattern = format!("%{}%", value);
+                let query = companies::table.into_boxed();
+                let query = match field {
+                    CompanySearchField::Name => query.filter(companies::name.$like(pattern)),
+                    CompanySearchField::Address => query.filter(companies::address.$like(pattern)),
+                };
+
+                Ok(query.order(companies::id.asc()).load::<Company>(self)?)
+            }
+
+            fn list_companies(&mut self) -> Result<Vec<Company>, DbError> {
+                Ok(companies::table
+                    .order(companies::id.asc())
+                    .load::<Company>(self)?)
+            }
+
+            fn create_client(
+                &mut self,
+                client: &NewClient,
+                company_ids: &[i32],
+                credit_limit: Option<f64>,
+            ) -> Result<Client, DbError> {
+                Ok(self.transaction(|conn| {
+                    let client = diesel::insert_into(clients::table)
+                        .values(client)
+                        .returning(Client::as_returning())
+                        .get_result(conn)?;
+
+                    for company_id in company_ids {
+                        diesel::insert_into(company_clients::table)
+                            .values(&NewCompanyClient {
+                                company_id: *company_id,
+                                client_id: client.id,
+                            })
+                            .execute(conn)?;
+                    }
+
+                    diesel::update(clients::table.find(client.id))
+                        .set(clients::credit_limit.eq(credit_limit))
+                        .returning(Client::as_returning())
+                        .get_result(conn)
+                })?)
+            }
+
+            fn find_client(&mut self, id: i32) -> Result<Option<Client>, DbError> {
+                Ok(clients::table.find(id).first::<Client>(self).optional()?)
+            }
+
+            fn list_clients(&mut self) -> Result<Vec<Client>, DbError> {
+                Ok(clients::table
+                    .order(clients::id.asc())
+                    .load::<Client>(self)?)
+            }
+
+            fn clients_with_companies(
+                &mut self,
+                search: Option<(&ClientSearchField, &str)>,
+            ) -> Result<Vec<(Client, Company)>, DbError> {
+                let mut query = clients::table
+                    .inner_join(
+                        company_clients::table.on(clients::id.eq(company_clients::client_id)),
+                    )
+                    .inner_join(companies::table.on(companies::id.eq(company_clients::company_id)))
+                    .into_boxed();
+
+                if let Some((field, value)) = search {
+                    let pattern = format!("%{}%", value);
+                    query = match field {
+                        ClientSearchField::Name => query
+                            .filter(clients::name.$like(pattern))
+                            .order(clients::name.asc()),
+                        ClientSearchField::Address => query
+                            .filter(clients::address.$like(pattern))
+                            .order(clients::name.asc()),
+                        ClientSearchField::Company => query
+                            .filter(companies::name.$like(pattern))
+                            .order(clients::name.asc()),
+                    };
+                } else {
+                    query = query.order(clients::id.asc());
+                }
+
+                Ok(query
+                    .select((clients::all_columns, companies::all_columns))
+                    .load::<(Client, Company)>(self)?)
+            }
+
+            fn set_credit_limit(
+                &mut self,
+                client_id: i32,
+                credit_limit: Option<f64>,
+            ) -> Result<Option<Client>, DbError> {
+                Ok(diesel::update(clients::table.find(client_id))
+                    .set(clients::credit_limit.eq(credit_limit))
+                    .returning(Client::as_returning())
+                    .get_result(self)
+                    .optional()?)
+            }
+
+            fn link_company_client(
+                &mut self,
+                company_id: i32,
+                client_id: i32,
+            ) -> Result<usize, DbError> {
+                Ok(diesel::insert_into(company_clients::table)
+                    .values(&NewCompanyClient {
+                        company_id,
+                        client_id,
+                    })
+                    .execute(self)?)
+            }
+
+            fn create_invoice(
+                &mut self,
+                invoice: &NewInvoice,
+                new_items: Vec<NewItem>,
+            ) -> Result<i32, DbError> {
+                Ok(self.transaction(|conn| {
+                    let invoice_id = diesel::insert_into(invoices::table)
+                        .values(invoice)
+                        .returning(invoices::id)
+                        .get_result::<i32>(conn)?;
+
+                    for mut item in new_items {
+                        item.invoice_id = invoice_id;
+                        diesel::insert_into(items::table)
+                            .values(&item)
+                            .execute(conn)?;
+                    }
+
+                    Ok::<_, diesel::result::Error>(invoice_id)
+                })?)
+            }
+
+            fn delete_invoice(&mut self, id: i32) -> Result<(), DbError> {
+                Ok(self.transaction(|conn| {
+                    diesel::delete(items::table.filter(items::invoice_id.eq(id))).execute(conn)?;
+                    diesel::delete(invoices::table.find(id)).execute(conn)?;
+                    Ok::<_, diesel::result::Error>(())
+                })?)
+            }
+
+            fn find_invoice(&mut self, id: i32) -> Result<Option<Invoice>, DbError> {
+                Ok(invoices::table.find(id).first::<Invoice>(self).optional()?)
+            }
+
+            fn list_invoices(&mut self, filter: &InvoiceFilter) -> Result<Vec<Invoice>, DbError> {
+                let mut query = invoices::table.into_boxed();
+                if let Some(id) = filter.id {
+                    query = query.filter(invoices::id.eq(id));
+                }
+                if let Some(client_id) = filter.client_id {
+                    query = query.filter(invoices::client_id.eq(client_id));
+                }
+                if let Some(client_name) = &filter.client_name {
+                    query = query.filter(invoices::client_name.eq(client_name));
+                }
+
+                Ok(query.order(invoices::id.asc()).load::<Invoice>(self)?)
+            }
+
+            fn latest_invoice_id(&mut self) -> Result<i32, DbError> {
+                let id = invoices::table
+                    .order(invoices::id.desc())
+                    .select(invoices::id)
+                    .first::<i32>(self)
+                    .optional()?;
+
+                Ok(id.unwrap_or(0))
+            }
+
+            fn invoice_items(&mut self, invoice_id: Option<i32>) -> Result<Vec<Item>, DbError> {
+                let mut query = items::table.into_boxed();
+                if let Some(invoice_id) = invoice_id {
+                    query = query.filter(items::invoice_id.eq(invoice_id));
+                }
+
+                Ok(query.order(items::id.asc()).load::<Item>(self)?)
+            }
+
+            fn paid_by_invoice(&mut self) -> Result<HashMap<i32, f64>, DbError> {
+                Ok(payments::table
+                    .group_by(payments::invoice_id)
+                    .select((payments::invoice_id, diesel::dsl::sum(payments::amount)))
+                    .load::<(i32, Option<f64>)>(self)?
+                    .into_iter()
+                    .map(|(invoice_id, total)| (invoice_id, total.unwrap_or_default()))
+                    .collect())
+            }
+
+            fn credited_by_invoice(&mut self) -> Result<HashMap<i32, f64>, DbError> {
+                Ok(credit_notes::table
+                    .group_by(credit_notes::invoice_id)
+                    .select((
+                        credit_notes::invoice_id,
+                        diesel::dsl::sum(credit_notes::total_amount),
+                    ))
+                    .load::<(i32, Option<f64>)>(self)?
+                    .into_iter()
+                    .map(|(invoice_id, total)| (invoice_id, -total.unwrap_or_default()))
+                    .collect())
+            }
+
+            fn payments(&mut self) -> Result<Vec<Payment>, DbError> {
+                Ok(payments::table
+                    .order((payments::date.asc(), payments::id.asc()))
+                    .load::<Payment>(self)?)
+            }
+
+            fn credit_notes(&mut self) -> Result<Vec<CreditNote>, DbError> {
+                Ok(credit_notes::table
+                    .order(credit_notes::id.asc())
+                    .load::<CreditNote>(self)?)
+            }
+
+            fn credit_note_items(&mut self) -> Result<Vec<CreditNoteItem>, DbError> {
+                Ok(credit_note_items::table
+                    .order(credit_note_items::id.asc())
+                    .load::<CreditNoteItem>(self)?)
+            }
+
+            fn migration_status(&mut self) -> Result<Vec<MigrationState>, DbError> {
+                let applied = self
+                    .applied_migrations()
+                    .map_err(migration_error)?
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+
+                let mut migrations = MigrationSource::<$backend>::migrations(&$migrations)
+                    .map_err(migration_error)?
+                    .iter()
+                    .map(|migration| {
+                        let version = migration.name().version().to_string();
+                        MigrationState {
+                            applied: applied.contains(&version),
+                            name: migration.name().to_string(),
+                            version,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                migrations.sort_by(|a, b| a.version.cmp(&b.version));
+
+                Ok(migrations)
+            }
+
+            fn unknown_migrations(&mut self) -> Result<Vec<String>, DbError> {
+                let known = self
+                    .migration_status()?
+                    .into_iter()
+                    .map(|migration| migration.version)
+                    .collect::<Vec<_>>();
+
+                Ok(self
+                    .applied_migrations()
+                    .map_err(migration_error)?
+                    .iter()
+                    .map(ToString::to_string)
+                    .filter(|version| !known.contains(version))
+                    .collect())
+            }
+
+            fn run_migrations(&mut self) -> Result<Vec<String>, DbError> {
+                self.check_schema()?;
+
+                let pending = self
+                    .migration_status()?
+                    .into_iter()
+                    .filter(|migration| !migration.applied)
+                    .map(|migration| migration.name)
+                    .collect();
+                self.run_pending_migrations($migrations)
+                    .map_err(migration_error)?;
+
+                Ok(pending)
+            }
+
+            fn revert_migrations(&mut self, steps: usize) -> Result<Vec<String>, DbError> {
+                self.check_schema()?;
+
+                let mut reverted = Vec::new();
+                for migration in self
+                    .migration_status()?
+                    .into_iter()
+                    .rev()
+                    .filter(|migration| migration.applied)
+                    .take(steps)
+                {
+                    self.revert_last_migration($migrations)
+                        .map_err(migration_error)?;
+                    reverted.push(migration.name);
+                }
+
+                Ok(reverted)
+            }
+        }
+    };
+}
+
+// SQLite's LIKE already ignores case
+impl_repository!(SqliteConnection, Sqlite, MIGRATIONS, like);
+impl_repository!(PgConnection, Pg, PG_MIGRATIONS, ilike);
@@ -7,6 +7,7 @@ diesel::table! {
         address -> Nullable<Text>,
         email -> Nullable<Text>,
         phone -> Nullable<Text>,
+        credit_limit -> Nullable<Double>,
     }
 }
 
@@ -27,6 +28,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    credit_note_items (id) {
+        id -> Integer,
+        credit_note_id -> Integer,
+        description -> Text,
+        quantity -> Integer,
+        unit_price -> Double,
+        total -> Double,
+        tax_rate -> Nullable<Double>,
+    }
+}
+
+diesel::table! {
+    credit_notes (id) {
+        id -> Integer,
+        invoice_id -> Integer,
+        date -> Text,
+        reason -> Nullable<Text>,
+        total_amount -> Double,
+    }
+}
+
 diesel::table! {
     invoices (id) {
         id -> Integer,
@@ -46,6 +69,9 @@ diesel::table! {
         tax -> Nullable<Double>,
         notes -> Nullable<Text>,
         regenerated -> Nullable<Bool>,
+        quote_id -> Nullable<Integer>,
+        due_date -> Nullable<Text>,
+        original_number -> Nullable<Text>,
     }
 }
 
@@ -57,19 +83,114 @@ diesel::table! {
         quantity -> Integer,
         unit_price -> Double,
         total -> Double,
+        tax_rate -> Nullable<Double>,
+    }
+}
+
+diesel::table! {
+    payments (id) {
+        id -> Integer,
+        invoice_id -> Integer,
+        amount -> Double,
+        date -> Text,
+        method -> Nullable<Text>,
+        reference -> Nullable<Text>,
+        date_unknown -> Bool,
+    }
+}
+
+diesel::table! {
+    quote_items (id) {
+        id -> Integer,
+        quote_id -> Integer,
+        description -> Text,
+        quantity -> Integer,
+        unit_price -> Double,
+        total -> Double,
+        tax_rate -> Nullable<Double>,
+    }
+}
+
+diesel::table! {
+    quotes (id) {
+        id -> Integer,
+        company_id -> Nullable<Integer>,
+        company_name -> Text,
+        company_address -> Nullable<Text>,
+        company_email -> Nullable<Text>,
+        company_phone -> Nullable<Text>,
+        client_id -> Nullable<Integer>,
+        client_name -> Text,
+        client_address -> Nullable<Text>,
+        client_email -> Nullable<Text>,
+        client_phone -> Nullable<Text>,
+        date -> Text,
+        valid_until -> Text,
+        total_amount -> Double,
+        notes -> Nullable<Text>,
+        status -> Text,
+        invoice_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    recurring_invoices (id) {
+        id -> Integer,
+        company_id -> Integer,
+        client_id -> Integer,
+        items -> Text,
+        notes -> Nullable<Text>,
+        interval -> Text,
+        day_of_month -> Integer,
+        start_date -> Text,
+        end_date -> Nullable<Text>,
+        next_run_date -> Text,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    time_entries (id) {
+        id -> Integer,
+        source -> Text,
+        external_id -> Text,
+        client_id -> Nullable<Integer>,
+        client_name -> Text,
+        project -> Nullable<Text>,
+        description -> Text,
+        duration_minutes -> Integer,
+        date -> Text,
+        invoice_id -> Nullable<Integer>,
     }
 }
 
 diesel::joinable!(company_clients -> clients (client_id));
 diesel::joinable!(company_clients -> companies (company_id));
+diesel::joinable!(credit_note_items -> credit_notes (credit_note_id));
+diesel::joinable!(credit_notes -> invoices (invoice_id));
 diesel::joinable!(invoices -> clients (client_id));
 diesel::joinable!(invoices -> companies (company_id));
 diesel::joinable!(items -> invoices (invoice_id));
+diesel::joinable!(payments -> invoices (invoice_id));
+diesel::joinable!(quote_items -> quotes (quote_id));
+diesel::joinable!(quotes -> clients (client_id));
+diesel::joinable!(quotes -> companies (company_id));
+diesel::joinable!(recurring_invoices -> clients (client_id));
+diesel::joinable!(recurring_invoices -> companies (company_id));
+diesel::joinable!(time_entries -> clients (client_id));
+diesel::joinable!(time_entries -> invoices (invoice_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     clients,
     companies,
     company_clients,
+    credit_note_items,
+    credit_notes,
     invoices,
     items,
+    payments,
+    quote_items,
+    quotes,
+    recurring_invoices,
+    time_entries,
 );
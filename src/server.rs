@@ -0,0 +1,319 @@
+//! `cli-invoice serve`: the [`service`] API over HTTP with JSON bodies, for
+//! dashboards and scripts that would otherwise parse tables. Every request
+//! except `GET /openapi.json` needs the configured `api_token` as a bearer
+//! token.
+
+use crate::config;
+use crate::service::{self, ClientInput, CompanyInput, Error, InvoiceFilter, InvoiceRequest};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::io::Read;
+use text_colorizer::*;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// OpenAPI 3 description of every endpoint, served at `/openapi.json`.
+pub const OPENAPI: &str = include_str!("openapi.json");
+
+/// Request bodies are small JSON objects; anything bigger is refused rather
+/// than read into memory.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: u16, value: &impl Serialize) -> Reply {
+        match serde_json::to_vec(value) {
+            Ok(body) => Reply {
+                status,
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => Reply {
+                status: 500,
+                content_type: "application/json",
+                body: json!({ "error": format!("Error serializing the response: {}", e) })
+                    .to_string()
+                    .into_bytes(),
+            },
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Reply {
+        Reply::json(status, &json!({ "error": message.to_string() }))
+    }
+}
+
+impl From<Error> for Reply {
+    fn from(e: Error) -> Self {
+        let status = match e {
+            Error::Invalid(_) => 400,
+            Error::NotFound(_) => 404,
+            Error::Credit(_) => 409,
+            Error::Render(_) | Error::Db(_) => 500,
+        };
+        Reply::error(status, e)
+    }
+}
+
+/// Answers requests on `bind` (e.g. `127.0.0.1:8080`) one at a time until the
+/// process is stopped.
+pub fn serve(bind: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let token = config::load_config()?.api_token;
+    if token.is_empty() {
+        return Err("Set an API token first: cli-invoice config set api_token <token>".into());
+    }
+
+    let server = Server::http(bind).map_err(|e| format!("Unable to listen on {}: {}", bind, e))?;
+    println!("Serving the API on {}", format!("http://{}", bind).green());
+
+    for mut request in server.incoming_requests() {
+        let reply = if request.url() == "/openapi.json" || authorized(&request, &token) {
+            route(&mut request).unwrap_or_else(Reply::from)
+        } else {
+            Reply::error(401, "Missing or wrong API token")
+        };
+
+        println!(
+            "{} {} {}",
+            request.method(),
+            request.url(),
+            reply.status.to_string().bright_black()
+        );
+
+        let mut response = Response::from_data(reply.body).with_status_code(reply.status);
+        if let Ok(content_type) = Header::from_bytes("Content-Type", reply.content_type) {
+            response.add_header(content_type);
+        }
+        if let Err(e) = request.respond(response) {
+            println!("{} {}", "Error:".red(), e);
+        }
+    }
+
+    Ok(())
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|given| same_token(given.as_bytes(), token.as_bytes()))
+}
+
+// Compares every byte so the response time doesn't give away how much of the
+// token was right
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn route(request: &mut Request) -> Result<Reply, Error> {
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+
+    match (&method, segments.as_slice()) {
+        (Method::Get, ["openapi.json"]) => Ok(Reply {
+            status: 200,
+            content_type: "application/json",
+            body: OPENAPI.as_bytes().to_vec(),
+        }),
+
+        (Method::Get, ["companies"]) => Ok(Reply::json(200, &service::list_companies()?)),
+        (Method::Post, ["companies"]) => {
+            let input = body::<CompanyInput>(request)?;
+            Ok(Reply::json(201, &service::create_company(input)?))
+        }
+        (Method::Get, ["companies", id]) => {
+            Ok(Reply::json(200, &service::get_company(parse_id(id)?)?))
+        }
+
+        (Method::Get, ["clients"]) => Ok(Reply::json(200, &service::list_clients()?)),
+        (Method::Post, ["clients"]) => {
+            let input = body::<ClientInput>(request)?;
+            Ok(Reply::json(201, &service::create_client(input)?))
+        }
+        (Method::Get, ["clients", id]) => {
+            Ok(Reply::json(200, &service::get_client(parse_id(id)?)?))
+        }
+
+        (Method::Get, ["invoices"]) => {
+            let mut filter = InvoiceFilter::default();
+            for (key, value) in query_params(query) {
+                match key.as_str() {
+                    "client_id" => filter.client_id = Some(parse_id(&value)?),
+                    "client_name" => filter.client_name = Some(value),
+                    _ => return Err(Error::Invalid(format!("Unknown filter {}", key))),
+                }
+            }
+            Ok(Reply::json(200, &service::list_invoices(&filter)?))
+        }
+        (Method::Post, ["invoices"]) => {
+            let invoice_request = body::<InvoiceRequest>(request)?;
+            Ok(Reply::json(
+                201,
+                &service::generate_invoice(invoice_request)?,
+            ))
+        }
+        (Method::Get, ["invoices", id]) => {
+            Ok(Reply::json(200, &service::get_invoice(parse_id(id)?)?))
+        }
+        (Method::Get, ["invoices", id, "items"]) => {
+            Ok(Reply::json(200, &service::list_items(parse_id(id)?)?))
+        }
+        (Method::Get, ["invoices", id, "pdf"]) => Ok(Reply {
+            status: 200,
+            content_type: "application/pdf",
+            body: service::render_invoice(parse_id(id)?)?,
+        }),
+
+        _ => Err(Error::NotFound(format!("Endpoint {} {}", method, path))),
+    }
+}
+
+fn body<T: DeserializeOwned>(request: &mut Request) -> Result<T, Error> {
+    // One byte over the limit is enough to tell the body was too big
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| Error::Invalid(format!("Unable to read the request body: {}", e)))?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err(Error::Invalid(format!(
+            "Request body is larger than {} bytes",
+            MAX_BODY_BYTES
+        )));
+    }
+
+    serde_json::from_str(&body).map_err(|e| Error::Invalid(format!("Invalid request body: {}", e)))
+}
+
+fn parse_id(id: &str) -> Result<i32, Error> {
+    id.parse()
+        .map_err(|_| Error::Invalid(format!("{} is not a valid id", id)))
+}
+
+/// Decodes `key=value&...`, with `+` and percent-encoded characters.
+fn query_params(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |part: &str| {
+                let part = part.replace('+', " ");
+                urlencoding::decode(&part)
+                    .map(|decoded| decoded.into_owned())
+                    .unwrap_or(part)
+            };
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_http::TestRequest;
+
+    fn request(method: Method, path: &str) -> Request {
+        TestRequest::new()
+            .with_method(method)
+            .with_path(path)
+            .into()
+    }
+
+    fn with_authorization(value: &str) -> Request {
+        TestRequest::new()
+            .with_path("/invoices")
+            .with_header(Header::from_bytes("Authorization", value).unwrap())
+            .into()
+    }
+
+    fn status(result: Result<Reply, Error>) -> u16 {
+        result.unwrap_or_else(Reply::from).status
+    }
+
+    #[test]
+    fn compares_tokens_of_any_length() {
+        assert!(same_token(b"secret", b"secret"));
+        assert!(!same_token(b"secreT", b"secret"));
+        assert!(!same_token(b"secret-and-more", b"secret"));
+        assert!(!same_token(b"", b"secret"));
+    }
+
+    #[test]
+    fn requires_the_bearer_token() {
+        assert!(authorized(&with_authorization("Bearer secret"), "secret"));
+        assert!(!authorized(&with_authorization("Bearer wrong"), "secret"));
+        assert!(!authorized(&with_authorization("secret"), "secret"));
+        assert!(!authorized(&request(Method::Get, "/invoices"), "secret"));
+    }
+
+    #[test]
+    fn decodes_query_parameters() {
+        assert_eq!(
+            query_params("client_name=Acme+Ltd&client_id=%34%32&&flag"),
+            vec![
+                ("client_name".to_string(), "Acme Ltd".to_string()),
+                ("client_id".to_string(), "42".to_string()),
+                ("flag".to_string(), String::new()),
+            ]
+        );
+        assert!(query_params("").is_empty());
+    }
+
+    #[test]
+    fn parses_ids() {
+        assert_eq!(parse_id("42").unwrap(), 42);
+        assert!(matches!(parse_id("abc"), Err(Error::Invalid(_))));
+        assert!(matches!(parse_id("-"), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn serves_the_openapi_description() {
+        let reply = route(&mut request(Method::Get, "/openapi.json")).unwrap_or_else(Reply::from);
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body, OPENAPI.as_bytes());
+    }
+
+    #[test]
+    fn answers_bad_requests_before_touching_the_database() {
+        assert_eq!(status(route(&mut request(Method::Get, "/nowhere"))), 404);
+        assert_eq!(
+            status(route(&mut request(Method::Delete, "/invoices/1"))),
+            404
+        );
+        assert_eq!(
+            status(route(&mut request(Method::Get, "/invoices/abc"))),
+            400
+        );
+        assert_eq!(status(route(&mut request(Method::Get, "/clients/1x"))), 400);
+        assert_eq!(
+            status(route(&mut request(Method::Get, "/invoices?paid=true"))),
+            400
+        );
+
+        let mut invalid_body: Request = TestRequest::new()
+            .with_method(Method::Post)
+            .with_path("/companies")
+            .with_body("{not json")
+            .into();
+        assert_eq!(status(route(&mut invalid_body)), 400);
+    }
+}
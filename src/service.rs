@@ -0,0 +1,309 @@
+//! The invoicing API for other Rust programs. Nothing here prints or prompts;
+//! results come back as data and failures as [`Error`].
+
+use crate::config::ConfigError;
+use crate::db::{repository, DbError};
+use crate::invoice::{self, InvoiceSummary};
+use crate::models::{Client, Company, Invoice, Item, NewClient, NewInvoice};
+use crate::{client, config, pdf};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Input that can't make a valid record
+    Invalid(String),
+    /// A company, client or invoice that doesn't exist
+    NotFound(String),
+    /// The client is over their credit limit or has long overdue invoices
+    Credit(String),
+    /// The invoice could not be rendered or saved as a PDF
+    Render(String),
+    Db(DbError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Invalid(e) | Error::Credit(e) => write!(f, "{}", e),
+            Error::NotFound(what) => write!(f, "{} not found", what),
+            Error::Render(e) => write!(f, "Error generating invoice: {}", e),
+            Error::Db(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<DbError> for Error {
+    fn from(e: DbError) -> Self {
+        match e {
+            DbError::NotFound(what) => Error::NotFound(what),
+            e => Error::Db(e),
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Db(DbError::Config(e))
+    }
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Db(DbError::Query(e))
+    }
+}
+
+fn optional(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.trim().is_empty())
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct CompanyInput {
+    pub name: String,
+    pub address: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+}
+
+pub fn create_company(input: CompanyInput) -> Result<Company, Error> {
+    if input.name.trim().is_empty() {
+        return Err(Error::Invalid("Company name cannot be empty".to_string()));
+    }
+
+    Ok(Company::create(
+        input.name.trim(),
+        optional(input.address).as_deref(),
+        optional(input.email).as_deref(),
+        optional(input.phone).as_deref(),
+    )?)
+}
+
+pub fn list_companies() -> Result<Vec<Company>, Error> {
+    Ok(repository()?.list_companies()?)
+}
+
+pub fn get_company(id: i32) -> Result<Company, Error> {
+    Company::find_by_id(id)?.ok_or_else(|| Error::NotFound(format!("Company with id {}", id)))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct ClientInput {
+    pub name: String,
+    pub address: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    /// Companies that bill the client; the default company when empty
+    pub company_ids: Vec<i32>,
+    pub credit_limit: Option<f64>,
+}
+
+/// Saves a client and links it to its companies, all or nothing.
+pub fn create_client(input: ClientInput) -> Result<Client, Error> {
+    if input.name.trim().is_empty() {
+        return Err(Error::Invalid("Client name cannot be empty".to_string()));
+    }
+    if input.credit_limit.is_some_and(|limit| limit < 0.0) {
+        return Err(Error::Invalid(
+            "Credit limit cannot be negative".to_string(),
+        ));
+    }
+
+    let company_ids = if input.company_ids.is_empty() {
+        vec![config::load_config()?.default_company]
+    } else {
+        input.company_ids
+    };
+    for company_id in &company_ids {
+        get_company(*company_id)?;
+    }
+
+    let address = optional(input.address);
+    let email = optional(input.email);
+    let phone = optional(input.phone);
+    let new_client = NewClient {
+        name: input.name.trim(),
+        address: address.as_deref(),
+        email: email.as_deref(),
+        phone: phone.as_deref(),
+    };
+
+    Ok(repository()?.create_client(&new_client, &company_ids, input.credit_limit)?)
+}
+
+pub fn list_clients() -> Result<Vec<Client>, Error> {
+    Ok(repository()?.list_clients()?)
+}
+
+pub fn get_client(id: i32) -> Result<Client, Error> {
+    Client::find_by_id(id)?.ok_or_else(|| Error::NotFound(format!("Client with id {}", id)))
+}
+
+pub use crate::repository::InvoiceFilter;
+
+pub fn list_invoices(filter: &InvoiceFilter) -> Result<Vec<InvoiceSummary>, Error> {
+    let list = repository()?.list_invoices(filter)?;
+
+    Ok(invoice::summarize(list)?)
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InvoiceDetail {
+    #[serde(flatten)]
+    pub summary: InvoiceSummary,
+    pub items: Vec<Item>,
+}
+
+pub fn get_invoice(id: i32) -> Result<InvoiceDetail, Error> {
+    let summary = list_invoices(&InvoiceFilter {
+        id: Some(id),
+        ..Default::default()
+    })?
+    .pop()
+    .ok_or_else(|| Error::NotFound(format!("Invoice with id {}", id)))?;
+
+    let items = repository()?.invoice_items(Some(id))?;
+
+    Ok(InvoiceDetail { summary, items })
+}
+
+pub fn list_items(invoice_id: i32) -> Result<Vec<Item>, Error> {
+    Ok(get_invoice(invoice_id)?.items)
+}
+
+#[derive(Deserialize)]
+pub struct InvoiceRequest {
+    /// Defaults to the default company
+    pub company_id: Option<i32>,
+    pub client_id: i32,
+    pub items: Vec<pdf::Item>,
+    pub notes: Option<String>,
+    /// Defaults to today
+    pub date: Option<NaiveDate>,
+    /// Refuse to invoice a client over their credit limit or with long
+    /// overdue invoices, instead of returning warnings
+    #[serde(default)]
+    pub strict: bool,
+}
+
+#[derive(Serialize)]
+pub struct GeneratedInvoice {
+    pub invoice: Invoice,
+    /// Where the PDF was saved
+    pub path: PathBuf,
+    /// Credit problems that were let through because `strict` was off
+    pub warnings: Vec<String>,
+}
+
+/// Saves an invoice for a client and renders its PDF into the invoice path,
+/// see [`issue_invoice`].
+pub fn generate_invoice(request: InvoiceRequest) -> Result<GeneratedInvoice, Error> {
+    if request.items.is_empty() {
+        return Err(Error::Invalid(
+            "An invoice needs at least one item".to_string(),
+        ));
+    }
+    if let Some(item) = request.items.iter().find(|item| {
+        item.description.trim().is_empty()
+            || item.price < 0.0
+            || item.tax_rate.is_some_and(|rate| rate < 0.0)
+    }) {
+        return Err(Error::Invalid(format!(
+            "Invalid item {}: items need a description and no negative amounts",
+            item.description
+        )));
+    }
+
+    let company = match request.company_id {
+        Some(id) => get_company(id)?,
+        None => Company::default_company()?,
+    };
+    let client = get_client(request.client_id)?;
+
+    let total_amount = pdf::get_total_amount(&request.items);
+    let warnings = client::credit_problems(&client, total_amount)?;
+    if request.strict && !warnings.is_empty() {
+        return Err(Error::Credit(warnings.join("\n")));
+    }
+
+    let company = pdf::CompanyPdf {
+        id: Some(company.id),
+        name: Some(company.name),
+        address: company.address,
+        email: company.email,
+        phone: company.phone,
+    };
+    let client = pdf::ClientPdf {
+        id: Some(client.id),
+        name: client.name,
+        address: client.address,
+        email: client.email,
+        phone: client.phone,
+    };
+    let new_invoice = pdf::new_invoice(
+        company,
+        client,
+        &request.items,
+        request.notes,
+        false,
+        request.date,
+    )?;
+
+    Ok(GeneratedInvoice {
+        warnings,
+        ..issue_invoice(&new_invoice, &request.items)?
+    })
+}
+
+/// Saves an invoice with its items, then renders its PDF from the saved
+/// record. Every invoice is issued this way; if the PDF can't be made the
+/// invoice is removed again.
+pub fn issue_invoice(
+    new_invoice: &NewInvoice,
+    items: &[pdf::Item],
+) -> Result<GeneratedInvoice, Error> {
+    let new_items = items.iter().map(pdf::Item::new_item).collect();
+
+    let repo = &mut *repository()?;
+    let id = repo.create_invoice(new_invoice, new_items)?;
+    let invoice = repo
+        .find_invoice(id)?
+        .ok_or_else(|| Error::NotFound(format!("Invoice with id {}", id)))?;
+
+    let path = match save_invoice_pdf(&invoice, items) {
+        Ok(path) => path,
+        Err(e) => {
+            repo.delete_invoice(id)?;
+            return Err(e);
+        }
+    };
+
+    Ok(GeneratedInvoice {
+        invoice,
+        path,
+        warnings: Vec::new(),
+    })
+}
+
+/// Renders a saved invoice and writes it to the invoice path.
+pub fn save_invoice_pdf(invoice: &Invoice, items: &[pdf::Item]) -> Result<PathBuf, Error> {
+    let date = crate::utils::parse_date(&invoice.date)
+        .ok_or_else(|| Error::Invalid(format!("Invoice {} has no readable date", invoice.id)))?;
+
+    pdf::render_invoice(invoice, items)
+        .and_then(|pdf_data| pdf::save_pdf(&pdf_data, &format!("{:05}", invoice.id), date))
+        .map_err(|e| Error::Render(e.to_string()))
+}
+
+/// Renders a saved invoice to PDF bytes without saving a file.
+pub fn render_invoice(id: i32) -> Result<Vec<u8>, Error> {
+    let (invoice, items) = invoice::load_with_items(id)?;
+
+    pdf::render_invoice(&invoice, &items).map_err(|e| Error::Render(e.to_string()))
+}
@@ -0,0 +1,220 @@
+use crate::config;
+use crate::models::{Client, Company, Invoice};
+use crate::pdf;
+use crate::report::{DateRange, Ledger};
+use chrono::NaiveDate;
+use cli_table::{print_stdout, Cell, Style, Table};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use text_colorizer::*;
+
+/// One line of a statement of account. Debits are invoices, credits are
+/// payments and credit notes.
+#[derive(Serialize)]
+struct StatementLine {
+    date: String,
+    description: String,
+    debit: String,
+    credit: String,
+    balance: String,
+}
+
+/// Invoices without a client id (imported, or from before ids were recorded
+/// reliably) are matched on the client's name.
+fn is_for_client(invoice: &Invoice, client: &Client) -> bool {
+    match invoice.client_id {
+        Some(client_id) => client_id == client.id,
+        None => invoice.client_name == client.name,
+    }
+}
+
+fn amount(value: f64) -> String {
+    if value.abs() < 0.005 {
+        String::new()
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+/// Builds and renders a statement of account for a client, listing every
+/// invoice, credit note and payment in the range with a running balance.
+pub fn generate(
+    client_id: i32,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = Client::find_by_id(client_id)?
+        .ok_or_else(|| format!("Client with id {} not found", client_id))?;
+
+    let to = to.unwrap_or_else(|| chrono::offset::Local::now().date_naive());
+    let range = DateRange { from, to: Some(to) };
+
+    let ledger = Ledger::load()?;
+    let client_invoices = ledger
+        .invoices
+        .iter()
+        .filter(|(invoice, _)| is_for_client(invoice, &client))
+        .collect::<Vec<_>>();
+    let invoice_numbers = client_invoices
+        .iter()
+        .map(|(invoice, _)| (invoice.id, invoice.number()))
+        .collect::<HashMap<_, _>>();
+    let is_client_invoice = |invoice_id: i32| invoice_numbers.contains_key(&invoice_id);
+
+    // (date, order on the day, description, signed amount)
+    let mut entries: Vec<(NaiveDate, u8, String, f64)> = Vec::new();
+
+    for (invoice, date) in &client_invoices {
+        entries.push((
+            *date,
+            0,
+            format!("Invoice {}", invoice.number()),
+            invoice.total_amount,
+        ));
+    }
+    for (note, date) in &ledger.credit_notes {
+        if is_client_invoice(note.invoice_id) {
+            entries.push((
+                *date,
+                1,
+                format!(
+                    "Credit note {} for invoice {}",
+                    crate::credit_note::format_credit_note_number(note.id),
+                    invoice_numbers[&note.invoice_id]
+                ),
+                note.total_amount,
+            ));
+        }
+    }
+    for (payment, date) in &ledger.payments {
+        if is_client_invoice(payment.invoice_id) {
+            let method = payment
+                .method
+                .as_ref()
+                .map(|method| format!(" ({})", method))
+                .unwrap_or_default();
+            entries.push((
+                *date,
+                2,
+                format!(
+                    "Payment for invoice {}{}",
+                    invoice_numbers[&payment.invoice_id], method
+                ),
+                -payment.amount,
+            ));
+        }
+    }
+
+    entries.sort_by_key(|(date, order, ..)| (*date, *order));
+
+    let opening_balance: f64 = entries
+        .iter()
+        .filter(|(date, ..)| from.is_some_and(|from| *date < from))
+        .fold(0.0, |acc, (.., amount)| acc + amount);
+
+    let mut balance = opening_balance;
+    let lines = entries
+        .into_iter()
+        .filter(|(date, ..)| range.contains(*date))
+        .map(|(date, _, description, value)| {
+            balance += value;
+            StatementLine {
+                date: date.format("%d %B %Y").to_string(),
+                description,
+                debit: amount(value.max(0.0)),
+                credit: amount(-value.min(0.0)),
+                balance: format!("{:.2}", balance),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let terms = config::load_config()?.payment_terms_days;
+    let overdue: f64 = ledger
+        .outstanding(to, terms)
+        .iter()
+        .filter(|(invoice, due, _)| invoice_numbers.contains_key(&invoice.id) && *due < to)
+        .fold(0.0, |acc, (.., balance)| acc + balance);
+
+    statement_table(opening_balance, &lines);
+    println!("Amount due: {:.2}", balance);
+    println!("Overdue: {:.2}", overdue);
+
+    // The letterhead is the company on the client's latest invoice, as it was
+    // printed there; clients with no invoices yet get the default company
+    let (company_name, company_address, company_email, company_phone) = match client_invoices
+        .iter()
+        .max_by_key(|(invoice, date)| (*date, invoice.id))
+    {
+        Some((invoice, _)) => (
+            invoice.company_name.clone(),
+            invoice.company_address.clone(),
+            invoice.company_email.clone(),
+            invoice.company_phone.clone(),
+        ),
+        None => {
+            let company = Company::default_company()?;
+            (company.name, company.address, company.email, company.phone)
+        }
+    };
+
+    let data = json!({
+        "statement_date": to.format("%d %B %Y").to_string(),
+        "from_date": from.map(|d| d.format("%d %B %Y").to_string()),
+        "client_name": client.name,
+        "client_address": client.address.clone().unwrap_or_default(),
+        "client_email": client.email.clone().unwrap_or_default(),
+        "client_phone": client.phone.clone().unwrap_or_default(),
+        "company_name": company_name,
+        "company_address": company_address.unwrap_or_default(),
+        "company_email": company_email.unwrap_or_default(),
+        "company_phone": company_phone.unwrap_or_default(),
+        "opening_balance": format!("{:.2}", opening_balance),
+        "lines": lines,
+        "amount_due": format!("{:.2}", balance),
+        "overdue": (overdue > 0.005).then(|| format!("{:.2}", overdue)),
+        "logo_url": pdf::get_image_data_url()?,
+    });
+
+    print!("{}", "Generating statement... \n".yellow());
+
+    pdf::render_with_template(
+        include_str!("statement_template.html"),
+        data,
+        format!("statement-{}-{}", client.id, to.format("%Y-%m-%d")),
+        to,
+    )
+}
+
+fn statement_table(opening_balance: f64, lines: &[StatementLine]) {
+    let mut rows = vec![vec![
+        String::new().cell(),
+        "Opening balance".cell(),
+        String::new().cell(),
+        String::new().cell(),
+        format!("{:.2}", opening_balance).cell(),
+    ]];
+
+    rows.extend(lines.iter().map(|line| {
+        vec![
+            line.date.clone().cell(),
+            line.description.clone().cell(),
+            line.debit.clone().cell(),
+            line.credit.clone().cell(),
+            line.balance.clone().cell(),
+        ]
+    }));
+
+    let table = rows
+        .table()
+        .title(vec![
+            "Date".cell(),
+            "Description".cell(),
+            "Debit".cell(),
+            "Credit".cell(),
+            "Balance".cell(),
+        ])
+        .bold(true);
+
+    print_stdout(table).expect("Error printing the table");
+}
@@ -0,0 +1,529 @@
+use crate::client::ClientSearchField;
+use crate::db::{establish_connection, DbError};
+use crate::models::{Client, NewTimeEntry, TimeEntry};
+use crate::schema::time_entries;
+use chrono::NaiveDate;
+use cli_table::{print_stdout, Cell, Style, Table};
+use diesel::prelude::*;
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use text_colorizer::*;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum TimeImportFormat {
+    Toggl,
+    Clockify,
+    Harvest,
+    Generic,
+}
+
+/// Candidate header names for each field, matched case-insensitively.
+struct ColumnMap {
+    id: &'static [&'static str],
+    client: &'static [&'static str],
+    project: &'static [&'static str],
+    description: &'static [&'static str],
+    duration: &'static [&'static str],
+    hours: &'static [&'static str],
+    minutes: &'static [&'static str],
+    date: &'static [&'static str],
+    start: &'static [&'static str],
+    /// How the tool writes dates, unless `--date-format` says otherwise.
+    date_format: &'static str,
+}
+
+impl TimeImportFormat {
+    fn name(&self) -> &'static str {
+        match self {
+            TimeImportFormat::Toggl => "toggl",
+            TimeImportFormat::Clockify => "clockify",
+            TimeImportFormat::Harvest => "harvest",
+            TimeImportFormat::Generic => "generic",
+        }
+    }
+
+    fn columns(&self) -> ColumnMap {
+        match self {
+            TimeImportFormat::Toggl => ColumnMap {
+                id: &["id", "time entry id"],
+                client: &["client"],
+                project: &["project"],
+                description: &["description", "task"],
+                duration: &["duration"],
+                hours: &[],
+                minutes: &[],
+                date: &["start date"],
+                start: &["start time"],
+                date_format: "%Y-%m-%d",
+            },
+            TimeImportFormat::Clockify => ColumnMap {
+                id: &["id", "time entry id"],
+                client: &["client"],
+                project: &["project"],
+                description: &["description", "task"],
+                duration: &["duration (h)"],
+                hours: &["duration (decimal)"],
+                minutes: &[],
+                date: &["start date"],
+                start: &["start time"],
+                date_format: "%m/%d/%Y",
+            },
+            TimeImportFormat::Harvest => ColumnMap {
+                id: &["id"],
+                client: &["client"],
+                project: &["project"],
+                description: &["notes", "task"],
+                duration: &[],
+                hours: &["hours"],
+                minutes: &[],
+                date: &["date", "spent date"],
+                start: &[],
+                date_format: "%Y-%m-%d",
+            },
+            TimeImportFormat::Generic => ColumnMap {
+                id: &["id", "external_id"],
+                client: &["client"],
+                project: &["project"],
+                description: &["description"],
+                duration: &["duration"],
+                hours: &["hours"],
+                minutes: &["minutes"],
+                date: &["date"],
+                start: &["start time", "start"],
+                date_format: "%Y-%m-%d",
+            },
+        }
+    }
+}
+
+/// Header positions resolved against the file being imported.
+struct ColumnIndex {
+    id: Option<usize>,
+    client: usize,
+    project: Option<usize>,
+    description: Option<usize>,
+    duration: Option<usize>,
+    hours: Option<usize>,
+    minutes: Option<usize>,
+    date: usize,
+    start: Option<usize>,
+}
+
+fn find_column(headers: &csv::StringRecord, names: &[&str]) -> Option<usize> {
+    names.iter().find_map(|name| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name))
+    })
+}
+
+fn resolve_columns(
+    headers: &csv::StringRecord,
+    format: TimeImportFormat,
+) -> Result<ColumnIndex, String> {
+    let map = format.columns();
+
+    let client = find_column(headers, map.client)
+        .ok_or_else(|| format!("Missing client column for {} format", format.name()))?;
+    let date = find_column(headers, map.date)
+        .ok_or_else(|| format!("Missing date column for {} format", format.name()))?;
+
+    let index = ColumnIndex {
+        id: find_column(headers, map.id),
+        client,
+        project: find_column(headers, map.project),
+        description: find_column(headers, map.description),
+        duration: find_column(headers, map.duration),
+        hours: find_column(headers, map.hours),
+        minutes: find_column(headers, map.minutes),
+        date,
+        start: find_column(headers, map.start),
+    };
+
+    if index.duration.is_none() && index.hours.is_none() && index.minutes.is_none() {
+        return Err(format!(
+            "Missing duration column for {} format",
+            format.name()
+        ));
+    }
+
+    Ok(index)
+}
+
+/// Parses `HH:MM[:SS]` or decimal hours into whole minutes.
+fn parse_duration(value: &str) -> Option<i32> {
+    let value = value.trim();
+
+    if value.contains(':') {
+        let parts = value
+            .split(':')
+            .map(|part| part.trim().parse::<i64>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+
+        let seconds = match parts.as_slice() {
+            [hours, minutes] => hours * 3600 + minutes * 60,
+            [hours, minutes, seconds] => hours * 3600 + minutes * 60 + seconds,
+            _ => return None,
+        };
+
+        return Some(((seconds as f64) / 60.0).round() as i32);
+    }
+
+    parse_hours(value)
+}
+
+fn parse_hours(value: &str) -> Option<i32> {
+    value
+        .trim()
+        .replace(',', ".")
+        .parse::<f64>()
+        .ok()
+        .map(|hours| (hours * 60.0).round() as i32)
+}
+
+/// Normalises a date written in `format` to `YYYY-MM-DD`.
+fn parse_date(value: &str, format: &str) -> Option<String> {
+    NaiveDate::parse_from_str(value.trim(), format)
+        .ok()
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+/// FNV-1a hash used as a stable external id when the export has no id column.
+/// `fields` must tell apart entries that are identical apart from when they
+/// happened: the start time, or failing that the row's position among the
+/// identical rows of the file.
+fn fingerprint(fields: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in fields.join("\u{1f}").bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn match_client(name: &str, mappings: &HashMap<String, i32>) -> Result<Option<i32>, DbError> {
+    if let Some(id) = mappings.get(&name.to_lowercase()) {
+        return Ok(Some(*id));
+    }
+
+    let clients: IndexMap<Client, Vec<String>> =
+        Client::search_by(ClientSearchField::Name, name, Some(false))?;
+
+    if clients.len() == 1 {
+        return Ok(clients.keys().next().map(|client| client.id));
+    }
+
+    Ok(clients
+        .keys()
+        .find(|client| client.name.eq_ignore_ascii_case(name))
+        .map(|client| client.id))
+}
+
+/// Parses `--map "Client Name=ID"` arguments into a lookup keyed by lower-cased name.
+fn parse_mappings(mappings: &[String]) -> Result<HashMap<String, i32>, String> {
+    mappings
+        .iter()
+        .map(|mapping| {
+            let (name, id) = mapping
+                .rsplit_once('=')
+                .ok_or_else(|| format!("Invalid mapping {}, expected NAME=ID", mapping))?;
+            let id = id
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| format!("Invalid client id in mapping {}", mapping))?;
+            Ok((name.trim().to_lowercase(), id))
+        })
+        .collect()
+}
+
+pub fn import(
+    file: &str,
+    format: TimeImportFormat,
+    date_format: Option<&str>,
+    mappings: &[String],
+) -> Result<(), String> {
+    let mappings = parse_mappings(mappings)?;
+    let date_format = date_format.unwrap_or(format.columns().date_format);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(file)
+        .map_err(|e| format!("Unable to read {}: {}", file, e))?;
+
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Unable to read headers: {}", e))?
+        .clone();
+    let columns = resolve_columns(&headers, format)?;
+
+    let conn = &mut *establish_connection()?;
+
+    let mut imported = 0;
+    let mut duplicates = 0;
+    let mut invalid = 0;
+    let mut unmatched: IndexMap<String, usize> = IndexMap::new();
+    let mut matched_clients: HashMap<String, Option<i32>> = HashMap::new();
+    let mut identical_rows: HashMap<String, usize> = HashMap::new();
+
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Unable to read row {}: {}", line + 2, e))?;
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| record.get(i))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+
+        let client_name = field(Some(columns.client)).unwrap_or_default().to_string();
+        let project = field(columns.project).map(ToString::to_string);
+        let description = field(columns.description)
+            .or(project.as_deref())
+            .unwrap_or_default()
+            .to_string();
+
+        let duration = field(columns.duration)
+            .and_then(parse_duration)
+            .or_else(|| field(columns.hours).and_then(parse_hours))
+            .or_else(|| field(columns.minutes).and_then(|m| m.parse::<i32>().ok()));
+        let date = field(Some(columns.date)).and_then(|date| parse_date(date, date_format));
+
+        let (Some(duration_minutes), Some(date)) = (duration, date) else {
+            println!(
+                "{} {} (dates are read as {})",
+                "Skipping row with invalid duration or date:".red(),
+                line + 2,
+                date_format
+            );
+            invalid += 1;
+            continue;
+        };
+
+        if client_name.is_empty() {
+            println!("{} {}", "Skipping row without a client:".red(), line + 2);
+            invalid += 1;
+            continue;
+        }
+
+        let client_id = match matched_clients.get(&client_name) {
+            Some(client_id) => *client_id,
+            None => {
+                let client_id = match_client(&client_name, &mappings)?;
+                matched_clients.insert(client_name.clone(), client_id);
+                client_id
+            }
+        };
+
+        let Some(client_id) = client_id else {
+            *unmatched.entry(client_name).or_insert(0) += 1;
+            continue;
+        };
+
+        let external_id = match field(columns.id) {
+            Some(id) => id.to_string(),
+            None => {
+                let fields = [
+                    date.as_str(),
+                    &client_name,
+                    project.as_deref().unwrap_or_default(),
+                    &description,
+                    &duration_minutes.to_string(),
+                ]
+                .join("\u{1f}");
+                let start = match field(columns.start) {
+                    Some(start) => start.to_string(),
+                    None => {
+                        let occurrence = identical_rows.entry(fields.clone()).or_insert(0);
+                        *occurrence += 1;
+                        format!("#{}", occurrence)
+                    }
+                };
+                fingerprint(&[&fields, &start])
+            }
+        };
+
+        let new_entry = NewTimeEntry {
+            source: format.name().to_string(),
+            external_id,
+            client_id: Some(client_id),
+            client_name,
+            project,
+            description,
+            duration_minutes,
+            date,
+        };
+
+        let inserted = diesel::insert_or_ignore_into(time_entries::table)
+            .values(&new_entry)
+            .execute(conn)
+            .map_err(DbError::from)?;
+
+        if inserted == 0 {
+            duplicates += 1;
+        } else {
+            imported += 1;
+        }
+    }
+
+    println!(
+        "{} {} imported, {} duplicates skipped, {} invalid rows",
+        "Time import complete:".green(),
+        imported,
+        duplicates,
+        invalid
+    );
+
+    if !unmatched.is_empty() {
+        println!(
+            "\n{}",
+            "The following clients could not be matched and were not imported:".yellow()
+        );
+        for (name, count) in &unmatched {
+            println!("  {} ({} entries)", name, count);
+        }
+        println!(
+            "Map them to an existing client with {} and import again.",
+            "--map \"<client name>=<client id>\"".bright_black()
+        );
+    }
+
+    Ok(())
+}
+
+/// Lists time entries that have not yet been invoiced.
+pub fn list_staged(client_id: Option<i32>) -> Result<Vec<TimeEntry>, DbError> {
+    let conn = &mut *establish_connection()?;
+
+    let mut query = time_entries::table
+        .filter(time_entries::invoice_id.is_null())
+        .into_boxed();
+
+    if let Some(client_id) = client_id {
+        query = query.filter(time_entries::client_id.eq(client_id));
+    }
+
+    let entries = query
+        .order((time_entries::client_name.asc(), time_entries::date.asc()))
+        .load::<TimeEntry>(conn)?;
+
+    time_entry_table(&entries);
+
+    Ok(entries)
+}
+
+fn time_entry_table(entries: &[TimeEntry]) {
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            vec![
+                entry.id.to_string().cell(),
+                entry.date.clone().cell(),
+                entry.client_name.clone().cell(),
+                entry.project.clone().unwrap_or_default().cell(),
+                entry.description.clone().cell(),
+                format!("{:.2}", entry.duration_minutes as f64 / 60.0).cell(),
+                entry.source.clone().cell(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let table = rows
+        .table()
+        .title(vec![
+            "ID".cell(),
+            "Date".cell(),
+            "Client".cell(),
+            "Project".cell(),
+            "Description".cell(),
+            "Hours".cell(),
+            "Source".cell(),
+        ])
+        .bold(true);
+
+    print_stdout(table).expect("Error printing the table");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(names: &[&str]) -> csv::StringRecord {
+        csv::StringRecord::from(names.to_vec())
+    }
+
+    #[test]
+    fn resolves_columns_for_each_format() {
+        let toggl = resolve_columns(
+            &headers(&[
+                "User",
+                "Client",
+                "Project",
+                "Description",
+                "Start date",
+                "Start time",
+                "Duration",
+            ]),
+            TimeImportFormat::Toggl,
+        )
+        .unwrap();
+        assert_eq!((toggl.client, toggl.date), (1, 4));
+        assert_eq!(toggl.start, Some(5));
+        assert_eq!(toggl.duration, Some(6));
+        assert_eq!(toggl.id, None);
+
+        let harvest = resolve_columns(
+            &headers(&[
+                "Date",
+                "Client",
+                "Project",
+                "Notes",
+                "Hours",
+                "External Reference URL",
+            ]),
+            TimeImportFormat::Harvest,
+        )
+        .unwrap();
+        assert_eq!(harvest.hours, Some(4));
+        assert_eq!(harvest.description, Some(3));
+        assert_eq!(harvest.id, None);
+
+        assert!(resolve_columns(&headers(&["client", "date"]), TimeImportFormat::Generic).is_err());
+        assert!(
+            resolve_columns(&headers(&["client", "hours"]), TimeImportFormat::Generic).is_err()
+        );
+    }
+
+    #[test]
+    fn reads_dates_in_the_given_format_only() {
+        let clockify = TimeImportFormat::Clockify.columns().date_format;
+        assert_eq!(
+            parse_date("03/04/2026", clockify).as_deref(),
+            Some("2026-03-04")
+        );
+        assert_eq!(
+            parse_date("03/04/2026", "%d/%m/%Y").as_deref(),
+            Some("2026-04-03")
+        );
+        assert_eq!(parse_date("25/04/2026", clockify), None);
+        assert_eq!(
+            parse_date(" 2026-04-25 ", "%Y-%m-%d").as_deref(),
+            Some("2026-04-25")
+        );
+    }
+
+    #[test]
+    fn parses_clock_and_decimal_durations() {
+        assert_eq!(parse_duration("01:30"), Some(90));
+        assert_eq!(parse_duration("1:15:30"), Some(76));
+        assert_eq!(parse_duration("1,5"), Some(90));
+        assert_eq!(parse_duration("soon"), None);
+    }
+
+    #[test]
+    fn maps_client_names_case_insensitively() {
+        let mappings = parse_mappings(&["Acme Ltd = 3".to_string()]).unwrap();
+        assert_eq!(mappings.get("acme ltd"), Some(&3));
+        assert!(parse_mappings(&["Acme Ltd".to_string()]).is_err());
+        assert!(parse_mappings(&["Acme Ltd=three".to_string()]).is_err());
+    }
+}
@@ -1,10 +1,39 @@
+use chrono::NaiveDate;
 use std::io::{self, Write};
 use text_colorizer::*;
 
 pub fn get_input(prompt: &str) -> String {
     print!("{}", prompt.bright_blue());
-    io::stdout().flush().unwrap();
+    // An unreadable terminal reads as an empty answer
+    let _ = io::stdout().flush();
     let mut buffer = String::new();
-    io::stdin().read_line(&mut buffer).unwrap();
+    let _ = io::stdin().read_line(&mut buffer);
     buffer.trim().to_owned()
 }
+
+/// Asks for a number from 1 to `count` until one is given, returning its
+/// index, or `None` when the answer is left empty.
+pub fn select_index(count: usize) -> Option<usize> {
+    loop {
+        let selection = get_input(&format!("Select 1-{} (empty to cancel): ", count));
+        if selection.is_empty() {
+            return None;
+        }
+
+        match selection.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => return Some(n - 1),
+            _ => println!(
+                "{}",
+                format!("{} is not a number from 1 to {}", selection, count).red()
+            ),
+        }
+    }
+}
+
+/// Parses the dates stored on records, which are either `01 January 2024`
+/// (invoices, quotes, credit notes) or ISO `2024-01-01`.
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%d %B %Y")
+        .or_else(|_| NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d"))
+        .ok()
+}